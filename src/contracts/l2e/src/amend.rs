@@ -0,0 +1,322 @@
+use near_sdk::json_types::U128;
+use near_sdk::{env, log, near, require, AccountId, NearToken, Promise};
+
+use crate::{
+    EscrowAsset, EventLog, EventLogVariant, GrantId, GrantLog, GrantRecord, GrantStatus, L2eTop, L2eTopExt,
+    CONSTRACT_NAME, CONSTRACT_VERSION,
+};
+
+#[near]
+impl L2eTop {
+    /// Add the attached deposit to the main token of a grant, called by the owner while its nft is unclaimed.
+    /// The ft of a grant is topped up with ft_transfer_call and `{"grant_id": <grant_id>, "top_up": true}` as msg.
+    #[payable]
+    pub fn top_up_grant(&mut self, grant_id: GrantId) -> bool {
+        let GrantRecord { campaign_id, owner, .. } = self.changeable_grant(grant_id);
+        let main_token_amount = env::attached_deposit();
+        require!(main_token_amount > NearToken::from_near(0), "attached_amount should be greater than 0");
+        let campaign = self.campaigns.get(&campaign_id).expect("Campaign not found");
+        require!(campaign.has_budget_for(main_token_amount, U128::from(0)), "Campaign budget exceeded");
+
+        let balance_entry = self.grant_mut(grant_id);
+        balance_entry.main_token_amount = balance_entry.main_token_amount.saturating_add(main_token_amount);
+        self.charge_campaign(campaign_id, main_token_amount, 0);
+        self.escrow_deposit(&owner, EscrowAsset::MainToken, main_token_amount.as_yoctonear());
+        self.log_grant_change(grant_id, true, main_token_amount, 0);
+
+        true
+    }
+
+    /// Lower the main token and ft of a grant to the given amounts, called by the owner while its nft is unclaimed.
+    /// The main token difference and the escrowed ft above the new ft_amount are refunded to the owner.
+    /// A grant with an ft basket can't be amended, revoke it and approve a new one instead.
+    pub fn amend_grant(&mut self, grant_id: GrantId, main_token_amount: NearToken, ft_amount: U128) -> bool {
+        let GrantRecord { campaign_id, owner, .. } = self.changeable_grant(grant_id);
+        require!(
+            main_token_amount > NearToken::from_near(0),
+            "main_token_amount should be greater than 0"
+        );
+
+        let balance_entry = self.grant_mut(grant_id);
+        require!(balance_entry.ft_basket.is_empty(), "A grant with an ft basket can't be amended");
+        let released_ft = balance_entry.ft_pledged.0.checked_sub(ft_amount.0);
+        require!(
+            main_token_amount <= balance_entry.main_token_amount && released_ft.is_some(),
+            "amend_grant can only lower the amounts, use top_up_grant to raise them"
+        );
+        let released_ft = released_ft.unwrap_or_default();
+        require!(
+            main_token_amount < balance_entry.main_token_amount || released_ft > 0,
+            "Nothing to amend"
+        );
+        let refund_main_token = balance_entry.main_token_amount.saturating_sub(main_token_amount);
+        // the escrowed ft above the new ft_amount goes back to the owner, what is below it stays
+        let ft_escrowed = balance_entry.ft_escrowed.0.min(ft_amount.0);
+        let refund_ft = balance_entry.ft_escrowed.0.saturating_sub(ft_escrowed);
+        balance_entry.main_token_amount = main_token_amount;
+        balance_entry.ft_pledged = ft_amount;
+        balance_entry.ft_escrowed = U128::from(ft_escrowed);
+        let erc20_address = balance_entry.erc20_address.clone();
+        self.release_campaign(campaign_id, refund_main_token, released_ft);
+
+        // the ledger records the refunds once they resolved, in amend_grant_callback
+        let mut refund: Option<Promise> = None;
+        if refund_main_token > NearToken::from_near(0) {
+            refund = Some(Promise::new(owner.clone()).transfer(refund_main_token));
+        }
+        if refund_ft > 0 {
+            let ft_promise = Self::ft_transfer_to(
+                &erc20_address,
+                &owner,
+                U128::from(refund_ft),
+                Some("L2E grant amended".to_string()),
+                false,
+            );
+            refund = Some(match refund {
+                Some(promise) => promise.and(ft_promise),
+                None => ft_promise,
+            });
+        }
+        if let Some(refund) = refund {
+            let _amend_grant_promise = refund.then(Self::ext(env::current_account_id()).amend_grant_callback(
+                grant_id,
+                erc20_address,
+                refund_main_token,
+                U128::from(refund_ft),
+            ));
+        }
+        self.log_grant_change(grant_id, false, refund_main_token, released_ft);
+
+        true
+    }
+
+    /// Resolve the refunds started by `amend_grant`, in order: main token transfer, ft_transfer. A zero leg is skipped.
+    /// A failed leg is added back to the grant while it is Minted or NftClaimed, the owner can amend it again.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn amend_grant_callback(
+        &mut self,
+        grant_id: GrantId,
+        erc20_address: AccountId,
+        refund_main_token: NearToken,
+        refund_ft: U128,
+    ) -> bool {
+        let GrantRecord { campaign_id, owner, spender, status, .. } = self.grant_record(grant_id);
+        let (main_token_returned, ft_returned) = Self::payout_results(refund_main_token, refund_ft);
+
+        if main_token_returned && refund_main_token > NearToken::from_near(0) {
+            self.escrow_refund(&owner, EscrowAsset::MainToken, refund_main_token.as_yoctonear());
+        }
+        if ft_returned && refund_ft.0 > 0 {
+            self.escrow_refund(&owner, EscrowAsset::Ft(erc20_address), refund_ft.0);
+        }
+        if main_token_returned && ft_returned {
+            return true;
+        }
+
+        let unreturned_main_token = if main_token_returned { NearToken::from_near(0) } else { refund_main_token };
+        let unreturned_ft = if ft_returned { 0 } else { refund_ft.0 };
+        let open = matches!(status, GrantStatus::Minted | GrantStatus::NftClaimed)
+            && self.balance_entry(grant_id).is_some_and(|x| !x.is_paying());
+        if open {
+            let balance_entry = self.grant_mut(grant_id);
+            balance_entry.main_token_amount = balance_entry.main_token_amount.saturating_add(unreturned_main_token);
+            balance_entry.ft_pledged = U128::from(balance_entry.ft_pledged.0.saturating_add(unreturned_ft));
+            balance_entry.ft_escrowed = U128::from(balance_entry.ft_escrowed.0.saturating_add(unreturned_ft));
+            self.charge_campaign(campaign_id, unreturned_main_token, unreturned_ft);
+        } else {
+            // the grant moved on meanwhile, what was not returned stays committed in the escrow ledger of the owner
+            log!("amend_grant_callback: grant {} is {:?}, the unreturned refund is not added back.", grant_id, status);
+        }
+
+        let amend_grant_failed_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: EventLogVariant::AmendGrantFailed(vec![GrantLog {
+                owner_id: owner.to_string(),
+                spender_id: spender.to_string(),
+                token_ids: self.nft_entry(grant_id).map(|x| x.token_id).into_iter().collect(),
+                main_token_amount: unreturned_main_token.as_yoctonear().to_string(),
+                ft_token_amount: unreturned_ft.to_string(),
+                grant_id: Some(grant_id),
+                campaign_id: Some(campaign_id),
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+        env::log_str(&amend_grant_failed_log.to_string());
+
+        false
+    }
+}
+
+impl L2eTop {
+    /// Why a grant can't be topped up or amended, None while it is minted, its nft unclaimed and not expired.
+    /// A grant with stages or vesting can't be changed, its schedule adds up to its amounts.
+    pub(crate) fn grant_change_blocker(&self, grant_id: GrantId) -> Option<String> {
+        let status = self.grant_record(grant_id).status;
+        if status != GrantStatus::Minted {
+            return Some(format!("a {:?} grant can't be changed", status));
+        }
+        let Some(nft_entry) = self.nft_entry(grant_id) else {
+            return Some("grant has no nft entry".to_string());
+        };
+        if self.nft_claims_in_flight.contains(&nft_entry.token_id) {
+            return Some("nft claim is already in progress".to_string());
+        }
+        let Some(balance_entry) = self.balance_entry(grant_id) else {
+            return Some("grant has no balance entry".to_string());
+        };
+        if balance_entry.is_expired(env::block_timestamp()) {
+            return Some("grant is expired".to_string());
+        }
+        if !balance_entry.stages.is_empty() || balance_entry.vesting.is_some() {
+            return Some("a grant with stages or vesting can't be changed".to_string());
+        }
+
+        None
+    }
+
+    /// Record of a grant the caller owns and can still change, panics with the reason otherwise.
+    fn changeable_grant(&self, grant_id: GrantId) -> GrantRecord {
+        let record = self.grant_record(grant_id);
+        require!(record.owner == env::predecessor_account_id(), "Only the owner can change a grant");
+        if let Some(reason) = self.grant_change_blocker(grant_id) {
+            env::panic_str(&format!("Grant can't be changed, {}", reason));
+        }
+
+        record
+    }
+
+    /// Log a top up with the amounts added, or an amendment with the amounts taken off the grant.
+    pub(crate) fn log_grant_change(&self, grant_id: GrantId, top_up: bool, main_token_amount: NearToken, ft_amount: u128) {
+        let GrantRecord { campaign_id, owner, spender, .. } = self.grant_record(grant_id);
+        let grant_log = GrantLog {
+            owner_id: owner.to_string(),
+            spender_id: spender.to_string(),
+            token_ids: self.nft_entry(grant_id).map(|x| x.token_id).into_iter().collect(),
+            main_token_amount: main_token_amount.as_yoctonear().to_string(),
+            ft_token_amount: ft_amount.to_string(),
+            grant_id: Some(grant_id),
+            campaign_id: Some(campaign_id),
+            memo: Some("L2E Team".to_string()),
+        };
+        let event = if top_up {
+            EventLogVariant::TopUpGrant(vec![grant_log])
+        } else {
+            EventLogVariant::AmendGrant(vec![grant_log])
+        };
+        let grant_change_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event,
+        };
+
+        env::log_str(&grant_change_log.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::DEFAULT_CAMPAIGN_ID;
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::{testing_env, PromiseOrValue, PromiseResult};
+
+    #[test]
+    fn test_top_up_and_amend_grant_before_claim() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init(erc20.clone(), "erc721.near".parse().unwrap());
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(100));
+        testing_env!(context(&erc20).build());
+        let _ = contract.ft_on_transfer(owner.clone(), U128::from(100), format!(r#"{{"grant_id":{}}}"#, grant_id));
+
+        // a bonus in main token and in ft, the ft top up is escrowed in full
+        testing_env!(context(&owner).attached_deposit(NearToken::from_near(1)).build());
+        assert!(contract.top_up_grant(grant_id));
+        testing_env!(context(&erc20).build());
+        let msg = format!(r#"{{"grant_id":{},"top_up":true}}"#, grant_id);
+        match contract.ft_on_transfer(owner.clone(), U128::from(50), msg) {
+            PromiseOrValue::Value(unused) => assert_eq!(unused, U128::from(0)),
+            PromiseOrValue::Promise(_) => panic!("ft_on_transfer should not return a promise"),
+        }
+        let balance_entry = contract.balance_entry(grant_id).unwrap();
+        assert_eq!(balance_entry.main_token_amount, NearToken::from_near(2));
+        assert_eq!(balance_entry.ft_pledged, U128::from(150));
+        assert_eq!(balance_entry.ft_escrowed, U128::from(150));
+        assert_eq!(contract.get_campaign(DEFAULT_CAMPAIGN_ID).unwrap().ft_granted, U128::from(150));
+
+        // lowering the amounts refunds the difference
+        testing_env!(context(&owner).build());
+        assert!(contract.amend_grant(grant_id, NearToken::from_millinear(1500), U128::from(120)));
+        let balance_entry = contract.balance_entry(grant_id).unwrap();
+        assert_eq!(balance_entry.main_token_amount, NearToken::from_millinear(1500));
+        assert_eq!(balance_entry.ft_pledged, U128::from(120));
+        assert_eq!(balance_entry.ft_escrowed, U128::from(120));
+        assert_eq!(contract.get_campaign(DEFAULT_CAMPAIGN_ID).unwrap().ft_granted, U128::from(120));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::Minted));
+    }
+
+    #[test]
+    #[should_panic(expected = "amend_grant can only lower the amounts, use top_up_grant to raise them")]
+    fn test_amend_grant_rejects_higher_amounts() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+        contract.amend_grant(grant_id, NearToken::from_near(1), U128::from(10));
+    }
+
+    #[test]
+    fn test_amend_grant_callback_adds_failed_refund_back() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init(erc20.clone(), "erc721.near".parse().unwrap());
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(2), U128::from(100));
+        testing_env!(context(&erc20).build());
+        let _ = contract.ft_on_transfer(owner.clone(), U128::from(100), format!(r#"{{"grant_id":{}}}"#, grant_id));
+        testing_env!(context(&owner).build());
+        assert!(contract.amend_grant(grant_id, NearToken::from_near(1), U128::from(60)));
+        // nothing is refunded in the ledger before the transfers resolved
+        assert_eq!(contract.get_escrow_ledger(owner.clone()).main_token.refunded, U128::from(0));
+
+        // main token refund succeeded, ft_transfer failed
+        callback_env(vec![PromiseResult::Successful(vec![]), PromiseResult::Failed]);
+        assert!(!contract.amend_grant_callback(grant_id, erc20.clone(), NearToken::from_near(1), U128::from(40)));
+
+        let balance_entry = contract.balance_entry(grant_id).unwrap();
+        assert_eq!(balance_entry.main_token_amount, NearToken::from_near(1));
+        assert_eq!(balance_entry.ft_pledged, U128::from(100));
+        assert_eq!(balance_entry.ft_escrowed, U128::from(100));
+        assert_eq!(contract.get_campaign(DEFAULT_CAMPAIGN_ID).unwrap().ft_granted, U128::from(100));
+        let ledger = contract.get_escrow_ledger(owner);
+        assert_eq!(ledger.main_token.refunded.0, NearToken::from_near(1).as_yoctonear());
+        assert_eq!(ledger.main_token.committed.0, NearToken::from_near(1).as_yoctonear());
+        assert_eq!(ledger.ft_committed(&erc20), 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Grant can't be changed, a NftClaimed grant can't be changed")]
+    fn test_top_up_grant_rejects_claimed_nft() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+        testing_env!(context(&spender).build());
+        contract.transfer_nft_from(grant_id, None, None, None);
+        contract.nft_transfer_callback(grant_id, None, Ok(()));
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_near(1)).build());
+        contract.top_up_grant(grant_id);
+    }
+}
@@ -0,0 +1,408 @@
+use near_contract_standards::non_fungible_token::Token;
+use near_sdk::json_types::{Base58CryptoHash, U64};
+use near_sdk::{env, log, near, require, AccountId, CryptoHash, NearToken, Promise, PromiseError};
+
+use crate::{
+    ext_nft_contract, AttendanceClaimLog, AttendanceSessionLog, CampaignId, EscrowAsset, EventLog, EventLogVariant,
+    L2eTop, L2eTopExt, CONSTRACT_NAME, CONSTRACT_VERSION, NFT_MINT_DEPOSIT,
+};
+
+pub type AttendanceSessionId = u64;
+
+/// Session of a workshop, attendees claim the nft template of its campaign with the code shown during the session.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttendanceSession {
+    pub session_id: AttendanceSessionId,
+    pub campaign_id: CampaignId,
+    pub owner: AccountId,
+    // sha256 of the utf-8 claim code
+    pub code_hash: Base58CryptoHash,
+    // block timestamps in nanoseconds, the code is accepted from starts_at until before ends_at
+    pub starts_at: U64,
+    pub ends_at: U64,
+    pub max_claims: u32,
+    // claims minted or waiting for attendance_mint_callback
+    pub claimed_count: u32,
+    // escrowed NFT_MINT_DEPOSIT of each claim not minted yet
+    pub mint_deposit_left: NearToken,
+    // closed by the owner after it ended, mint_deposit_left went back to the owner
+    pub closed: bool,
+}
+
+#[near]
+impl L2eTop {
+    /// Register a session in a campaign of the caller with the sha256 of its claim code.
+    /// The campaign needs an nft template, set with set_nft_template, and no verifier.
+    /// The attached deposit covers NFT_MINT_DEPOSIT for each of max_claims, it is escrowed and the rest refunded.
+    #[payable]
+    pub fn create_attendance_session(
+        &mut self,
+        campaign_id: CampaignId,
+        code_hash: Base58CryptoHash,
+        starts_at: U64,
+        ends_at: U64,
+        max_claims: u32,
+    ) -> AttendanceSessionId {
+        let owner = env::predecessor_account_id();
+        let campaign = self.campaign_for_grant(campaign_id, &owner);
+        require!(campaign.owner == owner, "Only the campaign owner can create an attendance session");
+        require!(campaign.nft_template.is_some(), "Campaign has no nft template");
+        require!(!self.is_claim_gated(campaign_id), "A campaign with a verifier can't have attendance sessions");
+        require!(starts_at.0 < ends_at.0, "starts_at should be before ends_at");
+        require!(ends_at.0 > env::block_timestamp(), "ends_at should be in the future");
+        require!(max_claims > 0, "max_claims should be greater than 0");
+        let attached_amount = env::attached_deposit();
+        let mint_deposit_total = NFT_MINT_DEPOSIT.saturating_mul(max_claims as u128);
+        require!(attached_amount >= mint_deposit_total, "attached deposit does not cover the mint deposits");
+
+        let session_id = self.next_attendance_session_id;
+        self.next_attendance_session_id += 1;
+        let session = AttendanceSession {
+            session_id,
+            campaign_id,
+            owner: owner.clone(),
+            code_hash,
+            starts_at,
+            ends_at,
+            max_claims,
+            claimed_count: 0,
+            mint_deposit_left: mint_deposit_total,
+            closed: false,
+        };
+        self.attendance_sessions.insert(session_id, session);
+        self.escrow_deposit(&owner, EscrowAsset::MainToken, mint_deposit_total.as_yoctonear());
+        self.log_attendance_session(EventLogVariant::CreateAttendanceSession, session_id, mint_deposit_total);
+
+        let refund = attached_amount.saturating_sub(mint_deposit_total);
+        if refund > NearToken::from_near(0) {
+            log!("Refund unused deposit: {:?}", refund);
+            let _refund_promise = Promise::new(owner).transfer(refund);
+        }
+
+        session_id
+    }
+
+    /// Mint the nft template of the session's campaign to the caller, with the claim code of a running session.
+    /// Every account claims once per session, a failed mint can be claimed again.
+    pub fn claim_attendance(&mut self, session_id: AttendanceSessionId, code: String) -> bool {
+        let attendee = env::predecessor_account_id();
+        let session = self.attendance_sessions.get(&session_id).expect("Attendance session not found").clone();
+        require!(!session.closed, "Attendance session is closed");
+        require!(!self.is_claim_gated(session.campaign_id), "Claims of this campaign need a verifier");
+        let now = env::block_timestamp();
+        require!(now >= session.starts_at.0, "Attendance session has not started");
+        require!(now < session.ends_at.0, "Attendance session has ended");
+        require!(
+            env::sha256_array(code.as_bytes()) == CryptoHash::from(session.code_hash),
+            "Invalid claim code"
+        );
+        require!(session.claimed_count < session.max_claims, "Attendance session is full");
+        require!(
+            !self.attendance_claims.contains(&(session_id, attendee.clone())),
+            "Attendance is already claimed"
+        );
+        let campaign = self.campaigns.get(&session.campaign_id).expect("Campaign not found");
+        let token_metadata = campaign.nft_template.clone().expect("Campaign has no nft template");
+        let erc721_address = campaign.erc721_address.clone();
+        let mint_deposit_left = session.mint_deposit_left.checked_sub(NFT_MINT_DEPOSIT);
+        require!(mint_deposit_left.is_some(), "Attendance session mint deposits are used up");
+
+        // the claim counts now, attendance_mint_callback gives it back if the mint fails
+        self.attendance_claims.insert((session_id, attendee.clone()));
+        let session = self.attendance_sessions.get_mut(&session_id).expect("Attendance session not found");
+        session.claimed_count += 1;
+        session.mint_deposit_left = mint_deposit_left.unwrap_or_default();
+
+        let token_id = self.next_token_id();
+        let _mint_nft_promise = ext_nft_contract::ext(erc721_address)
+            .with_attached_deposit(NFT_MINT_DEPOSIT)
+            .nft_mint(token_id, attendee.clone(), token_metadata)
+            .then(Self::ext(env::current_account_id()).attendance_mint_callback(session_id, attendee));
+
+        true
+    }
+
+    /// Log the minted attendance nft, or free the claim of the attendee if nft_mint failed.
+    /// The mint deposit is paid with the mint, a deposit given back to a closed session is refunded to the owner.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn attendance_mint_callback(
+        &mut self,
+        session_id: AttendanceSessionId,
+        attendee: AccountId,
+        #[callback_result] call_result: Result<Token, PromiseError>,
+    ) -> bool {
+        let (event, token_id): (fn(Vec<AttendanceClaimLog>) -> EventLogVariant, _) = match call_result {
+            Ok(token) => {
+                let owner = self.attendance_sessions.get(&session_id).expect("Attendance session not found").owner.clone();
+                self.escrow_pay(&owner, EscrowAsset::MainToken, NFT_MINT_DEPOSIT.as_yoctonear());
+                (EventLogVariant::ClaimAttendance, Some(token.token_id))
+            }
+            Err(e) => {
+                log!("There was an error contacting NFT contract nft_mint: {:#?}", e);
+                self.attendance_claims.remove(&(session_id, attendee.clone()));
+                let session = self.attendance_sessions.get_mut(&session_id).expect("Attendance session not found");
+                session.claimed_count -= 1;
+                session.mint_deposit_left = session.mint_deposit_left.saturating_add(NFT_MINT_DEPOSIT);
+                if session.closed {
+                    self.refund_attendance_session(session_id);
+                }
+                (EventLogVariant::ClaimAttendanceFailed, None)
+            }
+        };
+        let minted = token_id.is_some();
+
+        let campaign_id = self.attendance_sessions.get(&session_id).map(|x| x.campaign_id);
+        let claim_attendance_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: event(vec![AttendanceClaimLog {
+                session_id,
+                attendee_id: attendee.to_string(),
+                token_id,
+                campaign_id,
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+        env::log_str(&claim_attendance_log.to_string());
+
+        minted
+    }
+
+    /// Close a session of the caller once it ended and refund its unused mint deposits.
+    /// Claims still waiting for attendance_mint_callback refund their deposit when their mint fails.
+    pub fn close_attendance_session(&mut self, session_id: AttendanceSessionId) -> bool {
+        let session = self.attendance_sessions.get_mut(&session_id).expect("Attendance session not found");
+        require!(session.owner == env::predecessor_account_id(), "Only the owner can close an attendance session");
+        require!(!session.closed, "Attendance session is closed");
+        require!(session.ends_at.0 <= env::block_timestamp(), "Attendance session can only be closed once it ended");
+        session.closed = true;
+        self.refund_attendance_session(session_id);
+
+        true
+    }
+
+    /// Resolve the refund of close_attendance_session.
+    /// A failed refund is added back to the session, which is open again so the owner can close it again.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn attendance_close_callback(&mut self, session_id: AttendanceSessionId, refund_mint_deposit: NearToken) -> bool {
+        let owner = self.attendance_sessions.get(&session_id).expect("Attendance session not found").owner.clone();
+        if Self::promise_succeeded(0) {
+            self.escrow_refund(&owner, EscrowAsset::MainToken, refund_mint_deposit.as_yoctonear());
+            return true;
+        }
+
+        let session = self.attendance_sessions.get_mut(&session_id).expect("Attendance session not found");
+        session.closed = false;
+        session.mint_deposit_left = session.mint_deposit_left.saturating_add(refund_mint_deposit);
+        self.log_attendance_session(EventLogVariant::CloseAttendanceSessionFailed, session_id, refund_mint_deposit);
+
+        false
+    }
+
+    pub fn get_attendance_session(&self, session_id: AttendanceSessionId) -> Option<AttendanceSession> {
+        self.attendance_sessions.get(&session_id).cloned()
+    }
+
+    pub fn has_claimed_attendance(&self, session_id: AttendanceSessionId, account_id: AccountId) -> bool {
+        self.attendance_claims.contains(&(session_id, account_id))
+    }
+}
+
+impl L2eTop {
+    /// Refund the unused mint deposits of a closed session to the owner, resolved by attendance_close_callback.
+    fn refund_attendance_session(&mut self, session_id: AttendanceSessionId) {
+        let session = self.attendance_sessions.get_mut(&session_id).expect("Attendance session not found");
+        let refund_mint_deposit = session.mint_deposit_left;
+        session.mint_deposit_left = NearToken::from_near(0);
+        let owner = session.owner.clone();
+
+        // the ledger records the refund once it resolved, in attendance_close_callback
+        if refund_mint_deposit > NearToken::from_near(0) {
+            let _close_attendance_session_promise = Promise::new(owner)
+                .transfer(refund_mint_deposit)
+                .then(Self::ext(env::current_account_id()).attendance_close_callback(session_id, refund_mint_deposit));
+        }
+        self.log_attendance_session(EventLogVariant::CloseAttendanceSession, session_id, refund_mint_deposit);
+    }
+
+    fn log_attendance_session(
+        &self,
+        event: fn(Vec<AttendanceSessionLog>) -> EventLogVariant,
+        session_id: AttendanceSessionId,
+        main_token_amount: NearToken,
+    ) {
+        let session = self.attendance_sessions.get(&session_id).expect("Attendance session not found");
+        let attendance_session_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: event(vec![AttendanceSessionLog {
+                session_id,
+                campaign_id: session.campaign_id,
+                owner_id: session.owner.to_string(),
+                starts_at: session.starts_at.0.to_string(),
+                ends_at: session.ends_at.0.to_string(),
+                max_claims: session.max_claims,
+                main_token_amount: main_token_amount.as_yoctonear().to_string(),
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+
+        env::log_str(&attendance_session_log.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+    use near_sdk::json_types::U128;
+    use near_sdk::{testing_env, PromiseResult};
+
+    /// Campaign of owner with an nft template.
+    fn create_attendance_campaign(contract: &mut L2eTop, owner: &AccountId) -> CampaignId {
+        testing_env!(context(owner).build());
+        let campaign_id = contract.create_campaign("Rust 101".to_string(), None, None, None, None, None);
+        let token_metadata = TokenMetadata {
+            title: Some("Rust 101 workshop".to_string()),
+            description: None,
+            media: None,
+            media_hash: None,
+            copies: None,
+            issued_at: None,
+            expires_at: None,
+            starts_at: None,
+            updated_at: None,
+            extra: None,
+            reference: None,
+            reference_hash: None,
+        };
+        assert!(contract.set_nft_template(campaign_id, Some(token_metadata)));
+        campaign_id
+    }
+
+    /// Session of the code "rust-42" from timestamp 1000 until 2000, with the given deposit attached.
+    fn create_attendance_session_with_deposit(
+        contract: &mut L2eTop,
+        owner: &AccountId,
+        campaign_id: CampaignId,
+        max_claims: u32,
+        deposit: NearToken,
+    ) -> AttendanceSessionId {
+        testing_env!(context(owner).attached_deposit(deposit).build());
+        let code_hash = env::sha256_array(b"rust-42");
+        contract.create_attendance_session(campaign_id, code_hash.into(), U64::from(1_000), U64::from(2_000), max_claims)
+    }
+
+    /// Campaign of owner with an nft template and a session funded with the mint deposits of max_claims.
+    fn create_attendance_session(contract: &mut L2eTop, owner: &AccountId, max_claims: u32) -> AttendanceSessionId {
+        let campaign_id = create_attendance_campaign(contract, owner);
+        let deposit = NFT_MINT_DEPOSIT.saturating_mul(max_claims as u128);
+        create_attendance_session_with_deposit(contract, owner, campaign_id, max_claims, deposit)
+    }
+
+    #[test]
+    fn test_claim_attendance_mints_template_and_frees_failed_mint() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let attendee: AccountId = "attendee.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let session_id = create_attendance_session(&mut contract, &owner, 1);
+
+        testing_env!(context(&attendee).block_timestamp(1_500).build());
+        assert!(contract.claim_attendance(session_id, "rust-42".to_string()));
+        assert!(contract.has_claimed_attendance(session_id, attendee.clone()));
+        assert_eq!(contract.get_attendance_session(session_id).unwrap().claimed_count, 1);
+
+        assert!(!contract.attendance_mint_callback(session_id, attendee.clone(), Err(PromiseError::Failed)));
+        assert!(!contract.has_claimed_attendance(session_id, attendee.clone()));
+        let session = contract.get_attendance_session(session_id).unwrap();
+        assert_eq!(session.claimed_count, 0);
+        assert_eq!(session.mint_deposit_left, NFT_MINT_DEPOSIT);
+    }
+
+    #[test]
+    #[should_panic(expected = "attached deposit does not cover the mint deposits")]
+    fn test_create_attendance_session_rejects_missing_deposit() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let campaign_id = create_attendance_campaign(&mut contract, &owner);
+        create_attendance_session_with_deposit(&mut contract, &owner, campaign_id, 10, NearToken::from_near(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "A campaign with a verifier can't have attendance sessions")]
+    fn test_create_attendance_session_rejects_verifier_campaign() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let campaign_id = create_attendance_campaign(&mut contract, &owner);
+        assert!(contract.set_verifier_contract(campaign_id, Some("verifier.near".parse().unwrap())));
+        create_attendance_session_with_deposit(&mut contract, &owner, campaign_id, 1, NFT_MINT_DEPOSIT);
+    }
+
+    #[test]
+    fn test_close_attendance_session_refunds_unused_mint_deposits() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let attendee: AccountId = "attendee.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let session_id = create_attendance_session(&mut contract, &owner, 3);
+        assert_eq!(
+            contract.get_escrow_ledger(owner.clone()).main_token.committed,
+            U128::from(NFT_MINT_DEPOSIT.saturating_mul(3).as_yoctonear())
+        );
+
+        // the minted claim pays its deposit
+        testing_env!(context(&attendee).block_timestamp(1_500).build());
+        assert!(contract.claim_attendance(session_id, "rust-42".to_string()));
+        let token = Token { token_id: "10001".to_string(), owner_id: attendee.clone(), metadata: None, approved_account_ids: None };
+        assert!(contract.attendance_mint_callback(session_id, attendee, Ok(token)));
+
+        testing_env!(context(&owner).block_timestamp(2_000).build());
+        assert!(contract.close_attendance_session(session_id));
+        let session = contract.get_attendance_session(session_id).unwrap();
+        assert!(session.closed);
+        assert_eq!(session.mint_deposit_left, NearToken::from_near(0));
+
+        callback_env(vec![PromiseResult::Successful(vec![])]);
+        assert!(contract.attendance_close_callback(session_id, NFT_MINT_DEPOSIT.saturating_mul(2)));
+        let ledger = contract.get_escrow_ledger(owner);
+        assert_eq!(ledger.main_token.committed, U128::from(0));
+        assert_eq!(ledger.main_token.paid, U128::from(NFT_MINT_DEPOSIT.as_yoctonear()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Attendance is already claimed")]
+    fn test_claim_attendance_once_per_account() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let attendee: AccountId = "attendee.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let session_id = create_attendance_session(&mut contract, &owner, 10);
+
+        testing_env!(context(&attendee).block_timestamp(1_500).build());
+        contract.claim_attendance(session_id, "rust-42".to_string());
+        contract.claim_attendance(session_id, "rust-42".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Attendance session has ended")]
+    fn test_claim_attendance_rejects_code_after_session() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let session_id = create_attendance_session(&mut contract, &owner, 10);
+
+        testing_env!(context(&"attendee.near".parse().unwrap()).block_timestamp(2_000).build());
+        contract.claim_attendance(session_id, "rust-42".to_string());
+    }
+}
@@ -0,0 +1,247 @@
+use near_sdk::json_types::{Base64VecU8, U64};
+use near_sdk::{env, near, require, AccountId, CurveType, PublicKey};
+
+use crate::{
+    Campaign, CampaignId, EventLog, EventLogVariant, L2eTop, L2eTopExt, VerifierKeyLog, CONSTRACT_NAME,
+    CONSTRACT_VERSION,
+};
+
+/// Proof from a verifier of the campaign that the spender completed what a claim pays for.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attestation {
+    // unique per campaign, an attestation is used once
+    pub nonce: U64,
+    // block timestamp in nanoseconds the attestation can be used until
+    pub expires_at: U64,
+    // ed25519 signature of the borsh serialized AttestationMessage
+    pub signature: Base64VecU8,
+}
+
+/// What a verifier signs, borsh serialized.
+/// stage is the stage claimed by claim_stage, None for the nft claim of transfer_nft_from.
+#[near(serializers = [borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttestationMessage {
+    pub contract: AccountId,
+    pub campaign_id: CampaignId,
+    pub spender: AccountId,
+    pub stage: Option<u32>,
+    pub nonce: u64,
+    pub expires_at: u64,
+}
+
+#[near]
+impl L2eTop {
+    /// Add an ed25519 verifier key to a campaign, called by its owner or an admin.
+    /// Once a campaign has a key, its nfts and stages are only claimed with an attestation signed by one of its keys.
+    pub fn add_verifier_key(&mut self, campaign_id: CampaignId, public_key: PublicKey) -> bool {
+        require!(public_key.curve_type() == CurveType::ED25519, "Verifier key should be an ed25519 key");
+        let campaign = self.verifier_campaign_mut(campaign_id);
+        require!(!campaign.verifier_keys.contains(&public_key), "Verifier key is already added");
+        campaign.verifier_keys.push(public_key.clone());
+        Self::log_verifier_key(EventLogVariant::AddVerifierKey, campaign_id, public_key);
+
+        true
+    }
+
+    /// Remove a verifier key of a campaign, called by its owner or an admin.
+    /// Claims need no attestation anymore once the last key is removed.
+    pub fn remove_verifier_key(&mut self, campaign_id: CampaignId, public_key: PublicKey) -> bool {
+        let campaign = self.verifier_campaign_mut(campaign_id);
+        let index = campaign
+            .verifier_keys
+            .iter()
+            .position(|x| x == &public_key)
+            .expect("Verifier key not found");
+        campaign.verifier_keys.remove(index);
+        Self::log_verifier_key(EventLogVariant::RemoveVerifierKey, campaign_id, public_key);
+
+        true
+    }
+
+    pub fn is_attestation_nonce_used(&self, campaign_id: CampaignId, nonce: U64) -> bool {
+        self.attestation_nonces.contains(&(campaign_id, nonce.0))
+    }
+}
+
+impl L2eTop {
+    /// Check the attestation of a claim of spender in campaign_id and use up its nonce.
+    /// Nothing is checked for a campaign without verifier keys.
+    pub(crate) fn use_attestation(
+        &mut self,
+        campaign_id: CampaignId,
+        spender: &AccountId,
+        stage: Option<u32>,
+        attestation: Option<Attestation>,
+    ) {
+        let verifier_keys = &self.campaigns.get(&campaign_id).expect("Campaign not found").verifier_keys;
+        if verifier_keys.is_empty() {
+            return;
+        }
+        let attestation = attestation.expect("Campaign requires a verifier attestation");
+        require!(attestation.expires_at.0 > env::block_timestamp(), "Attestation is expired");
+        require!(
+            !self.attestation_nonces.contains(&(campaign_id, attestation.nonce.0)),
+            "Attestation nonce is already used"
+        );
+        let signature: [u8; 64] = attestation
+            .signature
+            .0
+            .as_slice()
+            .try_into()
+            .unwrap_or_else(|_| env::panic_str("Attestation signature should be 64 bytes"));
+
+        let message = near_sdk::borsh::to_vec(&AttestationMessage {
+            contract: env::current_account_id(),
+            campaign_id,
+            spender: spender.clone(),
+            stage,
+            nonce: attestation.nonce.0,
+            expires_at: attestation.expires_at.0,
+        })
+        .expect("Failed to serialize the attestation");
+        let signed = verifier_keys.iter().any(|key| {
+            // the key data of an ed25519 PublicKey is its curve type byte and the 32 key bytes
+            let key: &[u8; 32] = key.as_bytes()[1..].try_into().expect("Verifier key should be 32 bytes");
+            env::ed25519_verify(&signature, &message, key)
+        });
+        require!(signed, "Attestation is not signed by a verifier of the campaign");
+
+        self.attestation_nonces.insert((campaign_id, attestation.nonce.0));
+    }
+
+    /// Whether claims in a campaign need a verifier attestation.
+    pub(crate) fn requires_attestation(&self, campaign_id: CampaignId) -> bool {
+        self.campaigns.get(&campaign_id).is_some_and(|x| !x.verifier_keys.is_empty())
+    }
+
+    /// Campaign whose verifier keys and verifier contract the caller can change, its owner or an admin.
+    pub(crate) fn verifier_campaign_mut(&mut self, campaign_id: CampaignId) -> &mut Campaign {
+        let caller = env::predecessor_account_id();
+        let is_admin = self.admin_address.contains(&caller);
+        let campaign = self.campaigns.get_mut(&campaign_id).expect("Campaign not found");
+        require!(
+            campaign.owner == caller || is_admin,
+            "Only the campaign owner or an admin can change its verifiers"
+        );
+        campaign
+    }
+
+    fn log_verifier_key(event: fn(Vec<VerifierKeyLog>) -> EventLogVariant, campaign_id: CampaignId, public_key: PublicKey) {
+        let verifier_key_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: event(vec![VerifierKeyLog {
+                campaign_id,
+                owner_id: env::predecessor_account_id().to_string(),
+                public_key: String::from(&public_key),
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+
+        env::log_str(&verifier_key_log.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::DEFAULT_CAMPAIGN_ID;
+    use near_sdk::json_types::U128;
+    use near_sdk::{testing_env, NearToken, PromiseError};
+
+    /// Attestation of the verifier with secret key `[seed; 32]` for a claim of spender in the default campaign.
+    fn sign_attestation(seed: u8, spender: &AccountId, stage: Option<u32>, nonce: u64) -> Attestation {
+        use ed25519_dalek::Signer;
+        let message = near_sdk::borsh::to_vec(&AttestationMessage {
+            contract: env::current_account_id(),
+            campaign_id: DEFAULT_CAMPAIGN_ID,
+            spender: spender.clone(),
+            stage,
+            nonce,
+            expires_at: u64::MAX,
+        })
+        .unwrap();
+        let signature = ed25519_dalek::SigningKey::from_bytes(&[seed; 32]).sign(&message);
+        Attestation {
+            nonce: U64::from(nonce),
+            expires_at: U64::from(u64::MAX),
+            signature: signature.to_bytes().to_vec().into(),
+        }
+    }
+
+    fn verifier_key(seed: u8) -> near_sdk::PublicKey {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[seed; 32]).verifying_key();
+        near_sdk::PublicKey::from_parts(near_sdk::CurveType::ED25519, key.to_bytes().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_transfer_nft_from_checks_verifier_attestation() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        assert!(contract.add_verifier_key(DEFAULT_CAMPAIGN_ID, verifier_key(7)));
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        testing_env!(context(&spender).build());
+        assert!(!contract.is_attestation_nonce_used(DEFAULT_CAMPAIGN_ID, U64::from(1)));
+        assert!(contract.transfer_nft_from(grant_id, None, Some(sign_attestation(7, &spender, None, 1)), None));
+        assert!(contract.is_attestation_nonce_used(DEFAULT_CAMPAIGN_ID, U64::from(1)));
+
+        // claim_all leaves the nft of a campaign with verifier keys to transfer_nft_from
+        assert!(!contract.nft_transfer_callback(grant_id, None, Err(PromiseError::Failed)));
+        assert!(contract.claim_all(None, None).nft_claims.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Attestation is not signed by a verifier of the campaign")]
+    fn test_transfer_nft_from_rejects_attestation_of_a_stage() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        assert!(contract.add_verifier_key(DEFAULT_CAMPAIGN_ID, verifier_key(7)));
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        testing_env!(context(&spender).build());
+        contract.transfer_nft_from(grant_id, None, Some(sign_attestation(7, &spender, Some(0), 1)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Attestation nonce is already used")]
+    fn test_transfer_nft_from_rejects_replayed_attestation() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        assert!(contract.add_verifier_key(DEFAULT_CAMPAIGN_ID, verifier_key(7)));
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        testing_env!(context(&spender).build());
+        let attestation = sign_attestation(7, &spender, None, 1);
+        assert!(contract.transfer_nft_from(grant_id, None, Some(attestation.clone()), None));
+        assert!(!contract.nft_transfer_callback(grant_id, None, Err(PromiseError::Failed)));
+        contract.transfer_nft_from(grant_id, None, Some(attestation), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Attestation is not signed by a verifier of the campaign")]
+    fn test_transfer_nft_from_rejects_attestation_of_other_key() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        assert!(contract.add_verifier_key(DEFAULT_CAMPAIGN_ID, verifier_key(7)));
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        testing_env!(context(&spender).build());
+        contract.transfer_nft_from(grant_id, None, Some(sign_attestation(8, &spender, None, 1)), None);
+    }
+}
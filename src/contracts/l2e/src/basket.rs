@@ -0,0 +1,238 @@
+use near_sdk::json_types::U128;
+use near_sdk::{near, require, AccountId, Promise};
+
+use crate::{format_amount, L2eTop};
+
+/// Ft of a sponsor token paid with a grant next to the ft of its erc20_address, stored in `BalanceEntry::ft_basket`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasketEntry {
+    pub ft_contract: AccountId,
+    // ft pledged by approve_for_spender, in raw units of ft_contract
+    pub ft_pledged: U128,
+    // ft that arrived through ft_on_transfer, it is the amount paid out on claim
+    pub ft_escrowed: U128,
+}
+
+/// A token of the reward basket given to approve_for_spender.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasketReward {
+    pub ft_contract: AccountId,
+    // raw units of ft_contract
+    pub ft_amount: U128,
+}
+
+/// Escrowed ft of a basket entry with its human-readable value.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasketRewardView {
+    pub ft_contract: AccountId,
+    // raw units of ft_contract
+    pub ft_amount: U128,
+    // None until the decimals of ft_contract are known
+    pub ft_decimals: Option<u8>,
+    pub ft_amount_display: Option<String>,
+}
+
+impl BasketEntry {
+    /// Whether the entry has escrowed ft, only those entries are paid or returned.
+    pub fn is_funded(&self) -> bool {
+        self.ft_escrowed.0 > 0
+    }
+}
+
+impl L2eTop {
+    /// Basket of a new grant, every token registered, listed once and other than erc20_address of the grant.
+    pub(crate) fn new_basket(&self, ft_basket: Option<Vec<BasketReward>>, erc20_address: &AccountId) -> Vec<BasketEntry> {
+        let mut basket: Vec<BasketEntry> = vec![];
+        for reward in ft_basket.unwrap_or_default() {
+            require!(reward.ft_amount.0 > 0, "ft_basket amounts should be greater than 0");
+            require!(
+                self.erc20_address.iter().any(|x| x == &reward.ft_contract),
+                "ft_basket token is not registered"
+            );
+            require!(
+                &reward.ft_contract != erc20_address,
+                "ft_basket can't hold the erc20 of the campaign, use ft_amount"
+            );
+            require!(
+                basket.iter().all(|x| x.ft_contract != reward.ft_contract),
+                "ft_basket lists a token more than once"
+            );
+            basket.push(BasketEntry {
+                ft_contract: reward.ft_contract,
+                ft_pledged: reward.ft_amount,
+                ft_escrowed: U128::from(0),
+            });
+        }
+
+        basket
+    }
+
+    /// Add an ft_transfer to receiver for every funded basket entry after the legs of `payout`, in basket order.
+    /// With register_receiver each leg registers receiver with its token first.
+    /// A callback reads the results with `basket_results`.
+    pub(crate) fn basket_payout(
+        payout: Option<Promise>,
+        receiver: &AccountId,
+        ft_basket: &[BasketEntry],
+        memo: Option<String>,
+        register_receiver: bool,
+    ) -> Option<Promise> {
+        ft_basket.iter().filter(|x| x.is_funded()).fold(payout, |payout, entry| {
+            let ft_promise =
+                Self::ft_transfer_to(&entry.ft_contract, receiver, entry.ft_escrowed, memo.clone(), register_receiver);
+            Some(match payout {
+                Some(promise) => promise.and(ft_promise),
+                None => ft_promise,
+            })
+        })
+    }
+
+    /// Whether the leg of each basket entry succeeded, the legs of a `basket_payout` start at result `first_result`.
+    /// An entry with nothing escrowed counts as paid.
+    pub(crate) fn basket_results(ft_basket: &[BasketEntry], first_result: u64) -> Vec<bool> {
+        let mut result_index = first_result;
+        ft_basket
+            .iter()
+            .map(|entry| {
+                if !entry.is_funded() {
+                    return true;
+                }
+                let paid = Self::promise_succeeded(result_index);
+                result_index += 1;
+                paid
+            })
+            .collect()
+    }
+
+    pub(crate) fn basket_view(&self, ft_basket: &[BasketEntry]) -> Vec<BasketRewardView> {
+        ft_basket
+            .iter()
+            .map(|entry| {
+                let ft_decimals = self.ft_decimals.get(&entry.ft_contract).copied();
+                BasketRewardView {
+                    ft_contract: entry.ft_contract.clone(),
+                    ft_amount: entry.ft_escrowed,
+                    ft_decimals,
+                    ft_amount_display: ft_decimals.map(|decimals| format_amount(entry.ft_escrowed.0, decimals)),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::{GrantStatus, DEFAULT_CAMPAIGN_ID};
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_contract_standards::non_fungible_token::Token;
+    use near_sdk::{env, testing_env, NearToken, PromiseOrValue, PromiseResult};
+
+    #[test]
+    fn test_transfer_balances_from_retries_failed_basket_leg() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let sponsor: AccountId = "sponsor.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init(erc20.clone(), "erc721.near".parse().unwrap());
+        assert!(contract.add_contract_address(sponsor.clone(), "erc721.near".parse().unwrap()));
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_near(1)).build());
+        let ft_basket = vec![BasketReward { ft_contract: sponsor.clone(), ft_amount: U128::from(50) }];
+        let grant_id = contract
+            .approve_for_spender(DEFAULT_CAMPAIGN_ID, spender.clone(), NearToken::from_near(1), U128::from(100), None, None, None, None, Some(ft_basket))
+            .unwrap();
+        let token_id = contract.nft_entry(grant_id).unwrap().token_id;
+        let token = Token { token_id, owner_id: env::current_account_id(), metadata: None, approved_account_ids: None };
+        contract.nft_mint_callback(grant_id, Ok(token));
+
+        // each token funds its own leg, only the outstanding pledge is kept
+        let msg = format!(r#"{{"grant_id":{}}}"#, grant_id);
+        testing_env!(context(&erc20).build());
+        let _ = contract.ft_on_transfer(owner.clone(), U128::from(100), msg.clone());
+        testing_env!(context(&sponsor).build());
+        match contract.ft_on_transfer(owner.clone(), U128::from(80), msg) {
+            PromiseOrValue::Value(unused) => assert_eq!(unused, U128::from(30)),
+            PromiseOrValue::Promise(_) => panic!("ft_on_transfer should not return a promise"),
+        }
+        let rewards = contract.get_rewards_for_spender(spender.clone(), None);
+        assert_eq!(rewards[0].ft_amount, U128::from(100));
+        assert_eq!(rewards[0].ft_basket[0].ft_contract, sponsor);
+        assert_eq!(rewards[0].ft_basket[0].ft_amount, U128::from(50));
+
+        testing_env!(context(&spender).build());
+        contract.transfer_nft_from(grant_id, None, None, None);
+        contract.nft_transfer_callback(grant_id, None, Ok(()));
+        let balance_entry = contract.balance_entry(grant_id).unwrap();
+        let nft_entry = contract.nft_entry(grant_id).unwrap();
+        assert!(contract.transfer_balances_from(grant_id, None));
+
+        // main token and ft_transfer succeeded, the sponsor ft_transfer failed
+        callback_env(vec![PromiseResult::Successful(vec![]), PromiseResult::Successful(vec![]), PromiseResult::Failed]);
+        assert!(!contract.transfer_balances_callback(spender.clone(), balance_entry, nft_entry.clone(), None));
+        let restored = contract.balance_entry(grant_id).unwrap();
+        assert_eq!(restored.main_token_amount, NearToken::from_near(0));
+        assert_eq!(restored.ft_escrowed, U128::from(0));
+        assert_eq!(
+            restored.ft_basket,
+            vec![BasketEntry { ft_contract: sponsor.clone(), ft_pledged: U128::from(50), ft_escrowed: U128::from(50) }]
+        );
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::NftClaimed));
+
+        // the retry only pays the failed leg
+        testing_env!(context(&spender).build());
+        assert!(contract.transfer_balances_from(grant_id, None));
+        callback_env(vec![PromiseResult::Successful(vec![])]);
+        assert!(contract.transfer_balances_callback(spender, restored, nft_entry, None));
+        assert_eq!(contract.balance_entry(grant_id), None);
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::Paid));
+    }
+
+    #[test]
+    #[should_panic(expected = "ft_basket can't hold the erc20 of the campaign, use ft_amount")]
+    fn test_approve_for_spender_rejects_basket_of_campaign_token() {
+        testing_env!(context(&"bob.near".parse().unwrap()).attached_deposit(NearToken::from_near(1)).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let ft_basket = vec![BasketReward { ft_contract: "erc20.near".parse().unwrap(), ft_amount: U128::from(50) }];
+        contract.approve_for_spender(
+            DEFAULT_CAMPAIGN_ID,
+            "spender.near".parse().unwrap(),
+            NearToken::from_near(1),
+            U128::from(100),
+            None,
+            None,
+            None,
+            None,
+            Some(ft_basket),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "A grant with an ft basket can't be amended")]
+    fn test_amend_grant_rejects_grant_with_basket() {
+        let sponsor: AccountId = "sponsor.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        assert!(contract.add_contract_address(sponsor.clone(), "erc721.near".parse().unwrap()));
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_near(2)).build());
+        let ft_basket = vec![BasketReward { ft_contract: sponsor, ft_amount: U128::from(50) }];
+        let grant_id = contract
+            .approve_for_spender(DEFAULT_CAMPAIGN_ID, "spender.near".parse().unwrap(), NearToken::from_near(2), U128::from(0), None, None, None, None, Some(ft_basket))
+            .unwrap();
+        let token_id = contract.nft_entry(grant_id).unwrap().token_id;
+        let token = Token { token_id, owner_id: env::current_account_id(), metadata: None, approved_account_ids: None };
+        contract.nft_mint_callback(grant_id, Ok(token));
+
+        testing_env!(context(&owner).build());
+        contract.amend_grant(grant_id, NearToken::from_near(1), U128::from(0));
+    }
+}
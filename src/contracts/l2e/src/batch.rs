@@ -0,0 +1,189 @@
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, log, near, require, AccountId, Gas, NearToken, Promise};
+
+use crate::{CampaignId, GrantId, L2eTop, L2eTopExt, GAS_FOR_NFT_MINT_CALLBACK};
+
+/// Gas kept for the nft_mint and ft registration promises of one grant of batch_approve,
+/// nft_mint_callback has its own GAS_FOR_NFT_MINT_CALLBACK on top of it.
+pub const GAS_PER_BATCH_GRANT: Gas = Gas::from_tgas(15);
+/// Gas batch_approve needs to create one more grant and refund the deposit.
+pub const GAS_FOR_BATCH_GRANT_CALL: Gas = Gas::from_tgas(5);
+
+/// A grant of `batch_approve`.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchApproveItem {
+    pub spender: AccountId,
+    pub main_token_amount: NearToken,
+    // raw units of the erc20 of the campaign
+    pub ft_amount: U128,
+    pub token_metadata: Option<TokenMetadata>,
+}
+
+/// Outcome of a `batch_approve` item, the grant_id of the new grant or why the item was skipped.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchApproveResult {
+    pub spender: AccountId,
+    pub grant_id: Option<GrantId>,
+    pub error: Option<String>,
+}
+
+#[near]
+impl L2eTop {
+    /// Approve a grant in campaign_id for each item, the attached deposit covers the main_token_amount of all of them.
+    /// An item that is invalid, over the campaign budget, not covered by the deposit or beyond the gas of the call is skipped,
+    /// the deposit of skipped items and any surplus is refunded to the owner. Skipped items can be sent again in another batch.
+    /// Each grant mints its own nft, nft_mint_callback resolves it like for approve_for_spender, see `get_grant_status`.
+    #[payable]
+    pub fn batch_approve(
+        &mut self,
+        campaign_id: CampaignId,
+        items: Vec<BatchApproveItem>,
+        expires_at: Option<U64>,
+    ) -> Vec<BatchApproveResult> {
+        let owner = env::predecessor_account_id();
+        require!(!items.is_empty(), "items should not be empty");
+        if let Some(expires_at) = expires_at {
+            require!(expires_at.0 > env::block_timestamp(), "expires_at should be in the future");
+        }
+        self.campaign_for_grant(campaign_id, &owner);
+
+        let attached_amount = env::attached_deposit();
+        let mut used_amount = NearToken::from_near(0);
+        let mut reserved_gas = Gas::from_gas(0);
+        let mut results = Vec::with_capacity(items.len());
+        for item in items {
+            let spender = item.spender.clone();
+            match self.batch_item_error(campaign_id, &item, attached_amount.saturating_sub(used_amount), reserved_gas) {
+                Some(error) => {
+                    log!("batch_approve: {} skipped, {}.", spender, error);
+                    results.push(BatchApproveResult { spender, grant_id: None, error: Some(error) });
+                }
+                None => {
+                    let grant_id = self.create_grant(
+                        &owner,
+                        campaign_id,
+                        item.spender,
+                        item.main_token_amount,
+                        item.ft_amount,
+                        item.token_metadata,
+                        expires_at,
+                        None,
+                        None,
+                        None,
+                    );
+                    used_amount = used_amount.saturating_add(item.main_token_amount);
+                    reserved_gas = reserved_gas.saturating_add(GAS_PER_BATCH_GRANT).saturating_add(GAS_FOR_NFT_MINT_CALLBACK);
+                    results.push(BatchApproveResult { spender, grant_id: Some(grant_id), error: None });
+                }
+            }
+        }
+
+        let refund = attached_amount.saturating_sub(used_amount);
+        if refund > NearToken::from_near(0) {
+            log!("Refund unused deposit: {:?}", refund);
+            let _refund_promise = Promise::new(owner).transfer(refund);
+        }
+
+        results
+    }
+}
+
+impl L2eTop {
+    /// Why a batch_approve item can't be granted with the deposit and gas left, None if it can.
+    fn batch_item_error(
+        &self,
+        campaign_id: CampaignId,
+        item: &BatchApproveItem,
+        deposit_left: NearToken,
+        reserved_gas: Gas,
+    ) -> Option<String> {
+        if item.main_token_amount == NearToken::from_near(0) {
+            return Some("main_token_amount should be greater than 0".to_string());
+        }
+        if item.main_token_amount > deposit_left {
+            return Some("attached deposit does not cover main_token_amount".to_string());
+        }
+        let has_budget = self
+            .campaigns
+            .get(&campaign_id)
+            .is_some_and(|x| x.has_budget_for(item.main_token_amount, item.ft_amount));
+        if !has_budget {
+            return Some("campaign budget exceeded".to_string());
+        }
+        let gas_left = env::prepaid_gas().saturating_sub(env::used_gas());
+        let gas_needed = reserved_gas
+            .saturating_add(GAS_PER_BATCH_GRANT)
+            .saturating_add(GAS_FOR_NFT_MINT_CALLBACK)
+            .saturating_add(GAS_FOR_BATCH_GRANT_CALL);
+        if gas_left < gas_needed {
+            return Some("not enough gas left in this batch".to_string());
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::{GrantStatus, DEFAULT_CAMPAIGN_ID};
+    use near_sdk::testing_env;
+
+    fn batch_item(spender: &str, main_token_near: u128) -> BatchApproveItem {
+        BatchApproveItem {
+            spender: spender.parse().unwrap(),
+            main_token_amount: NearToken::from_near(main_token_near),
+            ft_amount: U128::from(10),
+            token_metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_batch_approve_reports_each_item() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        testing_env!(context(&owner).attached_deposit(NearToken::from_near(3)).build());
+        let results = contract.batch_approve(
+            DEFAULT_CAMPAIGN_ID,
+            vec![batch_item("alice.near", 1), batch_item("carol.near", 0), batch_item("dave.near", 1), batch_item("erin.near", 2)],
+            None,
+        );
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].grant_id, Some(1));
+        assert_eq!(results[1].error, Some("main_token_amount should be greater than 0".to_string()));
+        assert_eq!(results[2].grant_id, Some(2));
+        assert_eq!(results[3].spender, "erin.near".parse::<AccountId>().unwrap());
+        assert_eq!(results[3].error, Some("attached deposit does not cover main_token_amount".to_string()));
+
+        // every grant has its own nft mint in flight
+        for grant_id in [1, 2] {
+            assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::PendingMint));
+            assert!(contract.pending_mints.contains(&contract.nft_entry(grant_id).unwrap().token_id));
+        }
+        assert!(contract.get_grant(3).is_none());
+    }
+
+    #[test]
+    fn test_batch_approve_stops_at_gas_budget() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        testing_env!(context(&owner)
+            .attached_deposit(NearToken::from_near(2))
+            .prepaid_gas(near_sdk::Gas::from_tgas(60))
+            .build());
+        let results = contract.batch_approve(DEFAULT_CAMPAIGN_ID, vec![batch_item("alice.near", 1), batch_item("carol.near", 1)], None);
+
+        assert_eq!(results[0].grant_id, Some(1));
+        assert_eq!(results[1].grant_id, None);
+        assert_eq!(results[1].error, Some("not enough gas left in this batch".to_string()));
+    }
+}
@@ -0,0 +1,397 @@
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_sdk::json_types::U128;
+use near_sdk::{env, log, near, require, AccountId, NearToken, PublicKey};
+
+use crate::{CampaignLog, EventLog, EventLogVariant, L2eTop, L2eTopExt, CONSTRACT_NAME, CONSTRACT_VERSION};
+
+pub type CampaignId = u64;
+
+/// Campaign created by init and migrate, it holds the grants made before campaigns existed.
+/// Every owner can grant in it, it uses the first registered ft and nft contracts.
+pub const DEFAULT_CAMPAIGN_ID: CampaignId = 0;
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CampaignStatus {
+    // open for new grants
+    Active,
+    // no new grants until it is active again, open grants can still be claimed
+    Paused,
+    // no new grants anymore, open grants can still be claimed
+    Closed,
+}
+
+/// A course or program of an owner, every grant belongs to one campaign.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Campaign {
+    pub campaign_id: CampaignId,
+    pub title: String,
+    pub metadata_uri: Option<String>,
+    pub owner: AccountId,
+    // reward contracts of every grant in the campaign
+    pub erc20_address: AccountId,
+    pub erc721_address: AccountId,
+    // limits of the main token and ft granted in the campaign, None is no limit
+    pub main_token_budget: Option<NearToken>,
+    pub ft_budget: Option<U128>,
+    // main token and ft pledged by the grants of the campaign, revoked and expired grants are given back
+    pub main_token_granted: NearToken,
+    pub ft_granted: U128,
+    pub status: CampaignStatus,
+    // ed25519 keys of the verifiers whose attestations authorize claims, claims need no attestation while it is empty
+    pub verifier_keys: Vec<PublicKey>,
+    // nft metadata of grants approved without token_metadata and of attendance claims
+    pub nft_template: Option<TokenMetadata>,
+    // contract implementing ext_verifier whose verify result decides nft and stage claims, None for no check
+    pub verifier_contract: Option<AccountId>,
+}
+
+impl Campaign {
+    /// Whether a grant of main_token_amount and ft_amount still fits in the budget.
+    pub fn has_budget_for(&self, main_token_amount: NearToken, ft_amount: U128) -> bool {
+        let main_token_over = self
+            .main_token_budget
+            .is_some_and(|budget| self.main_token_granted.saturating_add(main_token_amount) > budget);
+        let ft_over = self
+            .ft_budget
+            .is_some_and(|budget| self.ft_granted.0.saturating_add(ft_amount.0) > budget.0);
+        !main_token_over && !ft_over
+    }
+}
+
+#[near]
+impl L2eTop {
+    /// Create a campaign owned by the caller, an admin or auth token owner.
+    /// erc20_address and erc721_address default to the first registered contracts.
+    pub fn create_campaign(
+        &mut self,
+        title: String,
+        metadata_uri: Option<String>,
+        erc20_address: Option<AccountId>,
+        erc721_address: Option<AccountId>,
+        main_token_budget: Option<NearToken>,
+        ft_budget: Option<U128>,
+    ) -> CampaignId {
+        let owner = env::predecessor_account_id();
+        require!(
+            self.admin_address.contains(&owner) || self.auth_token_owner.contains(&owner),
+            "Only an admin or auth token owner can create a campaign"
+        );
+
+        let erc20_address = erc20_address
+            .unwrap_or_else(|| self.erc20_address.get(0).expect("No erc20 address found").clone());
+        require!(
+            self.erc20_address.iter().any(|x| x == &erc20_address),
+            "erc20_address is not registered"
+        );
+        let erc721_address = erc721_address
+            .unwrap_or_else(|| self.erc721_address.get(0).expect("No erc721 address found").clone());
+        require!(
+            self.erc721_address.iter().any(|x| x == &erc721_address),
+            "erc721_address is not registered"
+        );
+
+        let campaign_id = self.next_campaign_id;
+        self.next_campaign_id += 1;
+        if !self.nfts.contains_key(&owner) {
+            self.nfts.insert(owner.clone(), Self::new_owner_nfts(&owner));
+        }
+        self.campaigns.insert(
+            campaign_id,
+            Campaign {
+                campaign_id,
+                title: title.clone(),
+                metadata_uri,
+                owner: owner.clone(),
+                erc20_address,
+                erc721_address,
+                main_token_budget,
+                ft_budget,
+                main_token_granted: NearToken::from_near(0),
+                ft_granted: U128::from(0),
+                status: CampaignStatus::Active,
+                verifier_keys: vec![],
+                nft_template: None,
+                verifier_contract: None,
+            },
+        );
+
+        let create_campaign_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: EventLogVariant::CreateCampaign(vec![CampaignLog {
+                campaign_id,
+                owner_id: owner.to_string(),
+                title,
+                status: format!("{:?}", CampaignStatus::Active),
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+
+        env::log_str(&create_campaign_log.to_string());
+
+        campaign_id
+    }
+
+    /// Pause, close or reopen a campaign, called by its owner or an admin.
+    pub fn set_campaign_status(&mut self, campaign_id: CampaignId, status: CampaignStatus) -> bool {
+        let caller = env::predecessor_account_id();
+        let is_admin = self.admin_address.contains(&caller);
+        let campaign = self.campaigns.get_mut(&campaign_id).expect("Campaign not found");
+        require!(
+            campaign.owner == caller || is_admin,
+            "Only the campaign owner or an admin can change its status"
+        );
+        campaign.status = status;
+        log!("Campaign {} status: {:?}", campaign_id, status);
+
+        let update_campaign_status_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: EventLogVariant::UpdateCampaignStatus(vec![CampaignLog {
+                campaign_id,
+                owner_id: campaign.owner.to_string(),
+                title: campaign.title.clone(),
+                status: format!("{:?}", status),
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+
+        env::log_str(&update_campaign_status_log.to_string());
+
+        true
+    }
+
+    /// Set the nft template of a campaign, called by its owner. None goes back to the default L2E.TOP metadata.
+    pub fn set_nft_template(&mut self, campaign_id: CampaignId, token_metadata: Option<TokenMetadata>) -> bool {
+        let campaign = self.campaigns.get_mut(&campaign_id).expect("Campaign not found");
+        require!(
+            campaign.owner == env::predecessor_account_id(),
+            "Only the campaign owner can set its nft template"
+        );
+        campaign.nft_template = token_metadata;
+        log!("Campaign {} nft template: {:?}", campaign_id, campaign.nft_template);
+
+        true
+    }
+
+    pub fn get_campaign(&self, campaign_id: CampaignId) -> Option<Campaign> {
+        self.campaigns.get(&campaign_id).cloned()
+    }
+
+    /// `limit` campaigns from `from_index`, default 0 and 50.
+    pub fn get_campaigns(&self, from_index: Option<u32>, limit: Option<u32>) -> Vec<Campaign> {
+        self.campaigns
+            .values()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(50) as usize)
+            .cloned()
+            .collect()
+    }
+}
+
+impl L2eTop {
+    /// Campaign owner can grant in, only the default campaign is open to every owner.
+    pub(crate) fn campaign_for_grant(&self, campaign_id: CampaignId, owner: &AccountId) -> Campaign {
+        let campaign = self.campaigns.get(&campaign_id).expect("Campaign not found").clone();
+        require!(
+            campaign_id == DEFAULT_CAMPAIGN_ID || &campaign.owner == owner,
+            "Only the campaign owner can grant in this campaign"
+        );
+        require!(campaign.status == CampaignStatus::Active, "Campaign is not active");
+        campaign
+    }
+
+    /// Count a grant against the budget of its campaign.
+    pub(crate) fn charge_campaign(&mut self, campaign_id: CampaignId, main_token_amount: NearToken, ft_amount: u128) {
+        if let Some(campaign) = self.campaigns.get_mut(&campaign_id) {
+            campaign.main_token_granted = campaign.main_token_granted.saturating_add(main_token_amount);
+            campaign.ft_granted = U128::from(campaign.ft_granted.0.saturating_add(ft_amount));
+        }
+    }
+
+    /// Give the budget of a grant that was never paid back to its campaign.
+    pub(crate) fn release_campaign(&mut self, campaign_id: CampaignId, main_token_amount: NearToken, ft_amount: u128) {
+        if let Some(campaign) = self.campaigns.get_mut(&campaign_id) {
+            campaign.main_token_granted = campaign.main_token_granted.saturating_sub(main_token_amount);
+            campaign.ft_granted = U128::from(campaign.ft_granted.0.saturating_sub(ft_amount));
+        }
+    }
+
+    /// The default campaign for init and migrate.
+    pub(crate) fn default_campaign(owner: AccountId, erc20_address: AccountId, erc721_address: AccountId) -> Campaign {
+        Campaign {
+            campaign_id: DEFAULT_CAMPAIGN_ID,
+            title: "Default".to_string(),
+            metadata_uri: None,
+            owner,
+            erc20_address,
+            erc721_address,
+            main_token_budget: None,
+            ft_budget: None,
+            main_token_granted: NearToken::from_near(0),
+            ft_granted: U128::from(0),
+            status: CampaignStatus::Active,
+            verifier_keys: vec![],
+            nft_template: None,
+            verifier_contract: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_contract_standards::non_fungible_token::Token;
+    use near_sdk::{testing_env, PromiseOrValue};
+
+    #[test]
+    fn test_grant_bound_to_campaign_contracts() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let other_erc20: AccountId = "other_erc20.near".parse().unwrap();
+        let other_erc721: AccountId = "other_erc721.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init(erc20.clone(), "erc721.near".parse().unwrap());
+        assert!(contract.add_contract_address(other_erc20.clone(), other_erc721.clone()));
+        let campaign_id = contract.create_campaign(
+            "Rust 101".to_string(),
+            None,
+            Some(other_erc20.clone()),
+            Some(other_erc721.clone()),
+            None,
+            None,
+        );
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_near(1)).build());
+        let grant_id = contract
+            .approve_for_spender(campaign_id, spender.clone(), NearToken::from_near(1), U128::from(100), None, None, None, None, None)
+            .unwrap();
+
+        let balance_entry = contract.balance_entry(grant_id).unwrap();
+        assert_eq!(balance_entry.campaign_id, campaign_id);
+        assert_eq!(balance_entry.erc20_address, other_erc20);
+        let nft_entry = contract.nft_entry(grant_id).unwrap();
+        assert_eq!(nft_entry.erc721_address, other_erc721);
+
+        // the default ft contract can not fund a grant approved with another one
+        let token = Token {
+            token_id: nft_entry.token_id.clone(),
+            owner_id: env::current_account_id(),
+            metadata: None,
+            approved_account_ids: None,
+        };
+        contract.nft_mint_callback(grant_id, Ok(token));
+        testing_env!(context(&erc20).build());
+        let msg = format!(r#"{{"grant_id":{}}}"#, grant_id);
+        match contract.ft_on_transfer(owner.clone(), U128::from(100), msg.clone()) {
+            PromiseOrValue::Value(unused) => assert_eq!(unused, U128::from(100)),
+            PromiseOrValue::Promise(_) => panic!("ft_on_transfer should not return a promise"),
+        }
+        testing_env!(context(&other_erc20).build());
+        match contract.ft_on_transfer(owner.clone(), U128::from(100), msg) {
+            PromiseOrValue::Value(unused) => assert_eq!(unused, U128::from(0)),
+            PromiseOrValue::Promise(_) => panic!("ft_on_transfer should not return a promise"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "erc721_address is not registered")]
+    fn test_create_campaign_rejects_unregistered_contract() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        contract.create_campaign("Rust 101".to_string(), None, None, Some("fake_erc721.near".parse().unwrap()), None, None);
+    }
+
+    #[test]
+    fn test_grants_kept_apart_per_campaign() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let campaign_id = contract.create_campaign("Rust 101".to_string(), None, None, None, None, None);
+        let (default_grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_near(2)).build());
+        let grant_id = contract
+            .approve_for_spender(campaign_id, spender.clone(), NearToken::from_near(2), U128::from(0), None, None, None, None, None)
+            .unwrap();
+
+        testing_env!(context(&spender).build());
+        let rewards = contract.get_rewards_for_spender(spender.clone(), Some(campaign_id));
+        assert_eq!(rewards.len(), 1);
+        assert_eq!(rewards[0].grant_id, grant_id);
+        assert_eq!(rewards[0].main_token_amount, U128::from(NearToken::from_near(2).as_yoctonear()));
+        assert_eq!(contract.get_rewards_for_spender(spender.clone(), None).len(), 2);
+        assert_eq!(
+            contract.get_allowances_for_spender(owner.clone(), Some(default_grant_id)),
+            Some((U128::from(NearToken::from_near(1).as_yoctonear()), U128::from(0)))
+        );
+        assert_eq!(
+            contract.get_allowances_for_spender(owner.clone(), None),
+            Some((U128::from(NearToken::from_near(3).as_yoctonear()), U128::from(0)))
+        );
+        assert_eq!(
+            contract.get_campaign(campaign_id).unwrap().main_token_granted,
+            NearToken::from_near(2)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the campaign owner can grant in this campaign")]
+    fn test_approve_for_spender_rejects_campaign_of_other_owner() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let other_owner: AccountId = "alice.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let campaign_id = contract.create_campaign("Rust 101".to_string(), None, None, None, None, None);
+        assert!(contract.add_auth_token_owner(other_owner.clone()));
+
+        testing_env!(context(&other_owner).attached_deposit(NearToken::from_near(1)).build());
+        contract.approve_for_spender(campaign_id, "spender.near".parse().unwrap(), NearToken::from_near(1), U128::from(0), None, None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Campaign budget exceeded")]
+    fn test_approve_for_spender_rejects_grant_over_budget() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let campaign_id = contract.create_campaign(
+            "Rust 101".to_string(),
+            Some("ipfs://rust101".to_string()),
+            None,
+            None,
+            Some(NearToken::from_near(1)),
+            None,
+        );
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_near(2)).build());
+        contract.approve_for_spender(campaign_id, "spender.near".parse().unwrap(), NearToken::from_near(2), U128::from(0), None, None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Campaign is not active")]
+    fn test_approve_for_spender_rejects_paused_campaign() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let campaign_id = contract.create_campaign("Rust 101".to_string(), None, None, None, None, None);
+        assert!(contract.set_campaign_status(campaign_id, CampaignStatus::Paused));
+        assert_eq!(contract.get_campaigns(None, None).len(), 2);
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_near(1)).build());
+        contract.approve_for_spender(campaign_id, "spender.near".parse().unwrap(), NearToken::from_near(1), U128::from(0), None, None, None, None, None);
+    }
+}
@@ -0,0 +1,285 @@
+use near_sdk::json_types::U128;
+use near_sdk::{env, log, near, AccountId, NearToken, Promise};
+
+use crate::{BalanceEntry, GrantId, GrantStatus, L2eTop, L2eTopExt, NftEntry};
+
+/// Ft of one contract paid by claim_all.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FtPayout {
+    pub ft_contract: AccountId,
+    // raw units of ft_contract
+    pub ft_amount: U128,
+}
+
+/// What claim_all started for the caller.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClaimAllSummary {
+    // grants whose nft claim started, their rewards are paid by the next claim_all
+    pub nft_claims: Vec<GrantId>,
+    // grants being paid, each is settled like transfer_balances_from by claim_all_callback
+    pub paid_grants: Vec<GrantId>,
+    // yoctoNEAR, paid in one transfer
+    pub main_token_amount: U128,
+    // one ft_transfer per ft contract
+    pub ft_payouts: Vec<FtPayout>,
+}
+
+#[near]
+impl L2eTop {
+    /// Claim the grants of the caller, `limit` grants at a time, default 10.
+    /// A grant whose nft is not claimed yet gets its nft transferred, its rewards are paid by the next claim_all.
+    /// The grants whose nft is claimed are paid together, with one main token transfer and one ft_transfer per ft contract.
+    /// Grants that are expired, paid by stage or vesting, or with a mint, nft claim or payout in flight are left out.
+    /// The nfts and rewards go to receiver_id if given, like for transfer_nft_from and transfer_balances_from.
+    /// The nft of a grant in a campaign with verifier keys is claimed with its attestation by transfer_nft_from, claim_all only pays it.
+    pub fn claim_all(&mut self, limit: Option<u32>, receiver_id: Option<AccountId>) -> ClaimAllSummary {
+        let spender = env::predecessor_account_id();
+        let receiver_id = Self::claim_receiver(&spender, receiver_id);
+        let now = env::block_timestamp();
+        let grant_ids: Vec<GrantId> = self
+            .balances
+            .get(&spender)
+            .map(|balances| {
+                balances
+                    .iter()
+                    .filter(|x| !x.is_expired(now) && x.stages.is_empty() && x.vesting.is_none())
+                    .map(|x| x.grant_id)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut nft_claims: Vec<GrantId> = vec![];
+        let mut paying: Vec<(BalanceEntry, NftEntry)> = vec![];
+        for grant_id in grant_ids {
+            if nft_claims.len() + paying.len() >= limit.unwrap_or(10) as usize {
+                break;
+            }
+            let Some(nft_entry) = self.nft_entry(grant_id) else {
+                continue;
+            };
+            match self.grant_record(grant_id).status {
+                GrantStatus::Minted => {
+                    let busy = self.pending_mints.contains(&nft_entry.token_id)
+                        || self.nft_claims_in_flight.contains(&nft_entry.token_id);
+                    // claims that need an attestation or a verifier proof are made one by one with transfer_nft_from
+                    let needs_proof = self.requires_attestation(nft_entry.campaign_id)
+                        || self.verifier_contract(nft_entry.campaign_id).is_some();
+                    if !busy && !nft_entry.claimed && !needs_proof {
+                        self.start_nft_claim(
+                            grant_id,
+                            &spender,
+                            receiver_id.clone(),
+                            &nft_entry.erc721_address,
+                            &nft_entry.token_id,
+                        );
+                        nft_claims.push(grant_id);
+                    }
+                }
+                GrantStatus::NftClaimed if nft_entry.claimed => {
+                    // removed now, restored by claim_all_callback if a leg fails
+                    self.take_nft_entry(grant_id);
+                    let balance_entry = self.take_balance_entry(grant_id).expect("No balance found for grant");
+                    self.set_grant_status(grant_id, GrantStatus::PaymentInFlight);
+                    paying.push((balance_entry, nft_entry));
+                }
+                _ => log!("claim_all: grant {} is skipped.", grant_id),
+            }
+        }
+
+        // sum the legs of all grants, per ft contract in the order they are met
+        let mut main_token_amount = NearToken::from_near(0);
+        let mut ft_payouts: Vec<FtPayout> = vec![];
+        for (balance_entry, _) in paying.iter() {
+            main_token_amount = main_token_amount.saturating_add(balance_entry.main_token_amount);
+            add_ft_payout(&mut ft_payouts, &balance_entry.erc20_address, balance_entry.ft_escrowed.0);
+            for entry in balance_entry.ft_basket.iter() {
+                add_ft_payout(&mut ft_payouts, &entry.ft_contract, entry.ft_escrowed.0);
+            }
+        }
+
+        let receiver = receiver_id.clone().unwrap_or_else(|| spender.clone());
+        let mut payout: Option<Promise> = None;
+        if main_token_amount > NearToken::from_near(0) {
+            payout = Some(Promise::new(receiver.clone()).transfer(main_token_amount));
+        }
+        for ft_payout in ft_payouts.iter() {
+            let ft_promise = Self::ft_transfer_to(
+                &ft_payout.ft_contract,
+                &receiver,
+                ft_payout.ft_amount,
+                None,
+                receiver_id.is_some(),
+            );
+            payout = Some(match payout {
+                Some(promise) => promise.and(ft_promise),
+                None => ft_promise,
+            });
+        }
+
+        let summary = ClaimAllSummary {
+            nft_claims,
+            paid_grants: paying.iter().map(|(x, _)| x.grant_id).collect(),
+            main_token_amount: U128::from(main_token_amount.as_yoctonear()),
+            ft_payouts: ft_payouts.clone(),
+        };
+        match payout {
+            Some(payout) => {
+                let _claim_all_promise = payout.then(Self::ext(env::current_account_id()).claim_all_callback(
+                    spender,
+                    paying,
+                    main_token_amount,
+                    ft_payouts,
+                    receiver_id,
+                ));
+            }
+            None => {
+                for (balance_entry, nft_entry) in paying {
+                    let basket_paid = vec![true; balance_entry.ft_basket.len()];
+                    self.settle_balances_payout(
+                        &spender,
+                        receiver_id.as_ref(),
+                        balance_entry,
+                        nft_entry,
+                        true,
+                        true,
+                        basket_paid,
+                    );
+                }
+            }
+        }
+
+        summary
+    }
+
+    /// Resolve the payout started by `claim_all`.
+    /// The promise results are in order: main token transfer, then an ft_transfer per entry of ft_payouts. A zero main token is not part of the results.
+    /// Each grant is settled with the legs of its tokens, the failed ones are written back so the spender can claim them again.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn claim_all_callback(
+        &mut self,
+        spender: AccountId,
+        paying: Vec<(BalanceEntry, NftEntry)>,
+        main_token_amount: NearToken,
+        ft_payouts: Vec<FtPayout>,
+        receiver_id: Option<AccountId>,
+    ) -> bool {
+        let mut result_index = 0;
+        let mut main_token_paid = true;
+        if main_token_amount > NearToken::from_near(0) {
+            main_token_paid = Self::promise_succeeded(result_index);
+            result_index += 1;
+        }
+        let ft_paid: Vec<(AccountId, bool)> = ft_payouts
+            .into_iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let paid = Self::promise_succeeded(result_index + i as u64);
+                (x.ft_contract, paid)
+            })
+            .collect();
+        let contract_paid = |ft_contract: &AccountId| {
+            !ft_paid.iter().any(|(x, paid)| x == ft_contract && !paid)
+        };
+
+        // a zero leg of a grant counts as paid, whatever happened to the transfer of its token
+        let mut all_paid = true;
+        for (balance_entry, nft_entry) in paying {
+            let grant_main_token_paid = main_token_paid || balance_entry.main_token_amount == NearToken::from_near(0);
+            let erc20_paid = balance_entry.ft_escrowed.0 == 0 || contract_paid(&balance_entry.erc20_address);
+            let basket_paid = balance_entry
+                .ft_basket
+                .iter()
+                .map(|x| !x.is_funded() || contract_paid(&x.ft_contract))
+                .collect();
+            all_paid &= self.settle_balances_payout(
+                &spender,
+                receiver_id.as_ref(),
+                balance_entry,
+                nft_entry,
+                grant_main_token_paid,
+                erc20_paid,
+                basket_paid,
+            );
+        }
+
+        all_paid
+    }
+}
+
+/// Add `amount` of ft_contract to the payout of its contract, zero amounts are left out.
+fn add_ft_payout(ft_payouts: &mut Vec<FtPayout>, ft_contract: &AccountId, amount: u128) {
+    if amount == 0 {
+        return;
+    }
+    match ft_payouts.iter_mut().find(|x| &x.ft_contract == ft_contract) {
+        Some(ft_payout) => ft_payout.ft_amount = U128::from(ft_payout.ft_amount.0 + amount),
+        None => ft_payouts.push(FtPayout { ft_contract: ft_contract.clone(), ft_amount: U128::from(amount) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::{testing_env, PromiseResult};
+
+    #[test]
+    fn test_claim_all_pays_grants_of_all_owners_together() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&bob).build());
+        let mut contract = L2eTop::init(erc20.clone(), "erc721.near".parse().unwrap());
+        assert!(contract.add_auth_token_owner(carol.clone()));
+        let (bob_grant, _) = approve_and_mint(&mut contract, &bob, &spender, NearToken::from_near(1), U128::from(100));
+        let (carol_grant, _) = approve_and_mint(&mut contract, &carol, &spender, NearToken::from_near(2), U128::from(50));
+        testing_env!(context(&erc20).build());
+        let _ = contract.ft_on_transfer(bob.clone(), U128::from(100), format!(r#"{{"grant_id":{}}}"#, bob_grant));
+        let _ = contract.ft_on_transfer(carol.clone(), U128::from(50), format!(r#"{{"grant_id":{}}}"#, carol_grant));
+
+        // the first call claims the nfts
+        testing_env!(context(&spender).build());
+        let summary = contract.claim_all(None, None);
+        assert_eq!(summary.nft_claims, vec![bob_grant, carol_grant]);
+        assert!(summary.paid_grants.is_empty());
+        assert!(contract.nft_transfer_callback(bob_grant, None, Ok(())));
+        assert!(contract.nft_transfer_callback(carol_grant, None, Ok(())));
+
+        // the second one pays both grants with one transfer per token
+        let paying: Vec<(BalanceEntry, NftEntry)> = [bob_grant, carol_grant]
+            .iter()
+            .map(|grant_id| (contract.balance_entry(*grant_id).unwrap(), contract.nft_entry(*grant_id).unwrap()))
+            .collect();
+        let summary = contract.claim_all(None, None);
+        assert_eq!(summary.paid_grants, vec![bob_grant, carol_grant]);
+        assert_eq!(summary.main_token_amount, U128::from(NearToken::from_near(3).as_yoctonear()));
+        assert_eq!(summary.ft_payouts, vec![FtPayout { ft_contract: erc20.clone(), ft_amount: U128::from(150) }]);
+
+        // main token transfer succeeded, ft_transfer failed
+        callback_env(vec![PromiseResult::Successful(vec![]), PromiseResult::Failed]);
+        assert!(!contract.claim_all_callback(
+            spender.clone(),
+            paying,
+            NearToken::from_near(3),
+            summary.ft_payouts,
+            None,
+        ));
+        for (grant_id, ft_amount) in [(bob_grant, 100), (carol_grant, 50)] {
+            let balance_entry = contract.balance_entry(grant_id).unwrap();
+            assert_eq!(balance_entry.main_token_amount, NearToken::from_near(0));
+            assert_eq!(balance_entry.ft_escrowed, U128::from(ft_amount));
+            assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::NftClaimed));
+        }
+
+        // the retry only pays the ft
+        testing_env!(context(&spender).build());
+        let summary = contract.claim_all(None, None);
+        assert_eq!(summary.main_token_amount, U128::from(0));
+        assert_eq!(summary.ft_payouts, vec![FtPayout { ft_contract: erc20, ft_amount: U128::from(150) }]);
+    }
+}
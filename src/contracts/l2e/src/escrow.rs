@@ -0,0 +1,318 @@
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, log, near, AccountId, PromiseError};
+
+use crate::{L2eTop, L2eTopExt};
+
+/// Amounts of one asset that owners put into grants.
+/// The main token is in yoctoNEAR, ft is in raw units of its contract.
+/// `committed` is always `deposited - paid - refunded`, it is what the contract owes to open grants.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EscrowBalance {
+    // received for grants, main token by approve_for_spender, ft by ft_on_transfer
+    pub deposited: U128,
+    // held for grants that are not paid out yet
+    pub committed: U128,
+    // paid to spenders by transfer_balances_from
+    pub paid: U128,
+    // returned to the owner, e.g. when nft_mint failed
+    pub refunded: U128,
+}
+
+/// Escrow ledger of an owner, or of the whole contract in `escrow_totals`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EscrowLedger {
+    pub main_token: EscrowBalance,
+    // (erc20 address, balance), one per ft contract used by the owner
+    pub ft: Vec<(AccountId, EscrowBalance)>,
+}
+
+/// Asset of an escrow ledger entry.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EscrowAsset {
+    MainToken,
+    Ft(AccountId),
+}
+
+/// Ft balance of the l2e contract, read with `sync_ft_balances`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FtHolding {
+    pub balance: U128,
+    // block timestamp in nanoseconds
+    pub synced_at: U64,
+}
+
+/// Main token liabilities against the balance of the l2e account.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct MainTokenReport {
+    pub liabilities: U128,
+    pub account_balance: U128,
+    // part of account_balance locked for contract storage
+    pub storage_locked: U128,
+    pub solvent: bool,
+}
+
+/// Liabilities of one ft contract against the last synced balance of the l2e account.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FtReport {
+    pub erc20_address: AccountId,
+    pub liabilities: U128,
+    // None until sync_ft_balances read it
+    pub holding: Option<FtHolding>,
+    pub solvent: Option<bool>,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct EscrowReport {
+    pub main_token: MainTokenReport,
+    pub ft: Vec<FtReport>,
+}
+
+impl EscrowBalance {
+    fn deposit(&mut self, amount: u128) {
+        self.deposited = U128::from(self.deposited.0.checked_add(amount).expect("Escrow deposited overflows"));
+        self.committed = U128::from(self.committed.0.checked_add(amount).expect("Escrow committed overflows"));
+    }
+
+    fn pay(&mut self, amount: u128) {
+        self.paid = U128::from(self.paid.0.checked_add(amount).expect("Escrow paid overflows"));
+        self.uncommit(amount);
+    }
+
+    fn refund(&mut self, amount: u128) {
+        self.refunded = U128::from(self.refunded.0.checked_add(amount).expect("Escrow refunded overflows"));
+        self.uncommit(amount);
+    }
+
+    /// Take amount off committed. Pay and refund run in callbacks, a ledger that would go below zero
+    /// is logged and set to 0 there instead of undoing the settled payout.
+    fn uncommit(&mut self, amount: u128) {
+        let committed = self.committed.0;
+        self.committed = U128::from(committed.checked_sub(amount).unwrap_or_else(|| {
+            log!("Escrow committed {} is below the {} paid or refunded, it is set to 0.", committed, amount);
+            0
+        }));
+    }
+}
+
+impl EscrowLedger {
+    fn balance_mut(&mut self, asset: &EscrowAsset) -> &mut EscrowBalance {
+        match asset {
+            EscrowAsset::MainToken => &mut self.main_token,
+            EscrowAsset::Ft(erc20_address) => {
+                let index = match self.ft.iter().position(|(x, _)| x == erc20_address) {
+                    Some(index) => index,
+                    None => {
+                        self.ft.push((erc20_address.clone(), EscrowBalance::default()));
+                        self.ft.len() - 1
+                    }
+                };
+                &mut self.ft[index].1
+            }
+        }
+    }
+
+    /// Ft liabilities of erc20_address, 0 if it was never used.
+    pub fn ft_committed(&self, erc20_address: &AccountId) -> u128 {
+        self.ft
+            .iter()
+            .find(|(x, _)| x == erc20_address)
+            .map_or(0, |(_, balance)| balance.committed.0)
+    }
+}
+
+#[near]
+impl L2eTop {
+    /// Escrow ledger of an owner, empty if the owner never funded a grant.
+    pub fn get_escrow_ledger(&self, owner: AccountId) -> EscrowLedger {
+        self.escrow_ledgers.get(&owner).cloned().unwrap_or_default()
+    }
+
+    /// Compare what the contract owes to open grants and quiz pools with what it holds.
+    /// Ft balances can't be read in a view, they are the ones cached by `sync_ft_balances`.
+    pub fn escrow_report(&self) -> EscrowReport {
+        let liabilities = self
+            .escrow_totals
+            .main_token
+            .committed
+            .0
+            .saturating_add(self.quiz_pool_total.as_yoctonear());
+        let account_balance = env::account_balance().as_yoctonear();
+        let storage_locked = env::storage_byte_cost().as_yoctonear() * env::storage_usage() as u128;
+        let main_token = MainTokenReport {
+            liabilities: U128::from(liabilities),
+            account_balance: U128::from(account_balance),
+            storage_locked: U128::from(storage_locked),
+            solvent: account_balance.saturating_sub(storage_locked) >= liabilities,
+        };
+
+        let ft = self
+            .erc20_address
+            .iter()
+            .map(|erc20_address| {
+                let liabilities = self.escrow_totals.ft_committed(erc20_address);
+                let holding = self.ft_holdings.get(erc20_address).cloned();
+                FtReport {
+                    erc20_address: erc20_address.clone(),
+                    liabilities: U128::from(liabilities),
+                    solvent: holding.as_ref().map(|x| x.balance.0 >= liabilities),
+                    holding,
+                }
+            })
+            .collect();
+
+        EscrowReport { main_token, ft }
+    }
+
+    /// Read the balance of the l2e contract on every registered ft contract for `escrow_report`.
+    pub fn sync_ft_balances(&mut self) -> bool {
+        for erc20_address in self.erc20_address.iter() {
+            let _ft_balance_promise = ext_ft_core::ext(erc20_address.clone())
+                .ft_balance_of(env::current_account_id())
+                .then(Self::ext(env::current_account_id()).ft_balance_of_callback(erc20_address.clone()));
+        }
+
+        true
+    }
+
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn ft_balance_of_callback(
+        &mut self,
+        erc20_address: AccountId,
+        #[callback_result] call_result: Result<U128, PromiseError>,
+    ) -> Option<U128> {
+        match call_result {
+            Ok(balance) => {
+                self.ft_holdings.insert(
+                    erc20_address,
+                    FtHolding {
+                        balance,
+                        synced_at: U64::from(env::block_timestamp()),
+                    },
+                );
+                Some(balance)
+            }
+            Err(e) => {
+                log!("There was an error contacting FT contract ft_balance_of: {:#?}", e);
+                None
+            }
+        }
+    }
+}
+
+impl L2eTop {
+    /// Record a deposit of owner for a grant, in the ledger of the owner and in `escrow_totals`.
+    pub(crate) fn escrow_deposit(&mut self, owner: &AccountId, asset: EscrowAsset, amount: u128) {
+        self.update_escrow(owner, &asset, |balance| balance.deposit(amount));
+    }
+
+    /// Record a payout of a grant of owner to its spender.
+    pub(crate) fn escrow_pay(&mut self, owner: &AccountId, asset: EscrowAsset, amount: u128) {
+        self.update_escrow(owner, &asset, |balance| balance.pay(amount));
+    }
+
+    /// Record a refund of a grant to owner.
+    pub(crate) fn escrow_refund(&mut self, owner: &AccountId, asset: EscrowAsset, amount: u128) {
+        self.update_escrow(owner, &asset, |balance| balance.refund(amount));
+    }
+
+    fn update_escrow(&mut self, owner: &AccountId, asset: &EscrowAsset, update: impl Fn(&mut EscrowBalance)) {
+        if !self.escrow_ledgers.contains_key(owner) {
+            self.escrow_ledgers.insert(owner.clone(), EscrowLedger::default());
+        }
+        let ledger = self.escrow_ledgers.get_mut(owner).expect("No escrow ledger found for owner");
+        update(ledger.balance_mut(asset));
+        update(self.escrow_totals.balance_mut(asset));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::DEFAULT_CAMPAIGN_ID;
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_contract_standards::non_fungible_token::Token;
+    use near_sdk::{testing_env, NearToken, PromiseResult};
+
+    #[test]
+    fn test_escrow_refund_above_committed_stops_at_zero() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        contract.escrow_deposit(&owner, EscrowAsset::MainToken, 5);
+        contract.escrow_refund(&owner, EscrowAsset::MainToken, 7);
+
+        let ledger = contract.get_escrow_ledger(owner);
+        assert_eq!(ledger.main_token.refunded, U128::from(7));
+        assert_eq!(ledger.main_token.committed, U128::from(0));
+        assert_eq!(contract.escrow_totals.main_token.committed, U128::from(0));
+    }
+
+    #[test]
+    fn test_escrow_ledger_tracks_grant_lifecycle() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+        let one_near = NearToken::from_near(1).as_yoctonear();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init(erc20.clone(), "erc721.near".parse().unwrap());
+
+        // 3 NEAR attached for a 1 NEAR grant, only 1 NEAR is kept
+        testing_env!(context(&owner).attached_deposit(NearToken::from_near(3)).build());
+        let grant_id = contract
+            .approve_for_spender(DEFAULT_CAMPAIGN_ID, spender.clone(), NearToken::from_near(1), U128::from(100), None, None, None, None, None)
+            .unwrap();
+        let token_id = contract.nft_entry(grant_id).unwrap().token_id;
+        let token = Token { token_id, owner_id: env::current_account_id(), metadata: None, approved_account_ids: None };
+        contract.nft_mint_callback(grant_id, Ok(token));
+
+        let ledger = contract.get_escrow_ledger(owner.clone());
+        assert_eq!(ledger.main_token.deposited, U128::from(one_near));
+        assert_eq!(ledger.main_token.committed, U128::from(one_near));
+        assert!(ledger.ft.is_empty());
+
+        testing_env!(context(&erc20).build());
+        let msg = format!(r#"{{"grant_id":{}}}"#, grant_id);
+        let _ = contract.ft_on_transfer(owner.clone(), U128::from(150), msg);
+        let ledger = contract.get_escrow_ledger(owner.clone());
+        assert_eq!(ledger.ft, vec![(erc20.clone(), EscrowBalance {
+            deposited: U128::from(100),
+            committed: U128::from(100),
+            paid: U128::from(0),
+            refunded: U128::from(0),
+        })]);
+
+        let report = contract.escrow_report();
+        assert_eq!(report.main_token.liabilities, U128::from(one_near));
+        assert_eq!(report.ft[0].liabilities, U128::from(100));
+        assert_eq!(report.ft[0].solvent, None);
+        assert_eq!(contract.ft_balance_of_callback(erc20.clone(), Ok(U128::from(90))), Some(U128::from(90)));
+        assert_eq!(contract.escrow_report().ft[0].solvent, Some(false));
+
+        testing_env!(context(&spender).build());
+        contract.transfer_nft_from(grant_id, None, None, None);
+        contract.nft_transfer_callback(grant_id, None, Ok(()));
+        let balance_entry = contract.balance_entry(grant_id).unwrap();
+        let nft_entry = contract.nft_entry(grant_id).unwrap();
+        assert!(contract.transfer_balances_from(grant_id, None));
+
+        callback_env(vec![PromiseResult::Successful(vec![]), PromiseResult::Successful(vec![])]);
+        assert!(contract.transfer_balances_callback(spender, balance_entry, nft_entry, None));
+
+        let ledger = contract.get_escrow_ledger(owner);
+        assert_eq!(ledger.main_token.paid, U128::from(one_near));
+        assert_eq!(ledger.main_token.committed, U128::from(0));
+        assert_eq!(ledger.ft[0].1.paid, U128::from(100));
+        assert_eq!(ledger.ft[0].1.committed, U128::from(0));
+        assert_eq!(contract.escrow_report().main_token.liabilities, U128::from(0));
+    }
+}
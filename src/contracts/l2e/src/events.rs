@@ -1,5 +1,6 @@
 use std::fmt;
 
+use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
 
 /// Enum that represents the data type of the EventLog.
@@ -14,16 +15,88 @@ pub enum EventLogVariant {
     NFTAddress(Vec<String>),
     AdminAddress(Vec<String>),
     AuthOwnerAddress(Vec<String>),
-    AllSpenderClaimedForOwner(Vec<(String, String, bool)>),
-    AllOwnerRewardsForSpender(Vec<(String, u128, u128)>),
+    // (spenderid, nft tokenid, claimed, grant id)
+    AllSpenderClaimedForOwner(Vec<(String, String, bool, u64)>),
+    // (ownerid, maintoken yocto amount, ft raw amount, grant id)
+    AllOwnerRewardsForSpender(Vec<(String, U128, U128, u64)>),
 
     // 主网币allowances返回事件
     AllowancesForSpender,
 
     // spender转移nft给自己
     TransferNftFrom(Vec<NftTransferLog>),
+    // nft_transfer失败，nft保持未领取状态
+    TransferNftFromFailed(Vec<NftTransferLog>),
     // spender转移balances给自己
     TransferBalacnesFrom(Vec<BalacnesTransferLog>),
+    // 转移balances失败，未转出部分已恢复，spender可以重新领取
+    TransferBalancesFromFailed(Vec<BalacnesTransferLog>),
+    // owner通过ft_transfer_call为spender托管ft
+    FtOnTransfer(Vec<FtFundLog>),
+    // nft_mint失败，撤销approve并退还owner的押金
+    ApproveForSpenderFailed(Vec<GrantLog>),
+    // owner或admin撤销未领取的grant，资金和nft退还owner
+    RevokeGrant(Vec<GrantLog>),
+    // 撤销时退还失败，未退还部分已恢复，可以重新撤销
+    RevokeGrantFailed(Vec<GrantLog>),
+    // grant过期未领取，资金和nft退还owner
+    ReclaimExpired(Vec<GrantLog>),
+    // owner创建campaign
+    CreateCampaign(Vec<CampaignLog>),
+    // campaign状态变更
+    UpdateCampaignStatus(Vec<CampaignLog>),
+    // spender领取一个阶段的奖励
+    ClaimStage(Vec<StageLog>),
+    // 阶段奖励转移失败，未转出部分已恢复，spender可以重新领取该阶段
+    ClaimStageFailed(Vec<StageLog>),
+    // owner确认spender完成了该阶段
+    UnlockStage(Vec<StageLog>),
+    // spender提取已归属的奖励
+    WithdrawVested(Vec<BalacnesTransferLog>),
+    // 提取失败，未转出部分已恢复，spender可以重新提取
+    WithdrawVestedFailed(Vec<BalacnesTransferLog>),
+    // owner在nft领取前追加grant的奖励
+    TopUpGrant(Vec<GrantLog>),
+    // owner在nft领取前调低grant的奖励，差额退还owner
+    AmendGrant(Vec<GrantLog>),
+    // 调低时退还失败，未退还部分已加回grant，owner可以重新调低
+    AmendGrantFailed(Vec<GrantLog>),
+    // campaign添加verifier公钥，领取需要该verifier签名的证明
+    AddVerifierKey(Vec<VerifierKeyLog>),
+    // campaign移除verifier公钥
+    RemoveVerifierKey(Vec<VerifierKeyLog>),
+    // owner发布merkle drop并存入奖励
+    CreateMerkleDrop(Vec<MerkleDropLog>),
+    // merkle drop过期后关闭，未领取的奖励退还owner
+    CloseMerkleDrop(Vec<MerkleDropLog>),
+    // 关闭时退还失败，未退还部分已恢复到merkle drop，owner可以重新关闭
+    CloseMerkleDropFailed(Vec<MerkleDropLog>),
+    // spender凭merkle proof领取nft和奖励
+    ClaimWithProof(Vec<MerkleClaimLog>),
+    // merkle领取失败，nft未铸造时可以重新领取，未转出的奖励可以重新领取
+    ClaimWithProofFailed(Vec<MerkleClaimLog>),
+    // campaign owner设置quiz和奖励池
+    SetQuiz(Vec<QuizLog>),
+    // campaign owner移除quiz，奖励池退还owner
+    RemoveQuiz(Vec<QuizLog>),
+    // 奖励池退还失败，quiz已恢复，owner可以重新移除
+    RemoveQuizFailed(Vec<QuizLog>),
+    // 参与者公开quiz答案，通过后解锁stage或创建grant
+    RevealQuiz(Vec<QuizRevealLog>),
+    // campaign owner创建签到session，参与者凭签到码领取nft
+    CreateAttendanceSession(Vec<AttendanceSessionLog>),
+    // 签到session结束后关闭，未使用的铸造押金退还owner
+    CloseAttendanceSession(Vec<AttendanceSessionLog>),
+    // 关闭时退还失败，未退还部分已恢复到session，owner可以重新关闭
+    CloseAttendanceSessionFailed(Vec<AttendanceSessionLog>),
+    // 参与者凭签到码领取签到nft
+    ClaimAttendance(Vec<AttendanceClaimLog>),
+    // 签到nft铸造失败，参与者可以重新领取
+    ClaimAttendanceFailed(Vec<AttendanceClaimLog>),
+    // campaign设置或移除verifier合约，领取需要该合约verify通过
+    SetVerifierContract(Vec<VerifierContractLog>),
+    // verifier合约未通过领取，nft或stage可以凭新的proof重新领取
+    ClaimNotVerified(Vec<VerifierClaimLog>),
 
 }
 
@@ -62,6 +135,9 @@ impl fmt::Display for EventLog {
 /// * `old_owner_id`: "owner.near"
 /// * `new_owner_id`: "receiver.near"
 /// * `token_ids`: ["1", "12345abc"]
+/// * `spender_id`: spender of the grant, only set when it claimed to another new_owner_id
+/// * `grant_id`: id of the grant
+/// * `campaign_id`: campaign of the grant
 /// * `memo`: optional message
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(crate = "near_sdk::serde")]
@@ -73,6 +149,16 @@ pub struct NftTransferLog {
     pub new_owner_id: String,
     pub token_ids: Vec<String>,
 
+    // the entitled spender, left out when new_owner_id is the spender
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spender_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grant_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub campaign_id: Option<u64>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub memo: Option<String>,
 }
@@ -84,6 +170,10 @@ pub struct NftTransferLog {
 /// * `old_owner_id`: "owner.near"
 /// * `new_owner_id`: "receiver.near"
 /// * `token_ids`: ["1", "12345abc"]
+/// * `ft_contract_ids`: ["ft.near", "sponsor.near"]
+/// * `spender_id`: spender of the grant, only set when it claimed to another new_owner_id
+/// * `grant_id`: id of the grant
+/// * `campaign_id`: campaign of the grant
 /// * `memo`: optional message
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(crate = "near_sdk::serde")]
@@ -96,6 +186,357 @@ pub struct BalacnesTransferLog {
     pub main_token_amount: Vec<String>,
     pub ft_token_amount: Vec<String>,
 
+    // ft contract of each ft_token_amount, left out for a grant without an ft_basket
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ft_contract_ids: Vec<String>,
+
+    // the entitled spender, left out when new_owner_id is the spender
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spender_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grant_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub campaign_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An event log to capture ft escrowed for a grant through `ft_on_transfer`
+///
+/// Arguments
+/// * `ft_contract_id`: "ft.near"
+/// * `owner_id`: "owner.near"
+/// * `spender_id`: "spender.near"
+/// * `ft_token_amount`: amount credited to the grant
+/// * `unused_amount`: amount returned to the owner
+/// * `grant_id`: id of the grant
+/// * `campaign_id`: campaign of the grant
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtFundLog {
+    pub ft_contract_id: String,
+    pub owner_id: String,
+    pub spender_id: String,
+    pub ft_token_amount: String,
+    pub unused_amount: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grant_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub campaign_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An event log to capture a grant of owner for spender
+///
+/// Arguments
+/// * `owner_id`: "owner.near"
+/// * `spender_id`: "spender.near"
+/// * `token_ids`: ["10001"]
+/// * `main_token_amount`: main token amount of the grant
+/// * `ft_token_amount`: ft token amount of the grant
+/// * `grant_id`: id of the grant
+/// * `campaign_id`: campaign of the grant
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GrantLog {
+    pub owner_id: String,
+    pub spender_id: String,
+    pub token_ids: Vec<String>,
+    pub main_token_amount: String,
+    pub ft_token_amount: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grant_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub campaign_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An event log to capture a campaign
+///
+/// Arguments
+/// * `campaign_id`: 1
+/// * `owner_id`: "owner.near"
+/// * `title`: "Rust 101"
+/// * `status`: "Active"
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CampaignLog {
+    pub campaign_id: u64,
+    pub owner_id: String,
+    pub title: String,
+    pub status: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An event log to capture a verifier key of a campaign
+///
+/// Arguments
+/// * `campaign_id`: 1
+/// * `owner_id`: "owner.near", the campaign owner or admin who changed the key
+/// * `public_key`: "ed25519:..."
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VerifierKeyLog {
+    pub campaign_id: u64,
+    pub owner_id: String,
+    pub public_key: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An event log to capture the verifier contract of a campaign
+///
+/// Arguments
+/// * `campaign_id`: 1
+/// * `owner_id`: "owner.near", the campaign owner or admin who set the verifier
+/// * `verifier_id`: "verifier.near", none when the verifier was removed
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VerifierContractLog {
+    pub campaign_id: u64,
+    pub owner_id: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verifier_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An event log to capture a claim the verifier contract did not accept
+///
+/// Arguments
+/// * `campaign_id`: 1
+/// * `spender_id`: "spender.near"
+/// * `verifier_id`: "verifier.near"
+/// * `grant_id`: grant claimed
+/// * `stage`: stage claimed, none for the nft claim
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VerifierClaimLog {
+    pub campaign_id: u64,
+    pub spender_id: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verifier_id: Option<String>,
+
+    pub grant_id: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An event log to capture a merkle drop
+///
+/// Arguments
+/// * `drop_id`: 1
+/// * `campaign_id`: campaign of the drop
+/// * `owner_id`: "owner.near"
+/// * `root`: base58 merkle root
+/// * `main_token_amount`: main token deposited, or refunded when the drop is closed
+/// * `ft_token_amount`: ft pledged, or refunded when the drop is closed
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MerkleDropLog {
+    pub drop_id: u64,
+    pub campaign_id: u64,
+    pub owner_id: String,
+    pub root: String,
+    pub main_token_amount: String,
+    pub ft_token_amount: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An event log to capture the claim of a merkle drop leaf
+///
+/// Arguments
+/// * `drop_id`: 1
+/// * `index`: leaf of the spender
+/// * `spender_id`: "spender.near"
+/// * `receiver_id`: account paid instead of the spender, only set when the spender claimed to another account
+/// * `main_token_amount`: main token paid, or unpaid on failure
+/// * `ft_token_amount`: ft paid, or unpaid on failure
+/// * `campaign_id`: campaign of the drop
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MerkleClaimLog {
+    pub drop_id: u64,
+    pub index: u64,
+    pub spender_id: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receiver_id: Option<String>,
+
+    pub main_token_amount: String,
+    pub ft_token_amount: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub campaign_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An event log to capture the quiz of a campaign
+///
+/// Arguments
+/// * `campaign_id`: 1
+/// * `owner_id`: "owner.near"
+/// * `questions`: number of questions
+/// * `passing_score`: correct answers needed to pass
+/// * `main_token_amount`: main token pool of the rewards, or refunded when the quiz is removed
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct QuizLog {
+    pub campaign_id: u64,
+    pub owner_id: String,
+    pub questions: u32,
+    pub passing_score: u32,
+    pub main_token_amount: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An event log to capture the revealed answers of a quiz
+///
+/// Arguments
+/// * `campaign_id`: 1
+/// * `spender_id`: "spender.near"
+/// * `score`: correct answers
+/// * `passing_score`: correct answers needed to pass
+/// * `passed`: whether the spender passed
+/// * `grant_id`: grant unlocked or created by the pass
+/// * `stage`: stage unlocked by the pass, none when a grant was created
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct QuizRevealLog {
+    pub campaign_id: u64,
+    pub spender_id: String,
+    pub score: u32,
+    pub passing_score: u32,
+    pub passed: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grant_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An event log to capture an attendance session
+///
+/// Arguments
+/// * `session_id`: 1
+/// * `campaign_id`: campaign whose nft template is claimed
+/// * `owner_id`: "owner.near"
+/// * `starts_at`: block timestamp in nanoseconds the claim code is accepted from
+/// * `ends_at`: block timestamp in nanoseconds the claim code is accepted until
+/// * `max_claims`: number of attendees who can claim
+/// * `main_token_amount`: mint deposits escrowed on create, refunded on close
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AttendanceSessionLog {
+    pub session_id: u64,
+    pub campaign_id: u64,
+    pub owner_id: String,
+    pub starts_at: String,
+    pub ends_at: String,
+    pub max_claims: u32,
+    pub main_token_amount: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An event log to capture an attendance claim
+///
+/// Arguments
+/// * `session_id`: 1
+/// * `attendee_id`: "attendee.near"
+/// * `token_id`: nft minted to the attendee, none when the mint failed
+/// * `campaign_id`: campaign of the session
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AttendanceClaimLog {
+    pub session_id: u64,
+    pub attendee_id: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub campaign_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An event log to capture a stage of a grant
+///
+/// Arguments
+/// * `owner_id`: "owner.near"
+/// * `spender_id`: "spender.near"
+/// * `stage`: 0
+/// * `main_token_amount`: main token amount of the stage
+/// * `ft_token_amount`: ft token amount of the stage
+/// * `receiver_id`: account paid instead of the spender, only set when the spender claimed to another account
+/// * `grant_id`: id of the grant
+/// * `campaign_id`: campaign of the grant
+/// * `memo`: optional message
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StageLog {
+    pub owner_id: String,
+    pub spender_id: String,
+    pub stage: u32,
+    pub main_token_amount: String,
+    pub ft_token_amount: String,
+
+    // the account paid, left out when it is the spender
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receiver_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grant_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub campaign_id: Option<u64>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub memo: Option<String>,
 }
@@ -150,13 +591,13 @@ mod tests {
 
     #[test]
     fn nep_format_all_spender_claimed_for_owner() {
-        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"all_spender_claimed_for_owner","data":[["spender1.near","token1.near",true],["spender2.near","token2.near",false]]}"#;
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"all_spender_claimed_for_owner","data":[["spender1.near","token1.near",true,1],["spender2.near","token2.near",false,2]]}"#;
         let log = EventLog {
             standard: "l2e.top".to_string(),
             version: "1.0.0".to_string(),
             event: EventLogVariant::AllSpenderClaimedForOwner(vec![
-                ("spender1.near".parse().unwrap(), "token1.near".to_string(), true),
-                ("spender2.near".parse().unwrap(), "token2.near".to_string(), false),
+                ("spender1.near".parse().unwrap(), "token1.near".to_string(), true, 1),
+                ("spender2.near".parse().unwrap(), "token2.near".to_string(), false, 2),
             ]),
         };
         assert_eq!(expected, log.to_string());
@@ -164,12 +605,12 @@ mod tests {
 
     #[test]
     fn nep_format_all_owner_rewards_for_spender() {
-        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"all_owner_rewards_for_spender","data":[["owner1.near",1,100]]}"#;
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"all_owner_rewards_for_spender","data":[["owner1.near","1","100",1]]}"#;
         let log = EventLog {
             standard: "l2e.top".to_string(),
             version: "1.0.0".to_string(),
             event: EventLogVariant::AllOwnerRewardsForSpender(vec![
-                ("owner1.near".parse().unwrap(), 1, 100),
+                ("owner1.near".parse().unwrap(), U128::from(1), U128::from(100), 1),
             ]),
         };
         assert_eq!(expected, log.to_string());
@@ -197,6 +638,9 @@ mod tests {
                 old_owner_id: "user1.near".to_string(),
                 new_owner_id: "user2.near".to_string(),
                 token_ids: vec!["token".to_string()],
+                spender_id: None,
+                grant_id: None,
+                campaign_id: None,
                 memo: Some("L2E Team!".to_owned()),
             }]),
         };
@@ -215,9 +659,487 @@ mod tests {
                 new_owner_id: "user2.near".to_string(),
                 main_token_amount: vec!["near".to_string()],
                 ft_token_amount: vec!["token".to_string()],
+                ft_contract_ids: vec![],
+                spender_id: None,
+                grant_id: None,
+                campaign_id: None,
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_transfer_balances_with_basket() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"transfer_balacnes_from","data":[{"authorized_id":"market.near","old_owner_id":"user1.near","new_owner_id":"user2.near","main_token_amount":["1"],"ft_token_amount":["100","50"],"ft_contract_ids":["ft.near","sponsor.near"],"grant_id":7,"memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::TransferBalacnesFrom(vec![BalacnesTransferLog {
+                authorized_id: Some("market.near".to_string()),
+                old_owner_id: "user1.near".to_string(),
+                new_owner_id: "user2.near".to_string(),
+                main_token_amount: vec!["1".to_string()],
+                ft_token_amount: vec!["100".to_string(), "50".to_string()],
+                ft_contract_ids: vec!["ft.near".to_string(), "sponsor.near".to_string()],
+                spender_id: None,
+                grant_id: Some(7),
+                campaign_id: None,
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_ft_on_transfer() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"ft_on_transfer","data":[{"ft_contract_id":"ft.near","owner_id":"user1.near","spender_id":"user2.near","ft_token_amount":"100","unused_amount":"5","memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::FtOnTransfer(vec![FtFundLog {
+                ft_contract_id: "ft.near".to_string(),
+                owner_id: "user1.near".to_string(),
+                spender_id: "user2.near".to_string(),
+                ft_token_amount: "100".to_string(),
+                unused_amount: "5".to_string(),
+                grant_id: None,
+                campaign_id: None,
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_transfer_balances_failed() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"transfer_balances_from_failed","data":[{"authorized_id":"market.near","old_owner_id":"user1.near","new_owner_id":"user2.near","main_token_amount":["1"],"ft_token_amount":["0"],"memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::TransferBalancesFromFailed(vec![BalacnesTransferLog {
+                authorized_id: Some("market.near".to_string()),
+                old_owner_id: "user1.near".to_string(),
+                new_owner_id: "user2.near".to_string(),
+                main_token_amount: vec!["1".to_string()],
+                ft_token_amount: vec!["0".to_string()],
+                ft_contract_ids: vec![],
+                spender_id: None,
+                grant_id: None,
+                campaign_id: None,
                 memo: Some("L2E Team!".to_owned()),
             }]),
         };
         assert_eq!(expected, log.to_string());
     }
-} 
+
+    #[test]
+    fn nep_format_transfer_nft_failed() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"transfer_nft_from_failed","data":[{"authorized_id":"market.near","old_owner_id":"user1.near","new_owner_id":"user2.near","token_ids":["token"],"memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::TransferNftFromFailed(vec![NftTransferLog {
+                authorized_id: Some("market.near".to_string()),
+                old_owner_id: "user1.near".to_string(),
+                new_owner_id: "user2.near".to_string(),
+                token_ids: vec!["token".to_string()],
+                spender_id: None,
+                grant_id: None,
+                campaign_id: None,
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_approve_for_spender_failed() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"approve_for_spender_failed","data":[{"owner_id":"user1.near","spender_id":"user2.near","token_ids":["10001"],"main_token_amount":"2","ft_token_amount":"0","memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::ApproveForSpenderFailed(vec![GrantLog {
+                owner_id: "user1.near".to_string(),
+                spender_id: "user2.near".to_string(),
+                token_ids: vec!["10001".to_string()],
+                main_token_amount: "2".to_string(),
+                ft_token_amount: "0".to_string(),
+                grant_id: None,
+                campaign_id: None,
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_revoke_grant() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"revoke_grant","data":[{"owner_id":"user1.near","spender_id":"user2.near","token_ids":["10001"],"main_token_amount":"1","ft_token_amount":"100","memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::RevokeGrant(vec![GrantLog {
+                owner_id: "user1.near".to_string(),
+                spender_id: "user2.near".to_string(),
+                token_ids: vec!["10001".to_string()],
+                main_token_amount: "1".to_string(),
+                ft_token_amount: "100".to_string(),
+                grant_id: None,
+                campaign_id: None,
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_top_up_grant() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"top_up_grant","data":[{"owner_id":"user1.near","spender_id":"user2.near","token_ids":["10001"],"main_token_amount":"1","ft_token_amount":"0","grant_id":4,"campaign_id":2,"memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::TopUpGrant(vec![GrantLog {
+                owner_id: "user1.near".to_string(),
+                spender_id: "user2.near".to_string(),
+                token_ids: vec!["10001".to_string()],
+                main_token_amount: "1".to_string(),
+                ft_token_amount: "0".to_string(),
+                grant_id: Some(4),
+                campaign_id: Some(2),
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_amend_grant() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"amend_grant","data":[{"owner_id":"user1.near","spender_id":"user2.near","token_ids":["10001"],"main_token_amount":"0","ft_token_amount":"50","grant_id":4,"campaign_id":2,"memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::AmendGrant(vec![GrantLog {
+                owner_id: "user1.near".to_string(),
+                spender_id: "user2.near".to_string(),
+                token_ids: vec!["10001".to_string()],
+                main_token_amount: "0".to_string(),
+                ft_token_amount: "50".to_string(),
+                grant_id: Some(4),
+                campaign_id: Some(2),
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_amend_grant_failed() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"amend_grant_failed","data":[{"owner_id":"user1.near","spender_id":"user2.near","token_ids":["10001"],"main_token_amount":"0","ft_token_amount":"40","grant_id":4,"campaign_id":2,"memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::AmendGrantFailed(vec![GrantLog {
+                owner_id: "user1.near".to_string(),
+                spender_id: "user2.near".to_string(),
+                token_ids: vec!["10001".to_string()],
+                main_token_amount: "0".to_string(),
+                ft_token_amount: "40".to_string(),
+                grant_id: Some(4),
+                campaign_id: Some(2),
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_revoke_grant_failed() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"revoke_grant_failed","data":[{"owner_id":"user1.near","spender_id":"user2.near","token_ids":[],"main_token_amount":"0","ft_token_amount":"100","memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::RevokeGrantFailed(vec![GrantLog {
+                owner_id: "user1.near".to_string(),
+                spender_id: "user2.near".to_string(),
+                token_ids: vec![],
+                main_token_amount: "0".to_string(),
+                ft_token_amount: "100".to_string(),
+                grant_id: None,
+                campaign_id: None,
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_reclaim_expired() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"reclaim_expired","data":[{"owner_id":"user1.near","spender_id":"user2.near","token_ids":["10001"],"main_token_amount":"1","ft_token_amount":"0","memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::ReclaimExpired(vec![GrantLog {
+                owner_id: "user1.near".to_string(),
+                spender_id: "user2.near".to_string(),
+                token_ids: vec!["10001".to_string()],
+                main_token_amount: "1".to_string(),
+                ft_token_amount: "0".to_string(),
+                grant_id: None,
+                campaign_id: None,
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_grant_with_campaign() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"revoke_grant","data":[{"owner_id":"user1.near","spender_id":"user2.near","token_ids":["10001"],"main_token_amount":"1","ft_token_amount":"0","campaign_id":3,"memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::RevokeGrant(vec![GrantLog {
+                owner_id: "user1.near".to_string(),
+                spender_id: "user2.near".to_string(),
+                token_ids: vec!["10001".to_string()],
+                main_token_amount: "1".to_string(),
+                ft_token_amount: "0".to_string(),
+                grant_id: None,
+                campaign_id: Some(3),
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_create_campaign() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"create_campaign","data":[{"campaign_id":1,"owner_id":"user1.near","title":"Rust 101","status":"Active","memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::CreateCampaign(vec![CampaignLog {
+                campaign_id: 1,
+                owner_id: "user1.near".to_string(),
+                title: "Rust 101".to_string(),
+                status: "Active".to_string(),
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_add_verifier_key() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"add_verifier_key","data":[{"campaign_id":1,"owner_id":"user1.near","public_key":"ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp","memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::AddVerifierKey(vec![VerifierKeyLog {
+                campaign_id: 1,
+                owner_id: "user1.near".to_string(),
+                public_key: "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp".to_string(),
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_claim_with_proof() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"claim_with_proof","data":[{"drop_id":1,"index":5,"spender_id":"user2.near","main_token_amount":"1000","ft_token_amount":"100","campaign_id":3,"memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::ClaimWithProof(vec![MerkleClaimLog {
+                drop_id: 1,
+                index: 5,
+                spender_id: "user2.near".to_string(),
+                receiver_id: None,
+                main_token_amount: "1000".to_string(),
+                ft_token_amount: "100".to_string(),
+                campaign_id: Some(3),
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_reveal_quiz() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"reveal_quiz","data":[{"campaign_id":3,"spender_id":"user2.near","score":4,"passing_score":3,"passed":true,"grant_id":7,"stage":1,"memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::RevealQuiz(vec![QuizRevealLog {
+                campaign_id: 3,
+                spender_id: "user2.near".to_string(),
+                score: 4,
+                passing_score: 3,
+                passed: true,
+                grant_id: Some(7),
+                stage: Some(1),
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_close_attendance_session() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"close_attendance_session","data":[{"session_id":1,"campaign_id":3,"owner_id":"user1.near","starts_at":"1000","ends_at":"2000","max_claims":40,"main_token_amount":"780000000000000000000000","memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::CloseAttendanceSession(vec![AttendanceSessionLog {
+                session_id: 1,
+                campaign_id: 3,
+                owner_id: "user1.near".to_string(),
+                starts_at: "1000".to_string(),
+                ends_at: "2000".to_string(),
+                max_claims: 40,
+                main_token_amount: "780000000000000000000000".to_string(),
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_claim_attendance() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"claim_attendance","data":[{"session_id":1,"attendee_id":"user2.near","token_id":"12","campaign_id":3,"memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::ClaimAttendance(vec![AttendanceClaimLog {
+                session_id: 1,
+                attendee_id: "user2.near".to_string(),
+                token_id: Some("12".to_string()),
+                campaign_id: Some(3),
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_claim_not_verified() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"claim_not_verified","data":[{"campaign_id":3,"spender_id":"user2.near","verifier_id":"verifier.near","grant_id":7,"memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::ClaimNotVerified(vec![VerifierClaimLog {
+                campaign_id: 3,
+                spender_id: "user2.near".to_string(),
+                verifier_id: Some("verifier.near".to_string()),
+                grant_id: 7,
+                stage: None,
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_update_campaign_status() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"update_campaign_status","data":[{"campaign_id":1,"owner_id":"user1.near","title":"Rust 101","status":"Closed","memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::UpdateCampaignStatus(vec![CampaignLog {
+                campaign_id: 1,
+                owner_id: "user1.near".to_string(),
+                title: "Rust 101".to_string(),
+                status: "Closed".to_string(),
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_claim_stage() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"claim_stage","data":[{"owner_id":"user1.near","spender_id":"user2.near","stage":1,"main_token_amount":"1000","ft_token_amount":"100","grant_id":7,"campaign_id":3,"memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::ClaimStage(vec![StageLog {
+                owner_id: "user1.near".to_string(),
+                spender_id: "user2.near".to_string(),
+                stage: 1,
+                main_token_amount: "1000".to_string(),
+                ft_token_amount: "100".to_string(),
+                receiver_id: None,
+                grant_id: Some(7),
+                campaign_id: Some(3),
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_claim_stage_to_receiver() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"claim_stage","data":[{"owner_id":"user1.near","spender_id":"user2.near","stage":0,"main_token_amount":"1000","ft_token_amount":"100","receiver_id":"fund.near","grant_id":7}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::ClaimStage(vec![StageLog {
+                owner_id: "user1.near".to_string(),
+                spender_id: "user2.near".to_string(),
+                stage: 0,
+                main_token_amount: "1000".to_string(),
+                ft_token_amount: "100".to_string(),
+                receiver_id: Some("fund.near".to_string()),
+                grant_id: Some(7),
+                campaign_id: None,
+                memo: None,
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_unlock_stage() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"unlock_stage","data":[{"owner_id":"user1.near","spender_id":"user2.near","stage":0,"main_token_amount":"1000","ft_token_amount":"0"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::UnlockStage(vec![StageLog {
+                owner_id: "user1.near".to_string(),
+                spender_id: "user2.near".to_string(),
+                stage: 0,
+                main_token_amount: "1000".to_string(),
+                ft_token_amount: "0".to_string(),
+                receiver_id: None,
+                grant_id: None,
+                campaign_id: None,
+                memo: None,
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_withdraw_vested() {
+        let expected = r#"EVENT_JSON:{"standard":"l2e.top","version":"1.0.0","event":"withdraw_vested","data":[{"authorized_id":"user1.near","old_owner_id":"l2e.near","new_owner_id":"user2.near","main_token_amount":["250"],"ft_token_amount":["25"],"campaign_id":0,"memo":"L2E Team!"}]}"#;
+        let log = EventLog {
+            standard: "l2e.top".to_string(),
+            version: "1.0.0".to_string(),
+            event: EventLogVariant::WithdrawVested(vec![BalacnesTransferLog {
+                authorized_id: Some("user1.near".to_string()),
+                old_owner_id: "l2e.near".to_string(),
+                new_owner_id: "user2.near".to_string(),
+                main_token_amount: vec!["250".to_string()],
+                ft_token_amount: vec!["25".to_string()],
+                ft_contract_ids: vec![],
+                spender_id: None,
+                grant_id: None,
+                campaign_id: Some(0),
+                memo: Some("L2E Team!".to_owned()),
+            }]),
+        };
+        assert_eq!(expected, log.to_string());
+    }
+}
@@ -1,6 +1,9 @@
 use near_sdk::{ext_contract, AccountId, Promise};
 use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
 use near_contract_standards::non_fungible_token::TokenId;
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+
+use crate::CampaignId;
 
 // Validator interface, for cross-contract calls
 #[ext_contract(ext_nft_contract)]
@@ -8,8 +11,17 @@ trait ERC721Contract {
     fn nft_mint(&mut self, token_id: TokenId, token_owner_id: AccountId, token_metadata: TokenMetadata,) -> Promise;
 }
 
+// Verifier interface of a campaign, verify decides whether the claim of spender goes on
+// only the generated ext_verifier is called, the trait itself is never implemented here
+#[allow(dead_code)]
+#[ext_contract(ext_verifier)]
+trait VerifierContract {
+    fn verify(&self, campaign_id: CampaignId, spender: AccountId, proof: String) -> bool;
+}
+
 #[ext_contract(ext_ft_contract)]
 trait ERC20Contract {
     fn storage_balance_of(&self, account_id: AccountId) -> Promise;
     fn storage_deposit(&mut self, account_id: Option<AccountId>, registration_only: Option<bool>) -> Promise;
+    fn ft_metadata(&self) -> FungibleTokenMetadata;
 }
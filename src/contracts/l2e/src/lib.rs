@@ -1,28 +1,179 @@
 // Find all our documentation at https://docs.near.org
 
+mod amend;
+mod attendance;
+mod attestation;
+mod basket;
+mod batch;
+mod campaign;
+mod claim;
+mod escrow;
 mod events;
 mod external;
-
+mod lifecycle;
+mod merkle;
+mod quiz;
+mod stage;
+#[cfg(test)]
+mod test_utils;
+mod verifier;
+mod vesting;
+
+pub use attendance::*;
+pub use attestation::*;
+pub use basket::*;
+pub use batch::*;
+pub use campaign::*;
+pub use claim::*;
+pub use escrow::*;
 pub use events::*;
 pub use external::*;
+pub use lifecycle::*;
+pub use merkle::*;
+pub use quiz::*;
+pub use stage::*;
+pub use vesting::*;
 
-use near_sdk::json_types::U128;
-use near_sdk::store::IterableSet;
+use std::collections::{HashMap, HashSet};
+
+use near_sdk::json_types::{U128, U64};
+use near_sdk::store::{IterableSet, LookupMap, LookupSet};
 use near_sdk::{
     env, log, near, require,
     store::{IterableMap, Vector},
-    AccountId, NearToken, PanicOnDefault, Promise, PromiseError,
+    AccountId, BorshStorageKey, CryptoHash, Gas, NearToken, PanicOnDefault, Promise, PromiseError,
+    PromiseOrValue,
 };
 use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
 use near_contract_standards::non_fungible_token::{Token, TokenId};
 use near_contract_standards::storage_management::StorageBalance;
 use near_contract_standards::non_fungible_token::core::ext_nft_core;
-// use near_contract_standards::non_fungible_token::approval::ext_nft_approval;
 
 use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
 
 pub const CONSTRACT_NAME: &str = "L2eTop";
 pub const CONSTRACT_VERSION: &str = "1.0.0";
+/// Decimals of the main token, amounts of it are kept in yoctoNEAR.
+pub const NEAR_DECIMALS: u8 = 24;
+/// Gas reserved for nft_mint_callback, it resolves the mint of a grant and refunds the grant if the mint failed.
+pub const GAS_FOR_NFT_MINT_CALLBACK: Gas = Gas::from_tgas(10);
+/// Deposit attached to nft_mint for the storage of the new token on the nft contract.
+pub const NFT_MINT_DEPOSIT: NearToken = NearToken::from_millinear(20);
+/// Deposit attached to the storage_deposit that registers a receiver with an ft contract.
+pub const FT_STORAGE_DEPOSIT: NearToken = NearToken::from_millinear(20);
+
+/// Id of a grant, increasing from 1 for every approve_for_spender.
+pub type GrantId = u64;
+
+/// Storage prefixes of L2eTop.
+/// The per-account vectors of `balances` and `nfts` are prefixed with the sha256 of the account id,
+/// so the grant lists of different accounts never share storage.
+#[derive(BorshStorageKey)]
+#[near]
+enum StorageKey {
+    Balances,
+    BalancesPerSpender { spender_hash: CryptoHash },
+    Nfts,
+    NftsPerOwner { owner_hash: CryptoHash },
+    Erc20Address,
+    Erc721Address,
+    AdminAddress,
+    AuthTokenOwner,
+    NftClaimsInFlight,
+    PendingMints,
+    FtDecimals,
+    EscrowLedgers,
+    FtHoldings,
+    Campaigns,
+    Grants,
+    AttestationNonces,
+    MerkleDrops,
+    MerkleClaimed,
+    MerkleUnpaid,
+    Quizzes,
+    QuizCommits,
+    QuizPassed,
+    AttendanceSessions,
+    AttendanceClaims,
+    QuizAttempts,
+}
+
+/// Main token and ft of a grant, stored in `balances` of the spender.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BalanceEntry {
+    pub grant_id: GrantId,
+    pub campaign_id: CampaignId,
+    pub owner: AccountId,
+    pub main_token_amount: NearToken,
+    // ft pledged by approve_for_spender, in raw units of the ft contract
+    pub ft_pledged: U128,
+    // ft that arrived through ft_on_transfer, it is the amount paid out on claim
+    pub ft_escrowed: U128,
+    // ft contract chosen at approval time, the only one ft_on_transfer accepts and transfer_balances_from pays from
+    pub erc20_address: AccountId,
+    // block timestamp in nanoseconds after which the grant can't be claimed and reclaim_expired returns it to the owner
+    pub expires_at: Option<U64>,
+    // stages paid in order by claim_stage, empty for a grant paid at once by transfer_balances_from
+    pub stages: Vec<GrantStage>,
+    // paid over time by withdraw_vested, None for a grant paid at once by transfer_balances_from
+    pub vesting: Option<Vesting>,
+    // sponsor tokens paid by transfer_balances_from next to the ft of erc20_address
+    pub ft_basket: Vec<BasketEntry>,
+}
+
+impl BalanceEntry {
+    /// Whether the grant expired at block timestamp `now`.
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at.0 <= now)
+    }
+
+    /// Whether a claim_stage or withdraw_vested payout of the grant is in flight.
+    pub fn is_paying(&self) -> bool {
+        self.stages.iter().any(|x| x.status == StageStatus::Paying)
+            || self.vesting.as_ref().is_some_and(|x| x.withdrawing)
+    }
+}
+
+/// Where the entries of a grant are stored, in `grants`.
+/// The record is kept when the grant is paid or returned, its entries are gone then.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrantRecord {
+    pub grant_id: GrantId,
+    pub campaign_id: CampaignId,
+    pub owner: AccountId,
+    pub spender: AccountId,
+    pub status: GrantStatus,
+}
+
+/// A grant with its entries, None once the grant is paid or returned.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrantView {
+    pub grant_id: GrantId,
+    pub campaign_id: CampaignId,
+    pub owner: AccountId,
+    pub spender: AccountId,
+    pub status: GrantStatus,
+    pub balance: Option<BalanceEntry>,
+    pub nft: Option<NftEntry>,
+}
+
+/// Nft of a grant, stored in `nfts` of the owner.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct NftEntry {
+    pub grant_id: GrantId,
+    pub campaign_id: CampaignId,
+    pub spender: AccountId,
+    pub token_id: TokenId,
+    pub claimed: bool,
+    // nft contract chosen at approval time, the nft is minted and transferred on it
+    pub erc721_address: AccountId,
+}
 
 // Define the contract structure
 #[near(contract_state)]
@@ -30,16 +181,77 @@ pub const CONSTRACT_VERSION: &str = "1.0.0";
 pub struct L2eTop {
     greeting: String,
 
-    // spenderid -> <(ownerid, maintoken balance, token balance)> total balance can be mutli stage claim.
-    balances: IterableMap<AccountId, Vector<(AccountId, NearToken, NearToken)>>,
-    // ownerid -> <(spenderid, nft tokenid, claimed true/false)>
-    nfts: IterableMap<AccountId, Vector<(AccountId, TokenId, bool)>>,
+    // spenderid -> <BalanceEntry of ownerid> total balance can be mutli stage claim.
+    balances: IterableMap<AccountId, Vector<BalanceEntry>>,
+    // ownerid -> <NftEntry of spenderid, claimed true/false>
+    nfts: IterableMap<AccountId, Vector<NftEntry>>,
     erc20_address: Vector<AccountId>,
     erc721_address: Vector<AccountId>,
     // nft token id num
     token_id_num: U128,
     admin_address: IterableSet<AccountId>,
     auth_token_owner: IterableSet<AccountId>,
+    // nft tokenids with a pending nft_transfer, locked until nft_transfer_callback
+    nft_claims_in_flight: LookupSet<TokenId>,
+    // nft tokenids approved for spender but not minted yet, confirmed by nft_mint_callback
+    pending_mints: LookupSet<TokenId>,
+    // erc20 address -> decimals from ft_metadata, cached when the ft contract is registered
+    ft_decimals: LookupMap<AccountId, u8>,
+    // ownerid -> main token and ft the owner deposited, committed, got paid out or refunded for grants
+    escrow_ledgers: LookupMap<AccountId, EscrowLedger>,
+    // sum of all escrow_ledgers, the liabilities checked by escrow_report
+    escrow_totals: EscrowLedger,
+    // erc20 address -> ft balance of the l2e contract, cached by sync_ft_balances
+    ft_holdings: LookupMap<AccountId, FtHolding>,
+    // campaignid -> Campaign, every grant belongs to one
+    campaigns: IterableMap<CampaignId, Campaign>,
+    next_campaign_id: CampaignId,
+    // grantid -> GrantRecord, to find the entries of a grant in `balances` and `nfts`
+    grants: LookupMap<GrantId, GrantRecord>,
+    next_grant_id: GrantId,
+    // (campaignid, nonce) of the verifier attestations already used for a claim
+    attestation_nonces: LookupSet<(CampaignId, u64)>,
+    // dropid -> MerkleDrop, its leaves are claimed with claim_with_proof
+    merkle_drops: LookupMap<MerkleDropId, MerkleDrop>,
+    next_merkle_drop_id: MerkleDropId,
+    // (dropid, leaf index / 128) -> bitmap of the claimed leaves
+    merkle_claimed: LookupMap<(MerkleDropId, u64), u128>,
+    // (dropid, leaf index) -> main token and ft of a claimed leaf whose payout failed
+    merkle_unpaid: LookupMap<(MerkleDropId, u64), (NearToken, U128)>,
+    // campaignid -> Quiz, passing it creates or unlocks a grant
+    quizzes: LookupMap<CampaignId, Quiz>,
+    // (campaignid, participant) -> answers committed and not revealed yet
+    quiz_commits: LookupMap<(CampaignId, AccountId), QuizCommit>,
+    // (campaignid, participant) of the passed quizzes
+    quiz_passed: LookupSet<(CampaignId, AccountId)>,
+    // (campaignid, participant) -> failed reveals, up to QUIZ_MAX_ATTEMPTS
+    quiz_attempts: LookupMap<(CampaignId, AccountId), u32>,
+    // main token of all quiz pools, owed to the campaign owners until it is granted or refunded
+    quiz_pool_total: NearToken,
+    // sessionid -> AttendanceSession, its nft is claimed with the code of the session
+    attendance_sessions: LookupMap<AttendanceSessionId, AttendanceSession>,
+    next_attendance_session_id: AttendanceSessionId,
+    // (sessionid, attendee) of the claimed attendances
+    attendance_claims: LookupSet<(AttendanceSessionId, AccountId)>,
+}
+
+/// Reward of a grant for the spender, with raw amounts and their human-readable value.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RewardView {
+    pub grant_id: GrantId,
+    pub campaign_id: CampaignId,
+    pub owner: AccountId,
+    // yoctoNEAR
+    pub main_token_amount: U128,
+    pub main_token_amount_display: String,
+    pub erc20_address: AccountId,
+    // raw units of erc20_address
+    pub ft_amount: U128,
+    // None until the decimals of erc20_address are known
+    pub ft_decimals: Option<u8>,
+    pub ft_amount_display: Option<String>,
+    pub ft_basket: Vec<BasketRewardView>,
 }
 
 // Implement the contract structure
@@ -48,29 +260,63 @@ impl L2eTop {
     #[init]
     #[private] // only callable by the contract's account
     pub fn init(erc20: AccountId, erc721: AccountId) -> Self {
-        let mut default_bal_map =
-            IterableMap::<AccountId, Vector<(AccountId, NearToken, NearToken)>>::new(b"b");
-        let mut empty_bal_vec: Vector<(AccountId, NearToken, NearToken)> = Vector::new(b"v");
-        empty_bal_vec.push((env::predecessor_account_id(), NearToken::from_near(0), NearToken::from_near(0)));
+        let mut default_bal_map = IterableMap::<AccountId, Vector<BalanceEntry>>::new(StorageKey::Balances);
+        let mut empty_bal_vec = Self::new_spender_balances(&env::predecessor_account_id());
+        empty_bal_vec.push(BalanceEntry {
+            grant_id: 0,
+            campaign_id: DEFAULT_CAMPAIGN_ID,
+            owner: env::predecessor_account_id(),
+            main_token_amount: NearToken::from_near(0),
+            ft_pledged: U128::from(0),
+            ft_escrowed: U128::from(0),
+            erc20_address: erc20.clone(),
+            expires_at: None,
+            stages: vec![],
+            vesting: None,
+            ft_basket: vec![],
+        });
         default_bal_map.insert(env::predecessor_account_id(), empty_bal_vec);
         
-        let mut default_nft_map =
-            IterableMap::<AccountId, Vector<(AccountId, TokenId, bool)>>::new(b"n");
-        let mut empty_nft_vec: Vector<(AccountId, TokenId, bool)> = Vector::new(b"i");
-        empty_nft_vec.push((env::predecessor_account_id(), TokenId::from("0"), false));
+        let mut default_nft_map = IterableMap::<AccountId, Vector<NftEntry>>::new(StorageKey::Nfts);
+        let mut empty_nft_vec = Self::new_owner_nfts(&env::predecessor_account_id());
+        empty_nft_vec.push(NftEntry {
+            grant_id: 0,
+            campaign_id: DEFAULT_CAMPAIGN_ID,
+            spender: env::predecessor_account_id(),
+            token_id: TokenId::from("0"),
+            claimed: false,
+            erc721_address: erc721.clone(),
+        });
         default_nft_map.insert(env::predecessor_account_id(),empty_nft_vec);
 
-        let mut erc20_address = Vector::new(b"2");
-        erc20_address.push(erc20);
+        // grant 0 is the placeholder entry of the contract account
+        let mut grants = LookupMap::new(StorageKey::Grants);
+        grants.insert(
+            0,
+            GrantRecord {
+                grant_id: 0,
+                campaign_id: DEFAULT_CAMPAIGN_ID,
+                owner: env::predecessor_account_id(),
+                spender: env::predecessor_account_id(),
+                status: GrantStatus::Minted,
+            },
+        );
 
-        let mut erc721_address = Vector::new(b"7");
-        erc721_address.push(erc721);
+        let mut erc20_address = Vector::new(StorageKey::Erc20Address);
+        erc20_address.push(erc20.clone());
+        Self::fetch_ft_decimals(erc20.clone());
+
+        let mut erc721_address = Vector::new(StorageKey::Erc721Address);
+        erc721_address.push(erc721.clone());
+
+        let mut campaigns = IterableMap::new(StorageKey::Campaigns);
+        campaigns.insert(DEFAULT_CAMPAIGN_ID, Self::default_campaign(env::predecessor_account_id(), erc20, erc721));
 
         let token_id_num = U128::from(10000);
-        let mut admin_address = IterableSet::new(b"a");
+        let mut admin_address = IterableSet::new(StorageKey::AdminAddress);
         admin_address.insert(env::predecessor_account_id());
 
-        let mut auth_token_owner = IterableSet::new(b"t");
+        let mut auth_token_owner = IterableSet::new(StorageKey::AuthTokenOwner);
         auth_token_owner.insert(env::predecessor_account_id());
 
         Self {
@@ -84,7 +330,223 @@ impl L2eTop {
             token_id_num: token_id_num,
             admin_address: admin_address,
             auth_token_owner: auth_token_owner,
+            nft_claims_in_flight: LookupSet::new(StorageKey::NftClaimsInFlight),
+            pending_mints: LookupSet::new(StorageKey::PendingMints),
+            ft_decimals: LookupMap::new(StorageKey::FtDecimals),
+            escrow_ledgers: LookupMap::new(StorageKey::EscrowLedgers),
+            escrow_totals: EscrowLedger::default(),
+            ft_holdings: LookupMap::new(StorageKey::FtHoldings),
+            campaigns,
+            next_campaign_id: DEFAULT_CAMPAIGN_ID + 1,
+            grants,
+            next_grant_id: 1,
+            attestation_nonces: LookupSet::new(StorageKey::AttestationNonces),
+            merkle_drops: LookupMap::new(StorageKey::MerkleDrops),
+            next_merkle_drop_id: 0,
+            merkle_claimed: LookupMap::new(StorageKey::MerkleClaimed),
+            merkle_unpaid: LookupMap::new(StorageKey::MerkleUnpaid),
+            quizzes: LookupMap::new(StorageKey::Quizzes),
+            quiz_commits: LookupMap::new(StorageKey::QuizCommits),
+            quiz_passed: LookupSet::new(StorageKey::QuizPassed),
+            quiz_attempts: LookupMap::new(StorageKey::QuizAttempts),
+            quiz_pool_total: NearToken::from_near(0),
+            attendance_sessions: LookupMap::new(StorageKey::AttendanceSessions),
+            next_attendance_session_id: 0,
+            attendance_claims: LookupSet::new(StorageKey::AttendanceClaims),
+        }
+    }
+
+    /// Move the per-account vectors of `balances` and `nfts` out of the shared `b"b"` and `b"n"` prefixes
+    /// into the hashed per-account prefixes of StorageKey. The other collections keep their stored prefixes.
+    /// The deployed contract gave every spender vector the prefix `b"b"` and every owner vector the prefix `b"n"`,
+    /// so the same stored entry is listed under several accounts. An nft is migrated once per token id, and only
+    /// for the owner whose grant to its spender is also listed in the spender balances. A balance entry is migrated
+    /// once per stored slot, and only for a spender that has a migrated nft of its owner. Other entries are dropped.
+    /// Old grants are bound to the first registered ft and nft contracts, the ones their claims defaulted to.
+    /// The escrow ledgers start with the main token of the open grants as deposited and committed.
+    /// The ft of old grants was never escrowed, it is pledged and funded by the owner with ft_transfer_call.
+    /// Old grants belong to the default campaign, owned by the l2e contract.
+    /// The placeholder entries of the contract account keep grant 0 as in `init`, the other grants get ids from 1.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let mut old_state: OldL2eTop = env::state_read().expect("No L2eTop state found to migrate");
+
+        let default_erc20 = old_state.erc20_address.get(0).expect("No erc20 address found").clone();
+        let default_erc721 = old_state.erc721_address.get(0).expect("No erc721 address found").clone();
+        // the vectors of the contract account were created by init with their own prefixes
+        let placeholder = env::current_account_id();
+
+        let mut grants = LookupMap::new(StorageKey::Grants);
+        let mut next_grant_id: GrantId = 1;
+        let mut grant_ids: HashMap<(AccountId, AccountId), GrantId> = HashMap::new();
+        let mut grant_id_for = |owner: &AccountId, spender: &AccountId| -> GrantId {
+            *grant_ids.entry((owner.clone(), spender.clone())).or_insert_with(|| {
+                let grant_id = if *owner == placeholder && *spender == placeholder {
+                    0
+                } else {
+                    next_grant_id += 1;
+                    next_grant_id - 1
+                };
+                grants.insert(
+                    grant_id,
+                    GrantRecord {
+                        grant_id,
+                        campaign_id: DEFAULT_CAMPAIGN_ID,
+                        owner: owner.clone(),
+                        spender: spender.clone(),
+                        status: GrantStatus::Minted,
+                    },
+                );
+                grant_id
+            })
+        };
+
+        let balance_pairs: HashSet<(AccountId, AccountId)> = old_state
+            .balances
+            .iter()
+            .flat_map(|(spender, old_balances)| old_balances.iter().map(move |x| (x.0.clone(), spender.clone())))
+            .collect();
+
+        let mut nfts = IterableMap::<AccountId, Vector<NftEntry>>::new(StorageKey::Nfts);
+        let mut seen_token_ids: HashSet<TokenId> = HashSet::new();
+        let mut nft_pairs: HashSet<(AccountId, AccountId)> = HashSet::new();
+        for (owner, old_nfts) in old_state.nfts.iter() {
+            let mut owner_nfts = Self::new_owner_nfts(owner);
+            for (spender, token_id, claimed) in old_nfts.iter() {
+                if !balance_pairs.contains(&(owner.clone(), spender.clone()))
+                    || !seen_token_ids.insert(token_id.clone())
+                {
+                    continue;
+                }
+                nft_pairs.insert((owner.clone(), spender.clone()));
+                owner_nfts.push(NftEntry {
+                    grant_id: grant_id_for(owner, spender),
+                    campaign_id: DEFAULT_CAMPAIGN_ID,
+                    spender: spender.clone(),
+                    token_id: token_id.clone(),
+                    claimed: *claimed,
+                    erc721_address: default_erc721.clone(),
+                });
+            }
+            nfts.insert(owner.clone(), owner_nfts);
+        }
+
+        let mut balances = IterableMap::<AccountId, Vector<BalanceEntry>>::new(StorageKey::Balances);
+        // (placeholder vector, index) of the stored balance entries already migrated
+        let mut seen_slots: HashSet<(bool, usize)> = HashSet::new();
+        for (spender, old_balances) in old_state.balances.iter() {
+            let mut spender_balances = Self::new_spender_balances(spender);
+            for (index, (owner, main_token_amount, ft_amount)) in old_balances.iter().enumerate() {
+                if !nft_pairs.contains(&(owner.clone(), spender.clone()))
+                    || !seen_slots.insert((*spender == placeholder, index))
+                {
+                    continue;
+                }
+                spender_balances.push(BalanceEntry {
+                    grant_id: grant_id_for(owner, spender),
+                    campaign_id: DEFAULT_CAMPAIGN_ID,
+                    owner: owner.clone(),
+                    main_token_amount: *main_token_amount,
+                    // the old claim paid `ft_amount.as_near()` raw units, nothing of it was escrowed
+                    ft_pledged: U128::from(ft_amount.as_near()),
+                    ft_escrowed: U128::from(0),
+                    erc20_address: default_erc20.clone(),
+                    expires_at: None,
+                    stages: vec![],
+                    vesting: None,
+                    ft_basket: vec![],
+                });
+            }
+            balances.insert(spender.clone(), spender_balances);
+        }
+        for nft_entry in nfts.values().flat_map(|x| x.iter()).filter(|x| x.claimed) {
+            if let Some(record) = grants.get_mut(&nft_entry.grant_id) {
+                record.status = GrantStatus::NftClaimed;
+            }
+        }
+        if !grants.contains_key(&0) {
+            grants.insert(
+                0,
+                GrantRecord {
+                    grant_id: 0,
+                    campaign_id: DEFAULT_CAMPAIGN_ID,
+                    owner: placeholder.clone(),
+                    spender: placeholder.clone(),
+                    status: GrantStatus::Minted,
+                },
+            );
+        }
+
+        // Only remove the old entries once everything is copied, the old vectors share their storage.
+        for (_, old_balances) in old_state.balances.iter_mut() {
+            old_balances.clear();
         }
+        old_state.balances.clear();
+        old_state.balances.flush();
+        for (_, old_nfts) in old_state.nfts.iter_mut() {
+            old_nfts.clear();
+        }
+        old_state.nfts.clear();
+        old_state.nfts.flush();
+
+        log!("Migrated balances of {} spenders and nfts of {} owners.", balances.len(), nfts.len());
+        for erc20 in old_state.erc20_address.iter() {
+            Self::fetch_ft_decimals(erc20.clone());
+        }
+
+        let mut contract = Self {
+            greeting: old_state.greeting,
+
+            balances,
+            nfts,
+            erc20_address: old_state.erc20_address,
+            erc721_address: old_state.erc721_address,
+
+            token_id_num: old_state.token_id_num,
+            admin_address: old_state.admin_address,
+            auth_token_owner: old_state.auth_token_owner,
+            nft_claims_in_flight: LookupSet::new(StorageKey::NftClaimsInFlight),
+            pending_mints: LookupSet::new(StorageKey::PendingMints),
+            ft_decimals: LookupMap::new(StorageKey::FtDecimals),
+            escrow_ledgers: LookupMap::new(StorageKey::EscrowLedgers),
+            escrow_totals: EscrowLedger::default(),
+            ft_holdings: LookupMap::new(StorageKey::FtHoldings),
+            campaigns: IterableMap::new(StorageKey::Campaigns),
+            next_campaign_id: DEFAULT_CAMPAIGN_ID + 1,
+            grants,
+            next_grant_id,
+            attestation_nonces: LookupSet::new(StorageKey::AttestationNonces),
+            merkle_drops: LookupMap::new(StorageKey::MerkleDrops),
+            next_merkle_drop_id: 0,
+            merkle_claimed: LookupMap::new(StorageKey::MerkleClaimed),
+            merkle_unpaid: LookupMap::new(StorageKey::MerkleUnpaid),
+            quizzes: LookupMap::new(StorageKey::Quizzes),
+            quiz_commits: LookupMap::new(StorageKey::QuizCommits),
+            quiz_passed: LookupSet::new(StorageKey::QuizPassed),
+            quiz_attempts: LookupMap::new(StorageKey::QuizAttempts),
+            quiz_pool_total: NearToken::from_near(0),
+            attendance_sessions: LookupMap::new(StorageKey::AttendanceSessions),
+            next_attendance_session_id: 0,
+            attendance_claims: LookupSet::new(StorageKey::AttendanceClaims),
+        };
+        contract.campaigns.insert(
+            DEFAULT_CAMPAIGN_ID,
+            Self::default_campaign(env::current_account_id(), default_erc20, default_erc721),
+        );
+
+        let open_grants: Vec<BalanceEntry> = contract.balances.values().flat_map(|x| x.iter().cloned()).collect();
+        for entry in open_grants {
+            contract.charge_campaign(DEFAULT_CAMPAIGN_ID, entry.main_token_amount, entry.ft_pledged.0);
+            if entry.main_token_amount > NearToken::from_near(0) {
+                contract.escrow_deposit(&entry.owner, EscrowAsset::MainToken, entry.main_token_amount.as_yoctonear());
+            }
+            if entry.ft_escrowed.0 > 0 {
+                contract.escrow_deposit(&entry.owner, EscrowAsset::Ft(entry.erc20_address.clone()), entry.ft_escrowed.0);
+            }
+        }
+
+        contract
     }
 
     pub fn get_erc20_address(&self) -> Vec<String> {
@@ -159,14 +621,19 @@ impl L2eTop {
         auth_token_owner
     }
 
-    pub fn get_all_spender_claim_for_owner(&self) -> Option<Vec<(String, String, bool)>> {
+    /// Spender, nft tokenid, claimed and grant id of every grant of the caller, in campaign_id or in all campaigns.
+    pub fn get_all_spender_claim_for_owner(
+        &self,
+        campaign_id: Option<CampaignId>,
+    ) -> Option<Vec<(String, String, bool, GrantId)>> {
         let owner = env::predecessor_account_id();
         let spender_nftid_claim = self.nfts.get(&owner);
         if let Some(spender_nftid_claim) = spender_nftid_claim {
-            let result_vecs: Vec<(std::string::String, std::string::String, bool)> =
+            let result_vecs: Vec<(std::string::String, std::string::String, bool, GrantId)> =
                 spender_nftid_claim
                     .iter()
-                    .map(|a_s_b| (a_s_b.0.to_string(), a_s_b.1.clone(), a_s_b.2))
+                    .filter(|a_s_b| campaign_id.is_none() || campaign_id == Some(a_s_b.campaign_id))
+                    .map(|a_s_b| (a_s_b.spender.to_string(), a_s_b.token_id.clone(), a_s_b.claimed, a_s_b.grant_id))
                     .collect();
 
             let all_spender_claim_for_owner_log = EventLog {
@@ -182,21 +649,24 @@ impl L2eTop {
         None
     }
 
-    pub fn get_all_owner_rewards_for_spender(&self) -> Option<Vec<(String, u128, u128)>> {
+    /// Main token in yoctoNEAR, escrowed ft in raw units and grant id for every grant of the spender,
+    /// in campaign_id or in all campaigns.
+    pub fn get_all_owner_rewards_for_spender(
+        &self,
+        campaign_id: Option<CampaignId>,
+    ) -> Option<Vec<(String, U128, U128, GrantId)>> {
         let spender = env::predecessor_account_id();
         let owner_bal_map = self.balances.get(&spender);
         if let Some(owner_bal_map) = owner_bal_map {
-            let result_vecs: Vec<(
-                std::string::String,
-                std::primitive::u128,
-                std::primitive::u128,
-            )> = owner_bal_map
+            let result_vecs: Vec<(std::string::String, U128, U128, GrantId)> = owner_bal_map
                 .iter()
+                .filter(|a_n_b| campaign_id.is_none() || campaign_id == Some(a_n_b.campaign_id))
                 .map(|a_n_b| {
                     (
-                        a_n_b.0.to_string(),
-                        a_n_b.1.as_near(),
-                        a_n_b.2.as_near(),
+                        a_n_b.owner.to_string(),
+                        U128::from(a_n_b.main_token_amount.as_yoctonear()),
+                        a_n_b.ft_escrowed,
+                        a_n_b.grant_id,
                     )
                 })
                 .collect();
@@ -215,18 +685,20 @@ impl L2eTop {
         None
     }
 
-    pub fn get_allowances_for_spender(&self, owner: AccountId) -> Option<(u128, u128)> {
+    /// Main token in yoctoNEAR and escrowed ft in raw units approved by owner for the caller,
+    /// of grant_id or summed over every grant of owner for the caller.
+    /// The ft of grants paid with different ft contracts is summed too.
+    pub fn get_allowances_for_spender(&self, owner: AccountId, grant_id: Option<GrantId>) -> Option<(U128, U128)> {
         let spender = env::predecessor_account_id();
-        let mut main_token = 0;
-        let mut token_amount = 0;
+        let mut main_token = U128::from(0);
+        let mut token_amount = U128::from(0);
 
         if self.balances.contains_key(&spender) {
             let balances = self.balances.get(&spender).unwrap();
 
-            let value = balances.iter().find(|x| x.0 == owner);
-            if let Some(value) = value {
-                main_token = value.1.as_near();
-                token_amount = value.2.as_near();
+            for value in balances.iter().filter(|x| x.owner == owner && (grant_id.is_none() || grant_id == Some(x.grant_id))) {
+                main_token = U128::from(main_token.0 + value.main_token_amount.as_yoctonear());
+                token_amount = U128::from(token_amount.0 + value.ft_escrowed.0);
             }
 
             let allowances_for_spender_log = EventLog {
@@ -243,143 +715,147 @@ impl L2eTop {
         None
     }
 
+    /// Rewards of spender from every owner, in campaign_id or in all campaigns,
+    /// with raw amounts and human-readable values.
+    pub fn get_rewards_for_spender(&self, spender: AccountId, campaign_id: Option<CampaignId>) -> Vec<RewardView> {
+        self.balances
+            .get(&spender)
+            .map(|owner_bal_map| {
+                owner_bal_map
+                    .iter()
+                    .filter(|entry| campaign_id.is_none() || campaign_id == Some(entry.campaign_id))
+                    .map(|entry| self.reward_view(entry))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// A grant with its balance and nft entries.
+    pub fn get_grant(&self, grant_id: GrantId) -> Option<GrantView> {
+        let record = self.grants.get(&grant_id)?.clone();
+        Some(GrantView {
+            grant_id,
+            campaign_id: record.campaign_id,
+            owner: record.owner,
+            spender: record.spender,
+            status: record.status,
+            balance: self.balance_entry(grant_id),
+            nft: self.nft_entry(grant_id),
+        })
+    }
+
+    /// Decimals cached from ft_metadata of a registered erc20 address.
+    pub fn get_ft_decimals(&self, erc20_address: AccountId) -> Option<u8> {
+        self.ft_decimals.get(&erc20_address).copied()
+    }
+
     /// Transfer main token, ft token, nft token for spender to l2e-top contract.
-    /// ft is l2e contract associated token.ft_amount is the amount of ft token to transfer. by frontend control, default value is 0.
+    /// ft is l2e contract associated token.ft_amount is the amount of ft token pledged for the spender. by frontend control, default value is 0.
+    /// The pledged ft is not counted until the owner funds it with `ft_transfer_call`, see `ft_on_transfer`.
+    /// The grant is made in campaign_id, its ft and nft contracts are stored with the grant and the claim methods always use them.
+    /// Only the campaign owner can grant in a campaign, except in the default campaign, and the campaign budget must cover the grant.
+    /// Only main_token_amount is kept in escrow, the rest of the attached deposit is refunded to the owner.
+    /// The call panics if the attached deposit does not cover main_token_amount, which returns the deposit.
+    /// expires_at is a block timestamp in nanoseconds, it is also written to the nft metadata in milliseconds.
+    /// With stages the grant is paid stage by stage by claim_stage, their amounts add up to main_token_amount and ft_amount.
+    /// With vesting the grant is paid over time by withdraw_vested, a grant has stages or vesting but not both.
+    /// Returns the id of the new grant, an owner can have any number of grants for the same spender.
     #[payable]
+    #[allow(clippy::too_many_arguments)]
     pub fn approve_for_spender(
         &mut self,
+        campaign_id: CampaignId,
         spender: AccountId,
         main_token_amount: NearToken,
-        ft_amount: NearToken,
+        ft_amount: U128,
         token_metadata: Option<TokenMetadata>,
-        erc20_address: Option<AccountId>,  // erc20 address's owner must be l2e-top contract.
-        erc721_address: Option<AccountId>,  // erc721 address's owner must be l2e-top contract.
-    ) -> bool {
-        let l2e_account = env::current_account_id();
+        expires_at: Option<U64>,
+        stages: Option<Vec<StageSchedule>>,
+        vesting: Option<VestingTerms>,
+        ft_basket: Option<Vec<BasketReward>>,
+    ) -> Option<GrantId> {
         let owner = env::predecessor_account_id();
-        let mut current_amount = NearToken::from_near(0);
 
         require!(
             main_token_amount > NearToken::from_near(0),
             "main_token_amount should be greater than 0"
         );
 
-        // main_token_amount should be transfer value, env::attached_deposit() is acutal value.
-        // frontend control vara_value >= env::attached_deposit()
         let attached_amount = env::attached_deposit();
-        require!(attached_amount > NearToken::from_near(0), "attached_amount should be greater than 0");
-        if attached_amount >= main_token_amount {
-            current_amount = main_token_amount;
-        } else {
-            log!("attached_amount cannot be less than main_token_amount.");
-            return false;
-        }
-        log!("attached_amount: {:?}",attached_amount);
-        log!("main_token_amount: {:?}",main_token_amount);
-        log!("ft_amount: {:?}",ft_amount);
-        // Approve main token and ft token for spender
-        // check if spender has balance
-        if self.balances.contains_key(&spender) {
-            let owner_value = self
-                .balances
-                .get_mut(&spender)
-                .expect("No balance found for spender");
-            if owner_value.iter().find(|x| x.0 == owner).is_some() {
-                log!("Spender has already approved balance.");
-                return false;
-            }
-
-            owner_value.push((owner.clone(), current_amount, ft_amount));
-        } else {
-            let mut owner_value = Vector::new(b"b");
-            owner_value.push((owner.clone(), current_amount, ft_amount));
-            self.balances.insert(spender.clone(), owner_value);
-
-            let mut current_erc20 = self.erc20_address.get(0).expect("No erc20 address found");
-
-            current_erc20 = erc20_address.as_ref().unwrap_or(current_erc20);
-
-            // cross contract call to erc20
-            // check spender and l2e account has balance
-            if !Self::is_account_registered_for_ft(spender.clone(), current_erc20.clone()) {
-                return false;
-            }
+        require!(attached_amount >= main_token_amount, "attached deposit does not cover main_token_amount");
+
+        let grant_id = self.create_grant(
+            &owner,
+            campaign_id,
+            spender,
+            main_token_amount,
+            ft_amount,
+            token_metadata,
+            expires_at,
+            stages,
+            vesting,
+            ft_basket,
+        );
 
-            log!("Spender has no balance, create new balance. {:?}", self
-            .balances
-            .get(&spender)
-            .expect("No balance found for spender").iter().find(|x| x.0 == owner));
+        // refund the surplus of the attached deposit
+        let surplus = attached_amount.saturating_sub(main_token_amount);
+        if surplus > NearToken::from_near(0) {
+            log!("Refund surplus deposit: {:?}", surplus);
+            let _surplus_refund_promise = Promise::new(owner.clone()).transfer(surplus);
         }
-
-        // Mint and Approve NFT for spender
-        let token_id: u128 = (self.token_id_num).into();
-        self.token_id_num = U128::from(token_id + 1);
-
-        let mut current_erc721 = self.erc721_address.get(0).expect("No erc721 address found");
-        current_erc721 = if let Some(ref erc721) = erc721_address {
-            erc721
-        } else {
-            current_erc721
-        };
-
-        // cross contract call to erc721
-        let _pro = if let Some(tm) = token_metadata {
-            let promise = ext_nft_contract::ext(current_erc721.clone())
-            // .with_static_gas(Gas::from_tgas(300))
-            .with_attached_deposit(NearToken::from_millinear(20))
-            .nft_mint((token_id + 1).to_string(), l2e_account.clone(), tm);
-            let _mint_nft_promise = promise.then(
-                // Create a promise to callback query_greeting_callback
-                Self::ext(l2e_account.clone())
-                    .nft_mint_callback(),
-            );
-            _mint_nft_promise
-        } else {
-            let promise = ext_nft_contract::ext(current_erc721.clone())
-            .with_attached_deposit(NearToken::from_millinear(20))
-            .nft_mint((token_id + 1).to_string(), l2e_account.clone(), TokenMetadata {
-                title: Some("L2E.TOP Chain Near Network".to_string()),
-                description: Some("Near Network and L2E.TOP Joint Certification Reward.".to_string()),
-                copies: Some(1),
-                media: None,
-                media_hash: None,
-                issued_at: None,
-                expires_at: None,
-                starts_at: None,
-                updated_at: None,
-                extra: None,
-                reference: None,
-                reference_hash: None,
-            });
-
-            let _mint_nft_promise = promise.then(
-                // Create a promise to callback query_greeting_callback
-                Self::ext(l2e_account.clone())
-                    .nft_mint_callback(),
-            );
-            _mint_nft_promise
-        };
-
-        // store nft tokenid and spender address
-        let nfts: &mut Vector<(AccountId, String, bool)> =
-            self.nfts.get_mut(&owner).expect("No nft owner found for owner");
-        nfts.push((spender, (token_id + 1).to_string(), false));
-        log!("Store nft tokenid and spender address.");
-        true
+        Some(grant_id)
     }
 
+    /// Confirm the grant created by approve_for_spender.
+    /// If nft_mint failed, the grant is removed from `balances` and `nfts` and its main token is refunded to the owner.
     #[private] // Public - but only callable by env::current_account_id()
     pub fn nft_mint_callback(
-        &self,
+        &mut self,
+        grant_id: GrantId,
         #[callback_result] call_result: Result<Token, PromiseError>,
     ) -> Option<Token> {
         log!("beigin nft_mint_callback");
+        let GrantRecord { campaign_id, owner, spender, .. } = self.grant_record(grant_id);
+        let nft_entry = self.nft_entry(grant_id).expect("No nft found for grant");
+        self.pending_mints.remove(&nft_entry.token_id);
+
         if call_result.is_err() {
             log!("There was an error contacting NFT contract nft_mint: {:#?}", call_result.map_err(|e| format!("error details: {:#?}", e)));
+
+            if !self.try_set_grant_status(grant_id, GrantStatus::Failed) {
+                return None;
+            }
+            self.take_nft_entry(grant_id);
+            let balance_entry = self.take_balance_entry(grant_id).expect("No balance found for grant");
+            let main_token_amount = balance_entry.main_token_amount;
+            self.release_campaign(campaign_id, main_token_amount, balance_entry.ft_pledged.0);
+
+            let _refund_promise = Promise::new(owner.clone()).transfer(main_token_amount);
+            self.escrow_refund(&owner, EscrowAsset::MainToken, main_token_amount.as_yoctonear());
+
+            let approve_for_spender_failed_log = EventLog {
+                standard: CONSTRACT_NAME.to_string(),
+                version: CONSTRACT_VERSION.to_string(),
+                event: EventLogVariant::ApproveForSpenderFailed(vec![GrantLog {
+                    owner_id: owner.to_string(),
+                    spender_id: spender.to_string(),
+                    token_ids: vec![nft_entry.token_id],
+                    main_token_amount: main_token_amount.as_yoctonear().to_string(),
+                    ft_token_amount: "0".to_string(),
+                    grant_id: Some(grant_id),
+                    campaign_id: Some(campaign_id),
+                    memo: Some("L2E Team".to_string()),
+                }]),
+            };
+
+            env::log_str(&approve_for_spender_failed_log.to_string());
             return None;
         }
 
         // Return the token data
+        if !self.try_set_grant_status(grant_id, GrantStatus::Minted) {
+            return None;
+        }
         let token: Token = call_result.unwrap();
         return Some(token);
     }
@@ -449,231 +925,395 @@ impl L2eTop {
     }
 
     /// First mint and approve nft for spender, Then call this method to claim nft.
-    pub fn transfer_nft_from(&mut self, owner: AccountId, erc721_address: Option<AccountId>) -> bool {
+    /// The nft is only marked as claimed by nft_transfer_callback once nft_transfer succeeded.
+    /// The nft is transferred on the erc721 contract the grant was approved with, to receiver_id if given, else to the spender.
+    /// In a campaign with verifier keys the claim needs an attestation of a verifier with no stage, see `Attestation`.
+    /// In a campaign with a verifier contract the nft is transferred once it verified the proof, see `verify_nft_claim_callback`.
+    pub fn transfer_nft_from(
+        &mut self,
+        grant_id: GrantId,
+        receiver_id: Option<AccountId>,
+        attestation: Option<Attestation>,
+        proof: Option<String>,
+    ) -> bool {
         let spender = env::predecessor_account_id();
-        let token_id = self
-            .nfts
-            .get(&owner)
-            .expect("No nft found for owner")
-            .iter()
-            .find(|x| x.0 == spender && !x.2)
-            .expect("No unclaimed nft found for spender")
-            .1
-            .clone();
-        let mut current_erc721 = self.erc721_address.get(0).expect("No nft address found");
-        current_erc721 = if let Some(ref erc721) = erc721_address {
-            erc721
-        } else {
-            current_erc721
+        require!(self.grant_record(grant_id).spender == spender, "Grant is not approved for spender");
+        let NftEntry {
+            token_id,
+            erc721_address: current_erc721,
+            claimed,
+            ..
+        } = self.nft_entry(grant_id).expect("No nft found for grant");
+        require!(!claimed, "Nft is already claimed by spender");
+        let expired = self.balance_entry(grant_id).is_some_and(|x| x.is_expired(env::block_timestamp()));
+        require!(!expired, "Grant is expired");
+        require!(!self.pending_mints.contains(&token_id), "Nft is not minted yet");
+        require!(
+            !self.nft_claims_in_flight.contains(&token_id),
+            "Nft claim is already in progress"
+        );
+        let GrantRecord { campaign_id, status, .. } = self.grant_record(grant_id);
+        require!(status == GrantStatus::Minted, format!("Nft can't be claimed from a {:?} grant", status));
+        self.use_attestation(campaign_id, &spender, None, attestation);
+
+        let receiver_id = Self::claim_receiver(&spender, receiver_id);
+        if let Some(verifier_contract) = self.verifier_contract(campaign_id) {
+            // in flight until verify_nft_claim_callback, so the nft is not claimed twice meanwhile
+            self.nft_claims_in_flight.insert(token_id.clone());
+            let _verify_promise = Self::verify_claim(&verifier_contract, campaign_id, &spender, proof)
+                .then(Self::ext(env::current_account_id()).verify_nft_claim_callback(grant_id, receiver_id));
+            return true;
+        }
+        self.start_nft_claim(grant_id, &spender, receiver_id, &current_erc721, &token_id);
+
+        true
+    }
+
+    /// Release the claim lock and set the claimed nft to true only if nft_transfer succeeded.
+    /// receiver_id is the account the nft was sent to when it is not the spender.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn nft_transfer_callback(
+        &mut self,
+        grant_id: GrantId,
+        receiver_id: Option<AccountId>,
+        #[callback_result] call_result: Result<(), PromiseError>,
+    ) -> bool {
+        let GrantRecord { campaign_id, owner, spender, .. } = self.grant_record(grant_id);
+        let token_id = self.nft_entry(grant_id).expect("No nft found for grant").token_id;
+        self.nft_claims_in_flight.remove(&token_id);
+
+        let nft_transfer_log = NftTransferLog {
+            authorized_id: Some(owner.to_string()),
+            old_owner_id: env::current_account_id().to_string(),
+            new_owner_id: receiver_id.as_ref().unwrap_or(&spender).to_string(),
+            token_ids: vec![token_id.clone()],
+            spender_id: receiver_id.as_ref().map(|_| spender.to_string()),
+            grant_id: Some(grant_id),
+            campaign_id: Some(campaign_id),
+            memo: Some("L2E Team".to_string()),
         };
-        // let nft_token_promise = ext_nft_core::ext(current_erc721.clone())
-        //     .with_attached_deposit(NearToken::from_yoctonear(1))
-        //     .nft_token(token_id.clone());
-
-        // let _nft_token_callback_promise = nft_token_promise.then(
-        //     // Create a promise to callback query_greeting_callback
-        //     Self::ext(env::current_account_id())
-        //         .nft_token_callback(),
-        // );
-
-        // // approve nft for spender
-        // let nft_approve_promise = ext_nft_approval::ext(current_erc721.clone())
-        //     // .with_attached_deposit(NearToken::from_yoctonear(1))
-        //     .with_attached_deposit(NearToken::from_millinear(200))
-        //     .nft_approve(token_id.clone(), spender.clone(), None,);
-
-        // let _nft_approve_callback_promise = nft_approve_promise.then(
-        //     Self::ext(env::current_account_id())
-        //         .nft_approve_callback(),
-        // );
-
-        let nft_token_promise = ext_nft_core::ext(current_erc721.clone())
-            .with_attached_deposit(NearToken::from_yoctonear(1))
-            .nft_token(token_id.clone());
 
-        let _nft_token_callback_promise = nft_token_promise.then(
-            // Create a promise to callback query_greeting_callback
-            Self::ext(env::current_account_id())
-                .nft_token_callback(),
-        );
+        // Check if the promise succeeded
+        if call_result.is_err() {
+            log!("There was an error contacting NFT contract nft_transfer: {:#?}", call_result.map_err(|e| format!("error details: {:#?}", e)));
 
-        log!("transfer_nft_from current_erc721: {:#?}", current_erc721);
-        log!("current contract account id: {:#?}", env::current_account_id() );
-        log!("transfer to spender: {:#?}", spender.clone());
-        log!("transfer nft: {:#?}", token_id.clone());
-        let promise = ext_nft_core::ext(current_erc721.clone())
-            .with_attached_deposit(NearToken::from_yoctonear(1))
-            .nft_transfer(spender.clone(), token_id.clone(), None, None);
+            let transfer_nft_failed_log = EventLog {
+                standard: CONSTRACT_NAME.to_string(),
+                version: CONSTRACT_VERSION.to_string(),
+                event: EventLogVariant::TransferNftFromFailed(vec![nft_transfer_log]),
+            };
 
-        let _transfer_nft_promise = promise.then(
-            // Create a promise to callback query_greeting_callback
-            Self::ext(env::current_account_id()).nft_transfer_callback(),
-        );
+            env::log_str(&transfer_nft_failed_log.to_string());
+            return false;
+        }
 
         // Set already claimed nft to true
-        let nfts: &mut Vector<(AccountId, String, bool)> =
+        if !self.try_set_grant_status(grant_id, GrantStatus::NftClaimed) {
+            return false;
+        }
+        let nfts: &mut Vector<NftEntry> =
             self.nfts.get_mut(&owner).expect("No nft found for owner");
         let index = nfts
             .iter()
-            .position(|x| x.0 == spender && x.1 == token_id)
-            .unwrap();
-        nfts[index as u32].2 = true;
+            .position(|x| x.grant_id == grant_id)
+            .expect("No nft found for grant");
+        nfts[index as u32].claimed = true;
 
         let transfer_nft_from_log = EventLog {
             standard: CONSTRACT_NAME.to_string(),
             version: CONSTRACT_VERSION.to_string(),
-            event: EventLogVariant::TransferNftFrom(vec![NftTransferLog {
-                authorized_id: Some(owner.clone().to_string()),
-                old_owner_id: env::current_account_id().to_string(),
-                new_owner_id: spender.to_string(),
-                token_ids: vec![token_id.clone()],
-                memo: Some("L2E Team".to_string()),
-            }]),
+            event: EventLogVariant::TransferNftFrom(vec![nft_transfer_log]),
         };
 
         env::log_str(&transfer_nft_from_log.to_string());
+        log!("nft_transfer_callback success");
 
         true
     }
 
-    #[private]
-    pub fn nft_token_callback(&self, #[callback_result] call_result: Result<Option<Token>, PromiseError>,){
-        if call_result.is_err() {
-            log!("There was an error contacting NFT contract nft_token");
-        }
-
-        let token: Option<Token> = call_result.unwrap();
-        log!("nft_token_callback token id: {:#?}", token);
-    }
+    /// Pay the main token and the escrowed ft of the grant once its nft is claimed.
+    /// The ft is paid from the erc20 contract the grant was approved with, and each token of its ft_basket from its own contract.
+    /// Everything is paid to receiver_id if given, it is registered with each ft contract before its ft_transfer.
+    pub fn transfer_balances_from(&mut self, grant_id: GrantId, receiver_id: Option<AccountId>) -> bool {
+        log!("transfer_balances_from: {:#?}", grant_id);
+        let spender = env::predecessor_account_id();
+        require!(self.grant_record(grant_id).spender == spender, "Grant is not approved for spender");
 
-    #[private] // Public - but only callable by env::current_account_id()
-    pub fn nft_transfer_callback(&self, #[callback_result] call_result: Result<(), PromiseError>,) {
-        // Check if the promise succeeded
-        if call_result.is_err() {
-            log!("There was an error contacting NFT contract nft_transfer: {:#?}", call_result.map_err(|e| format!("error details: {:#?}", e)));
+        // check nft authoriaztion
+        let nft_entry = self
+            .nft_entry(grant_id)
+            .filter(|x| x.claimed)
+            .expect("No claimed nft found for spender");
+
+        log!("transfer_balances_from nft_id: {:#?}", nft_entry.token_id);
+        let balance_entry = self.balance_entry(grant_id).expect("No main_token balance found for spender");
+        require!(!balance_entry.is_expired(env::block_timestamp()), "Grant is expired");
+        require!(balance_entry.stages.is_empty(), "Grant is paid by stage, use claim_stage");
+        require!(balance_entry.vesting.is_none(), "Grant vests over time, use withdraw_vested");
+
+        self.set_grant_status(grant_id, GrantStatus::PaymentInFlight);
+
+        // transfer current contract main token and ft token to spender, or to the receiver it chose.
+        // only the escrowed ft amount is paid, it is what actually arrived through ft_on_transfer.
+        let receiver_id = Self::claim_receiver(&spender, receiver_id);
+        let receiver = receiver_id.as_ref().unwrap_or(&spender);
+        let payout = Self::payout(
+            receiver,
+            balance_entry.main_token_amount,
+            &balance_entry.erc20_address,
+            balance_entry.ft_escrowed,
+            receiver_id.is_some(),
+        );
+        let payout = Self::basket_payout(payout, receiver, &balance_entry.ft_basket, None, receiver_id.is_some());
+
+        // Remove nft and main token records, restored by transfer_balances_callback if a transfer fails
+        self.take_nft_entry(grant_id);
+        self.take_balance_entry(grant_id);
+
+        match payout {
+            Some(payout) => {
+                let _transfer_balances_promise = payout.then(
+                    Self::ext(env::current_account_id()).transfer_balances_callback(
+                        spender,
+                        balance_entry,
+                        nft_entry,
+                        receiver_id,
+                    ),
+                );
+            }
+            None => self.set_grant_status(grant_id, GrantStatus::Paid),
         }
-        log!("nft_transfer_callback success");
 
+        true
     }
 
-    pub fn transfer_balances_from(
+    /// Resolve the transfers started by `transfer_balances_from`.
+    /// The promise results are in order: main token transfer, ft_transfer, then an ft_transfer per funded ft_basket entry.
+    /// A zero leg is not part of the results.
+    /// Every failed leg is written back to `balances` and `nfts`, so the spender can call transfer_balances_from again.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn transfer_balances_callback(
         &mut self,
-        owner: AccountId,
-        erc20_address: Option<AccountId>,
+        spender: AccountId,
+        balance_entry: BalanceEntry,
+        nft_entry: NftEntry,
+        receiver_id: Option<AccountId>,
     ) -> bool {
-        log!("transfer_balances_from: {:#?}", owner);
-        let spender = env::predecessor_account_id();
+        let (main_token_paid, ft_paid) = Self::payout_results(balance_entry.main_token_amount, balance_entry.ft_escrowed);
+        let first_basket_result = (balance_entry.main_token_amount > NearToken::from_near(0)) as u64
+            + (balance_entry.ft_escrowed.0 > 0) as u64;
+        let basket_paid = Self::basket_results(&balance_entry.ft_basket, first_basket_result);
+        self.settle_balances_payout(
+            &spender,
+            receiver_id.as_ref(),
+            balance_entry,
+            nft_entry,
+            main_token_paid,
+            ft_paid,
+            basket_paid,
+        )
+    }
 
-        // check nft authoriaztion
-        let nft_id = self
-            .nfts
-            .get(&owner)
-            .expect("No Approved nft found for owner")
-            .iter()
-            .find(|x| x.0 == spender && x.2)
-            .expect("No claimed nft found for spender")
-            .1
-            .clone();
+    /// Cancel an unclaimed grant and return its main token, escrowed ft and nft to the owner.
+    /// Called by the owner or by an admin. The ft pledged but never escrowed is dropped.
+    /// Returns that fail are restored by revoke_grant_callback, so the grant can be revoked again.
+    pub fn revoke_grant(&mut self, grant_id: GrantId) -> bool {
+        let caller = env::predecessor_account_id();
+        let owner = self.grant_record(grant_id).owner;
+        require!(
+            caller == owner || self.admin_address.contains(&caller),
+            "Only the owner or an admin can revoke a grant"
+        );
 
-        log!("transfer_balances_from nft_id: {:#?}", nft_id);
-        // transfer main token and ft token from owner to spender
-        // for (k, v) in self.balances.iter(){
-        //     log!("transfer_balances_from k: {:#?}", k);
-        //     log!("transfer_balances_from v: {:#?}", v);
-        //     for arr_v_tuple in v.iter(){
-        //         log!("transfer_balances_from arr_v_tuple0: {:#?}", arr_v_tuple.0);
-        //         log!("transfer_balances_from arr_v_tuple1: {:#?}", arr_v_tuple.1);
-        //         log!("transfer_balances_from arr_v_tuple2: {:#?}", arr_v_tuple.2);
-        //     }
-        // }
-        // log!("transfer_balances_from spender: {:#?}", spender);
-        // for v in self.balances.get(&spender).expect("read vector fail").iter(){
-        //     log!("transfer_balances_from v: {:#?}", v);
-        // }
-        let current_main_token_amount = self
+        // the nft entry is gone if only the nft was returned by a previous revoke
+        let nft_entry = self.nft_entry(grant_id);
+        if let Some(nft_entry) = &nft_entry {
+            require!(!nft_entry.claimed, "Nft is already claimed by spender");
+            require!(!self.pending_mints.contains(&nft_entry.token_id), "Nft is not minted yet");
+            require!(
+                !self.nft_claims_in_flight.contains(&nft_entry.token_id),
+                "Nft claim is already in progress"
+            );
+        }
+
+        self.return_grant_to_owner(grant_id, false);
+        true
+    }
+
+    /// Return the grants that expired before being claimed to their owners, anyone can call it.
+    /// Walks `limit` spenders of `balances` from `from_index`, default 0 and 10, and returns the number of grants reclaimed.
+    /// A claimed nft stays with the spender, only the unpaid main token and ft are returned then.
+    pub fn reclaim_expired(&mut self, from_index: Option<u32>, limit: Option<u32>) -> u32 {
+        let now = env::block_timestamp();
+        let expired: Vec<(GrantId, bool)> = self
             .balances
-            .get(&spender)
-            .expect("No balance approve found for spender")
-            .iter()
-            .find(|x| x.0 == owner)
-            .expect("No main_token balance found for spender")
-            .1;
+            .values()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(10) as usize)
+            .flat_map(|owner_value| {
+                owner_value
+                    .iter()
+                    .filter(|x| x.is_expired(now))
+                    .map(|x| (x.grant_id, x.is_paying()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
 
-        // transfer current contract main token to spender
-        let _promise = Promise::new(spender.clone()).transfer(current_main_token_amount);
+        let mut reclaimed = 0;
+        for (grant_id, paying) in expired {
+            // a grant with its mint, nft claim or payout still in flight is left for a later call
+            let busy = paying
+                || self.nft_entry(grant_id).is_some_and(|x| {
+                    self.pending_mints.contains(&x.token_id) || self.nft_claims_in_flight.contains(&x.token_id)
+                });
+            if busy {
+                log!("reclaim_expired: grant {} is busy.", grant_id);
+                continue;
+            }
 
-        // transfer ft token from owner to
-        let mut current_erc20 = self.erc20_address.get(0).expect("No nft address found");
-        current_erc20 = if let Some(ref erc20) = erc20_address {
-            erc20
-        } else {
-            current_erc20
-        };
+            self.return_grant_to_owner(grant_id, true);
+            reclaimed += 1;
+        }
 
-        let current_ft_token_amount = self
-            .balances
-            .get(&spender)
-            .expect("No balance approve found for owner")
-            .iter()
-            .find(|x| x.0 == owner)
-            .expect("No ft_amount balance found for spender")
-            .2;
+        reclaimed
+    }
 
-        // transfer current contract ft token to spender, cross contract call to erc20
-        // cross contract call to erc20
-        let promise = ext_ft_core::ext(current_erc20.clone())
-            .with_attached_deposit(NearToken::from_yoctonear(1))
-            .ft_transfer(spender.clone(), U128::from(current_ft_token_amount.as_near()), None);
-        
-        let _mint_nft_promise = promise.then(
-            // Create a promise to callback query_greeting_callback
-            Self::ext(env::current_account_id())
-                .ft_transfer_callback(),
-        );
+    /// Resolve the returns started by `revoke_grant` and `reclaim_expired`.
+    /// The promise results are in order: main token transfer, ft_transfer, an ft_transfer per funded ft_basket entry, nft_transfer.
+    /// A skipped leg is not part of the results.
+    /// Every failed leg is written back to `balances` and `nfts`.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn revoke_grant_callback(
+        &mut self,
+        spender: AccountId,
+        balance_entry: BalanceEntry,
+        nft_entry: Option<NftEntry>,
+        expired: bool,
+    ) -> bool {
+        let owner = balance_entry.owner.clone();
+        let grant_id = balance_entry.grant_id;
+        let campaign_id = balance_entry.campaign_id;
+        let mut result_index = 0;
+        let mut main_token_returned = true;
+        if balance_entry.main_token_amount > NearToken::from_near(0) {
+            main_token_returned = Self::promise_succeeded(result_index);
+            result_index += 1;
+        }
+        let mut ft_returned = true;
+        if balance_entry.ft_escrowed.0 > 0 {
+            ft_returned = Self::promise_succeeded(result_index);
+            result_index += 1;
+        }
+        let basket_returned = Self::basket_results(&balance_entry.ft_basket, result_index);
+        result_index += balance_entry.ft_basket.iter().filter(|x| x.is_funded()).count() as u64;
+        let mut nft_returned = true;
+        if nft_entry.is_some() {
+            nft_returned = Self::promise_succeeded(result_index);
+        }
 
-        // Remove nft record from owner
-        let nfts: &mut Vector<(AccountId, String, bool)> =
-            self.nfts.get_mut(&owner).expect("No nft found for owner");
-        let index = nfts
-            .iter()
-            .position(|x: &(AccountId, String, bool)| x.0 == spender)
-            .unwrap();
-        nfts.swap_remove(index as u32);
+        let unreturned_main_token = if main_token_returned { NearToken::from_near(0) } else { balance_entry.main_token_amount };
+        let unreturned_ft = if ft_returned { 0 } else { balance_entry.ft_escrowed.0 };
+        let returned_main_token = balance_entry.main_token_amount.saturating_sub(unreturned_main_token);
+        let returned_ft = balance_entry.ft_escrowed.0 - unreturned_ft;
+        if returned_main_token > NearToken::from_near(0) {
+            self.escrow_refund(&owner, EscrowAsset::MainToken, returned_main_token.as_yoctonear());
+        }
+        if returned_ft > 0 {
+            self.escrow_refund(&owner, EscrowAsset::Ft(balance_entry.erc20_address.clone()), returned_ft);
+        }
+        for (entry, returned) in balance_entry.ft_basket.iter().zip(&basket_returned) {
+            if *returned && entry.is_funded() {
+                self.escrow_refund(&owner, EscrowAsset::Ft(entry.ft_contract.clone()), entry.ft_escrowed.0);
+            }
+        }
 
-        // Remove main token record from spender
-        let balances: &mut Vector<(AccountId, NearToken, NearToken)> =
-            self.balances.get_mut(&spender).expect("No balance found for spender");
-        let index = balances
-            .iter()
-            .position(|x| x.0 == owner)
-            .unwrap();
-        balances.swap_remove(index as u32);
+        if main_token_returned && ft_returned && basket_returned.iter().all(|x| *x) && nft_returned {
+            Self::log_grant_returned(
+                expired,
+                GrantLog {
+                    owner_id: owner.to_string(),
+                    spender_id: spender.to_string(),
+                    token_ids: nft_entry.map(|x| x.token_id).into_iter().collect(),
+                    main_token_amount: returned_main_token.as_yoctonear().to_string(),
+                    ft_token_amount: returned_ft.to_string(),
+                    grant_id: Some(grant_id),
+                    campaign_id: Some(campaign_id),
+                    memo: Some("L2E Team".to_string()),
+                },
+            );
+            return true;
+        }
+
+        // Restore what was not returned, the nft entry only if the nft is still held by the l2e contract.
+        let unreturned_nft = if nft_returned { None } else { nft_entry };
+        let token_ids: Vec<TokenId> = unreturned_nft.iter().map(|x| x.token_id.clone()).collect();
+        self.charge_campaign(campaign_id, unreturned_main_token, unreturned_ft);
+        self.restore_grant(
+            &spender,
+            BalanceEntry {
+                main_token_amount: unreturned_main_token,
+                ft_pledged: U128::from(unreturned_ft),
+                ft_escrowed: U128::from(unreturned_ft),
+                ft_basket: balance_entry
+                    .ft_basket
+                    .iter()
+                    .zip(&basket_returned)
+                    .filter(|(entry, returned)| entry.is_funded() && !**returned)
+                    .map(|(entry, _)| BasketEntry { ft_pledged: entry.ft_escrowed, ..entry.clone() })
+                    .collect(),
+                ..balance_entry
+            },
+            unreturned_nft,
+        );
 
-        let transfer_balances_from_log = EventLog {
+        let revoke_grant_failed_log = EventLog {
             standard: CONSTRACT_NAME.to_string(),
             version: CONSTRACT_VERSION.to_string(),
-            event: EventLogVariant::TransferBalacnesFrom(vec![BalacnesTransferLog {
-                authorized_id: Some(owner.clone().to_string()),
-                old_owner_id: env::current_account_id().to_string(),
-                new_owner_id: spender.to_string(),
-                main_token_amount: vec![current_main_token_amount.as_near().to_string()],
-                ft_token_amount: vec![current_ft_token_amount.as_near().to_string()],
+            event: EventLogVariant::RevokeGrantFailed(vec![GrantLog {
+                owner_id: owner.to_string(),
+                spender_id: spender.to_string(),
+                token_ids,
+                main_token_amount: unreturned_main_token.as_yoctonear().to_string(),
+                ft_token_amount: unreturned_ft.to_string(),
+                grant_id: Some(grant_id),
+                campaign_id: Some(campaign_id),
                 memo: Some("L2E Team".to_string()),
             }]),
         };
 
-        env::log_str(&transfer_balances_from_log.to_string());
+        env::log_str(&revoke_grant_failed_log.to_string());
 
-        true
+        false
     }
 
+    /// Cache the decimals of a registered ft contract.
     #[private] // Public - but only callable by env::current_account_id()
-    pub fn ft_transfer_callback(&self, #[callback_result] call_result: Result<(), PromiseError>) {
-        // Check if the promise succeeded
-        if call_result.is_err() {
-            log!("There was an error contacting FT contract ft_transfer: {:#?}", call_result.map_err(|e| format!("error details: {:#?}", e)));
+    pub fn ft_metadata_callback(
+        &mut self,
+        erc20_address: AccountId,
+        #[callback_result] call_result: Result<FungibleTokenMetadata, PromiseError>,
+    ) -> Option<u8> {
+        match call_result {
+            Ok(metadata) => {
+                log!("ft_metadata_callback {} decimals: {}", erc20_address, metadata.decimals);
+                self.ft_decimals.insert(erc20_address, metadata.decimals);
+                Some(metadata.decimals)
+            }
+            Err(e) => {
+                log!("There was an error contacting FT contract ft_metadata: {:#?}", e);
+                None
+            }
         }
-        log!("ft_transfer_callback success");
+    }
+
+    /// Fetch ft_metadata again, e.g. when it failed at registration.
+    pub fn refresh_ft_decimals(&mut self, erc20_address: AccountId) -> bool {
+        require!(
+            self.erc20_address.iter().any(|x| x == &erc20_address),
+            "erc20_address is not registered"
+        );
+        Self::fetch_ft_decimals(erc20_address);
+        true
     }
 
     pub fn add_admin_address(&mut self, new_admin_address: AccountId) -> bool {
@@ -706,11 +1346,11 @@ impl L2eTop {
             }
 
             if !self.balances.contains_key(&owner_address) {
-                self.balances.insert(owner_address.clone(), Vector::new(b"b"));
+                self.balances.insert(owner_address.clone(), Self::new_spender_balances(&owner_address));
             }
 
             if !self.nfts.contains_key(&owner_address) {
-                self.nfts.insert(owner_address.clone(), Vector::new(b"n"));
+                self.nfts.insert(owner_address.clone(), Self::new_owner_nfts(&owner_address));
             }
 
             log!("New auth_token_owner added: {}", owner_address.to_string());
@@ -733,6 +1373,7 @@ impl L2eTop {
         {
             if self.erc20_address.iter().position(|x| x == &erc20_address).is_none() {
                 self.erc20_address.push(erc20_address.clone());
+                Self::fetch_ft_decimals(erc20_address.clone());
             }
 
             if self.erc721_address.iter().position(|x| x == &erc721_address).is_none() {
@@ -759,11 +1400,732 @@ impl L2eTop {
     }
 }
 
-use near_sdk::json_types::Base64VecU8;
-use near_sdk::serde::Deserialize;
-use near_sdk::serde_json;
-#[derive(Deserialize)]
-#[serde(crate = "near_sdk::serde")]
+impl L2eTop {
+    /// Create a grant of owner for spender in campaign_id and mint its nft, resolved by nft_mint_callback.
+    /// main_token_amount is already attached by the caller, it is kept in escrow.
+    #[allow(clippy::too_many_arguments)]
+    fn create_grant(
+        &mut self,
+        owner: &AccountId,
+        campaign_id: CampaignId,
+        spender: AccountId,
+        current_amount: NearToken,
+        ft_amount: U128,
+        token_metadata: Option<TokenMetadata>,
+        expires_at: Option<U64>,
+        stages: Option<Vec<StageSchedule>>,
+        vesting: Option<VestingTerms>,
+        ft_basket: Option<Vec<BasketReward>>,
+    ) -> GrantId {
+        let l2e_account = env::current_account_id();
+        let owner = owner.clone();
+        if let Some(expires_at) = expires_at {
+            require!(expires_at.0 > env::block_timestamp(), "expires_at should be in the future");
+        }
+        let stages = Self::new_stages(stages, current_amount, ft_amount);
+        let vesting = Self::new_vesting(vesting, current_amount, ft_amount);
+        require!(stages.is_empty() || vesting.is_none(), "A grant can't have both stages and vesting");
+
+        // erc20 and erc721 address's owner must be l2e-top contract.
+        let campaign = self.campaign_for_grant(campaign_id, &owner);
+        require!(campaign.has_budget_for(current_amount, ft_amount), "Campaign budget exceeded");
+        let current_erc20 = campaign.erc20_address;
+        let current_erc721 = campaign.erc721_address;
+        let nft_template = campaign.nft_template;
+        let ft_basket = self.new_basket(ft_basket, &current_erc20);
+        require!(
+            ft_basket.is_empty() || (stages.is_empty() && vesting.is_none()),
+            "A grant with stages or vesting can't have an ft_basket"
+        );
+        let grant_id = self.next_grant_id;
+        self.next_grant_id += 1;
+        self.grants.insert(
+            grant_id,
+            GrantRecord {
+                grant_id,
+                campaign_id,
+                owner: owner.clone(),
+                spender: spender.clone(),
+                status: GrantStatus::PendingMint,
+            },
+        );
+        let balance_entry = BalanceEntry {
+            grant_id,
+            campaign_id,
+            owner: owner.clone(),
+            main_token_amount: current_amount,
+            ft_pledged: ft_amount,
+            ft_escrowed: U128::from(0),
+            erc20_address: current_erc20.clone(),
+            expires_at,
+            stages,
+            vesting,
+            ft_basket: ft_basket.clone(),
+        };
+
+        // Approve main token and ft token for spender
+        // check if spender has balance
+        if self.balances.contains_key(&spender) {
+            let owner_value = self
+                .balances
+                .get_mut(&spender)
+                .expect("No balance found for spender");
+            owner_value.push(balance_entry);
+        } else {
+            let mut owner_value = Self::new_spender_balances(&spender);
+            owner_value.push(balance_entry);
+            self.balances.insert(spender.clone(), owner_value);
+
+            // cross contract call to erc20
+            // check spender and l2e account has balance
+            Self::is_account_registered_for_ft(spender.clone(), current_erc20.clone());
+
+            log!("Spender has no balance, create new balance. {:?}", self
+            .balances
+            .get(&spender)
+            .expect("No balance found for spender").iter().find(|x| x.grant_id == grant_id));
+        }
+        // the spender has to be registered with every sponsor token of the basket too
+        for entry in ft_basket.iter() {
+            Self::is_account_registered_for_ft(spender.clone(), entry.ft_contract.clone());
+        }
+        self.charge_campaign(campaign_id, current_amount, ft_amount.0);
+
+        // Keep main_token_amount in escrow
+        self.escrow_deposit(&owner, EscrowAsset::MainToken, current_amount.as_yoctonear());
+
+        // Mint and Approve NFT for spender
+        let new_token_id = self.next_token_id();
+
+        // cross contract call to erc721
+        let mut tm = token_metadata.or(nft_template).unwrap_or(TokenMetadata {
+            title: Some("L2E.TOP Chain Near Network".to_string()),
+            description: Some("Near Network and L2E.TOP Joint Certification Reward.".to_string()),
+            copies: Some(1),
+            media: None,
+            media_hash: None,
+            issued_at: None,
+            expires_at: None,
+            starts_at: None,
+            updated_at: None,
+            extra: None,
+            reference: None,
+            reference_hash: None,
+        });
+        if let Some(expires_at) = expires_at {
+            // nft metadata timestamps are unix epoch in milliseconds
+            tm.expires_at = Some((expires_at.0 / 1_000_000).to_string());
+        }
+        let _mint_nft_promise = ext_nft_contract::ext(current_erc721.clone())
+            // .with_static_gas(Gas::from_tgas(300))
+            .with_attached_deposit(NFT_MINT_DEPOSIT)
+            .nft_mint(new_token_id.clone(), l2e_account.clone(), tm)
+            .then(
+                Self::ext(l2e_account.clone())
+                    .with_static_gas(GAS_FOR_NFT_MINT_CALLBACK)
+                    .nft_mint_callback(grant_id),
+            );
+
+        // store nft tokenid and spender address, pending until nft_mint_callback confirms the mint
+        self.pending_mints.insert(new_token_id.clone());
+        let nfts: &mut Vector<NftEntry> =
+            self.nfts.get_mut(&owner).expect("No nft owner found for owner");
+        nfts.push(NftEntry {
+            grant_id,
+            campaign_id,
+            spender,
+            token_id: new_token_id,
+            claimed: false,
+            erc721_address: current_erc721,
+        });
+        log!("Store nft tokenid and spender address.");
+        grant_id
+    }
+
+    /// Token id of the next nft minted by the l2e contract.
+    fn next_token_id(&mut self) -> TokenId {
+        let token_id: u128 = (self.token_id_num).into();
+        self.token_id_num = U128::from(token_id + 1);
+        (token_id + 1).to_string()
+    }
+
+    /// Transfer the nft of a grant to receiver_id, or to spender without one, resolved by nft_transfer_callback.
+    fn start_nft_claim(
+        &mut self,
+        grant_id: GrantId,
+        spender: &AccountId,
+        receiver_id: Option<AccountId>,
+        erc721_address: &AccountId,
+        token_id: &TokenId,
+    ) {
+        self.nft_claims_in_flight.insert(token_id.clone());
+        let receiver = receiver_id.clone().unwrap_or_else(|| spender.clone());
+        let promise = ext_nft_core::ext(erc721_address.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .nft_transfer(receiver, token_id.clone(), None, None);
+
+        let _transfer_nft_promise =
+            promise.then(Self::ext(env::current_account_id()).nft_transfer_callback(grant_id, receiver_id));
+    }
+
+    /// Settle a grant paid by transfer_balances_from or claim_all with the outcome of each of its legs.
+    /// The grant is Paid when every leg succeeded, otherwise the unpaid legs are written back to `balances` and `nfts`.
+    /// receiver_id is the account paid when it is not the spender.
+    #[allow(clippy::too_many_arguments)]
+    fn settle_balances_payout(
+        &mut self,
+        spender: &AccountId,
+        receiver_id: Option<&AccountId>,
+        balance_entry: BalanceEntry,
+        nft_entry: NftEntry,
+        main_token_paid: bool,
+        ft_paid: bool,
+        basket_paid: Vec<bool>,
+    ) -> bool {
+        let owner = balance_entry.owner.clone();
+        let grant_id = balance_entry.grant_id;
+        let campaign_id = balance_entry.campaign_id;
+
+        let paid_main_token = if main_token_paid { balance_entry.main_token_amount } else { NearToken::from_near(0) };
+        let paid_ft = if ft_paid { balance_entry.ft_escrowed.0 } else { 0 };
+        if paid_main_token > NearToken::from_near(0) {
+            self.escrow_pay(&owner, EscrowAsset::MainToken, paid_main_token.as_yoctonear());
+        }
+        if paid_ft > 0 {
+            self.escrow_pay(&owner, EscrowAsset::Ft(balance_entry.erc20_address.clone()), paid_ft);
+        }
+        for (entry, paid) in balance_entry.ft_basket.iter().zip(&basket_paid) {
+            if *paid && entry.is_funded() {
+                self.escrow_pay(&owner, EscrowAsset::Ft(entry.ft_contract.clone()), entry.ft_escrowed.0);
+            }
+        }
+
+        // the contract of each ft amount is only logged for a grant with an ft_basket
+        let ft_contract_ids: Vec<String> = if balance_entry.ft_basket.is_empty() {
+            vec![]
+        } else {
+            std::iter::once(&balance_entry.erc20_address)
+                .chain(balance_entry.ft_basket.iter().map(|x| &x.ft_contract))
+                .map(|x| x.to_string())
+                .collect()
+        };
+
+        if main_token_paid && ft_paid && basket_paid.iter().all(|x| *x) {
+            self.try_set_grant_status(grant_id, GrantStatus::Paid);
+            let ft_token_amount = std::iter::once(paid_ft)
+                .chain(balance_entry.ft_basket.iter().map(|x| x.ft_escrowed.0))
+                .map(|x| x.to_string())
+                .collect();
+            let transfer_balances_from_log = EventLog {
+                standard: CONSTRACT_NAME.to_string(),
+                version: CONSTRACT_VERSION.to_string(),
+                event: EventLogVariant::TransferBalacnesFrom(vec![BalacnesTransferLog {
+                    authorized_id: Some(owner.to_string()),
+                    old_owner_id: env::current_account_id().to_string(),
+                    new_owner_id: receiver_id.unwrap_or(spender).to_string(),
+                    main_token_amount: vec![paid_main_token.as_yoctonear().to_string()],
+                    ft_token_amount,
+                    ft_contract_ids,
+                    spender_id: receiver_id.map(|_| spender.to_string()),
+                    grant_id: Some(grant_id),
+                    campaign_id: Some(campaign_id),
+                    memo: Some("L2E Team".to_string()),
+                }]),
+            };
+
+            env::log_str(&transfer_balances_from_log.to_string());
+            return true;
+        }
+
+        // Restore the unpaid part of the grant, it is marked as partially paid by the zero amounts.
+        // A paid basket entry keeps only its ft pledged but never escrowed.
+        self.try_set_grant_status(grant_id, GrantStatus::NftClaimed);
+        let unpaid_main_token = balance_entry.main_token_amount.saturating_sub(paid_main_token);
+        let unpaid_ft = balance_entry.ft_escrowed.0.saturating_sub(paid_ft);
+        let unpaid_basket: Vec<BasketEntry> = balance_entry
+            .ft_basket
+            .iter()
+            .zip(&basket_paid)
+            .map(|(entry, paid)| {
+                if !paid {
+                    return entry.clone();
+                }
+                BasketEntry {
+                    ft_pledged: U128::from(entry.ft_pledged.0.saturating_sub(entry.ft_escrowed.0)),
+                    ft_escrowed: U128::from(0),
+                    ..entry.clone()
+                }
+            })
+            .collect();
+        let ft_token_amount = std::iter::once(unpaid_ft)
+            .chain(unpaid_basket.iter().map(|x| x.ft_escrowed.0))
+            .map(|x| x.to_string())
+            .collect();
+        self.restore_grant(
+            spender,
+            BalanceEntry {
+                main_token_amount: unpaid_main_token,
+                ft_pledged: U128::from(balance_entry.ft_pledged.0.saturating_sub(paid_ft)),
+                ft_escrowed: U128::from(unpaid_ft),
+                ft_basket: unpaid_basket,
+                ..balance_entry
+            },
+            Some(nft_entry),
+        );
+
+        let transfer_balances_failed_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: EventLogVariant::TransferBalancesFromFailed(vec![BalacnesTransferLog {
+                authorized_id: Some(owner.to_string()),
+                old_owner_id: env::current_account_id().to_string(),
+                new_owner_id: receiver_id.unwrap_or(spender).to_string(),
+                main_token_amount: vec![unpaid_main_token.as_yoctonear().to_string()],
+                ft_token_amount,
+                ft_contract_ids,
+                spender_id: receiver_id.map(|_| spender.to_string()),
+                grant_id: Some(grant_id),
+                campaign_id: Some(campaign_id),
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+
+        env::log_str(&transfer_balances_failed_log.to_string());
+
+        false
+    }
+
+    /// Grant list of a spender in `balances`, stored under its own hashed prefix.
+    fn new_spender_balances(spender: &AccountId) -> Vector<BalanceEntry> {
+        Vector::new(StorageKey::BalancesPerSpender {
+            spender_hash: env::sha256_array(spender.as_bytes()),
+        })
+    }
+
+    /// Nft list of an owner in `nfts`, stored under its own hashed prefix.
+    fn new_owner_nfts(owner: &AccountId) -> Vector<NftEntry> {
+        Vector::new(StorageKey::NftsPerOwner {
+            owner_hash: env::sha256_array(owner.as_bytes()),
+        })
+    }
+
+    /// Write a grant back to `balances` of spender and its nft back to `nfts` of the owner, after a failed payout.
+    fn restore_grant(&mut self, spender: &AccountId, balance_entry: BalanceEntry, nft_entry: Option<NftEntry>) {
+        let owner = balance_entry.owner.clone();
+        if !self.balances.contains_key(spender) {
+            self.balances.insert(spender.clone(), Self::new_spender_balances(spender));
+        }
+        let balances: &mut Vector<BalanceEntry> =
+            self.balances.get_mut(spender).expect("No balance found for spender");
+        balances.push(balance_entry);
+
+        if let Some(nft_entry) = nft_entry {
+            if !self.nfts.contains_key(&owner) {
+                self.nfts.insert(owner.clone(), Self::new_owner_nfts(&owner));
+            }
+            let nfts: &mut Vector<NftEntry> =
+                self.nfts.get_mut(&owner).expect("No nft found for owner");
+            nfts.push(nft_entry);
+        }
+    }
+
+    /// Remove a grant and return its main token, escrowed ft, escrowed ft_basket and the nft still held
+    /// by the l2e contract to the owner, resolved by revoke_grant_callback.
+    /// The whole grant is given back to the budget of its campaign, revoke_grant_callback charges what fails to return.
+    fn return_grant_to_owner(&mut self, grant_id: GrantId, expired: bool) {
+        let GrantRecord { campaign_id, owner, spender, .. } = self.grant_record(grant_id);
+        self.set_grant_status(grant_id, if expired { GrantStatus::Expired } else { GrantStatus::Revoked });
+        let balance_entry = self.take_balance_entry(grant_id).expect("No grant found for spender");
+        let nft_entry = self.take_nft_entry(grant_id);
+
+        // return main token, escrowed ft and the custody nft to the owner, zero legs are skipped.
+        let mut refund: Option<Promise> = None;
+        if balance_entry.main_token_amount > NearToken::from_near(0) {
+            refund = Some(Promise::new(owner.clone()).transfer(balance_entry.main_token_amount));
+        }
+        if balance_entry.ft_escrowed.0 > 0 {
+            let ft_promise = ext_ft_core::ext(balance_entry.erc20_address.clone())
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .ft_transfer(owner.clone(), balance_entry.ft_escrowed, Some("L2E grant revoked".to_string()));
+            refund = Some(match refund {
+                Some(promise) => promise.and(ft_promise),
+                None => ft_promise,
+            });
+        }
+        let memo = Some("L2E grant revoked".to_string());
+        refund = Self::basket_payout(refund, &owner, &balance_entry.ft_basket, memo, false);
+
+        let mut returned_nft: Option<NftEntry> = None;
+        if let Some(nft_entry) = nft_entry {
+            if !nft_entry.claimed {
+                let nft_promise = ext_nft_core::ext(nft_entry.erc721_address.clone())
+                    .with_attached_deposit(NearToken::from_yoctonear(1))
+                    .nft_transfer(owner.clone(), nft_entry.token_id.clone(), None, Some("L2E grant revoked".to_string()));
+                refund = Some(match refund {
+                    Some(promise) => promise.and(nft_promise),
+                    None => nft_promise,
+                });
+                returned_nft = Some(nft_entry);
+            }
+        }
+
+        self.release_campaign(campaign_id, balance_entry.main_token_amount, balance_entry.ft_pledged.0);
+
+        match refund {
+            Some(refund) => {
+                let _revoke_grant_promise = refund.then(
+                    Self::ext(env::current_account_id()).revoke_grant_callback(spender, balance_entry, returned_nft, expired),
+                );
+            }
+            None => Self::log_grant_returned(
+                expired,
+                GrantLog {
+                    owner_id: owner.to_string(),
+                    spender_id: spender.to_string(),
+                    token_ids: vec![],
+                    main_token_amount: "0".to_string(),
+                    ft_token_amount: "0".to_string(),
+                    grant_id: Some(grant_id),
+                    campaign_id: Some(campaign_id),
+                    memo: Some("L2E Team".to_string()),
+                },
+            ),
+        }
+    }
+
+    fn grant_record(&self, grant_id: GrantId) -> GrantRecord {
+        self.grants.get(&grant_id).expect("Grant not found").clone()
+    }
+
+    /// Balance entry of a grant, None once it is paid or returned.
+    fn balance_entry(&self, grant_id: GrantId) -> Option<BalanceEntry> {
+        let spender = &self.grants.get(&grant_id)?.spender;
+        self.balances.get(spender)?.iter().find(|x| x.grant_id == grant_id).cloned()
+    }
+
+    /// Nft entry of a grant, None once it is paid or returned.
+    fn nft_entry(&self, grant_id: GrantId) -> Option<NftEntry> {
+        let owner = &self.grants.get(&grant_id)?.owner;
+        self.nfts.get(owner)?.iter().find(|x| x.grant_id == grant_id).cloned()
+    }
+
+    fn grant_mut(&mut self, grant_id: GrantId) -> &mut BalanceEntry {
+        let spender = self.grant_record(grant_id).spender;
+        self.balances
+            .get_mut(&spender)
+            .and_then(|balances| balances.iter_mut().find(|x| x.grant_id == grant_id))
+            .expect("No balance found for grant")
+    }
+
+    /// Remove the balance entry of a grant from `balances` of its spender.
+    fn take_balance_entry(&mut self, grant_id: GrantId) -> Option<BalanceEntry> {
+        let spender = self.grant_record(grant_id).spender;
+        let balances = self.balances.get_mut(&spender)?;
+        let index = balances.iter().position(|x| x.grant_id == grant_id)?;
+        Some(balances.swap_remove(index as u32))
+    }
+
+    /// Remove the nft entry of a grant from `nfts` of its owner.
+    fn take_nft_entry(&mut self, grant_id: GrantId) -> Option<NftEntry> {
+        let owner = self.grant_record(grant_id).owner;
+        let nfts = self.nfts.get_mut(&owner)?;
+        let index = nfts.iter().position(|x| x.grant_id == grant_id)?;
+        Some(nfts.swap_remove(index as u32))
+    }
+
+    /// Whether the spender claimed the nft of a grant.
+    fn is_nft_claimed(&self, grant_id: GrantId) -> bool {
+        self.nft_entry(grant_id).is_some_and(|x| x.claimed)
+    }
+
+    /// Whether the promise at result_index of a callback succeeded, its returned value is not read.
+    fn promise_succeeded(result_index: u64) -> bool {
+        !matches!(env::promise_result_checked(result_index, 0), Err(PromiseError::Failed))
+    }
+
+    /// Transfer main token and ft from the l2e contract to receiver, zero legs are skipped.
+    /// None if both legs are zero. A callback reads the results with `payout_results`.
+    /// With register_receiver the ft leg first registers receiver with erc20_address, see `ft_transfer_to`.
+    fn payout(
+        receiver: &AccountId,
+        main_token_amount: NearToken,
+        erc20_address: &AccountId,
+        ft_amount: U128,
+        register_receiver: bool,
+    ) -> Option<Promise> {
+        let mut payout: Option<Promise> = None;
+        if main_token_amount > NearToken::from_near(0) {
+            payout = Some(Promise::new(receiver.clone()).transfer(main_token_amount));
+        }
+
+        // cross contract call to erc20
+        if ft_amount.0 > 0 {
+            let ft_promise = Self::ft_transfer_to(erc20_address, receiver, ft_amount, None, register_receiver);
+            payout = Some(match payout {
+                Some(near_promise) => near_promise.and(ft_promise),
+                None => ft_promise,
+            });
+        }
+
+        payout
+    }
+
+    /// ft_transfer of ft_contract to receiver. With register_receiver, receiver is registered by storage_deposit first,
+    /// an account that is already registered gets the deposit refunded, the result is the one of ft_transfer.
+    fn ft_transfer_to(
+        ft_contract: &AccountId,
+        receiver: &AccountId,
+        ft_amount: U128,
+        memo: Option<String>,
+        register_receiver: bool,
+    ) -> Promise {
+        let ft_promise = ext_ft_core::ext(ft_contract.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer(receiver.clone(), ft_amount, memo);
+        if !register_receiver {
+            return ft_promise;
+        }
+
+        ext_ft_contract::ext(ft_contract.clone())
+            .with_attached_deposit(FT_STORAGE_DEPOSIT)
+            .storage_deposit(Some(receiver.clone()), Some(true))
+            .then(ft_promise)
+    }
+
+    /// The receiver_id a spender claims to, None when it is not given or is the spender itself.
+    /// Only another receiver needs to be registered with the ft contracts, the spender is registered by approve_for_spender.
+    fn claim_receiver(spender: &AccountId, receiver_id: Option<AccountId>) -> Option<AccountId> {
+        receiver_id.filter(|x| x != spender)
+    }
+
+    /// Whether the main token and ft legs of a `payout` succeeded, a zero leg counts as paid.
+    fn payout_results(main_token_amount: NearToken, ft_amount: U128) -> (bool, bool) {
+        let mut result_index = 0;
+        let mut main_token_paid = true;
+        if main_token_amount > NearToken::from_near(0) {
+            main_token_paid = Self::promise_succeeded(result_index);
+            result_index += 1;
+        }
+        let mut ft_paid = true;
+        if ft_amount.0 > 0 {
+            ft_paid = Self::promise_succeeded(result_index);
+        }
+
+        (main_token_paid, ft_paid)
+    }
+
+    /// Log a grant returned to its owner, as reclaim_expired for an expired grant and as revoke_grant otherwise.
+    fn log_grant_returned(expired: bool, grant_log: GrantLog) {
+        let event = if expired {
+            EventLogVariant::ReclaimExpired(vec![grant_log])
+        } else {
+            EventLogVariant::RevokeGrant(vec![grant_log])
+        };
+        let grant_returned_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event,
+        };
+
+        env::log_str(&grant_returned_log.to_string());
+    }
+
+    /// Cross contract call ft_metadata, ft_metadata_callback caches the decimals.
+    fn fetch_ft_decimals(erc20_address: AccountId) {
+        let _ft_metadata_promise = ext_ft_contract::ext(erc20_address.clone())
+            .ft_metadata()
+            .then(Self::ext(env::current_account_id()).ft_metadata_callback(erc20_address));
+    }
+
+    fn reward_view(&self, entry: &BalanceEntry) -> RewardView {
+        let ft_decimals = self.ft_decimals.get(&entry.erc20_address).copied();
+        RewardView {
+            grant_id: entry.grant_id,
+            campaign_id: entry.campaign_id,
+            owner: entry.owner.clone(),
+            main_token_amount: U128::from(entry.main_token_amount.as_yoctonear()),
+            main_token_amount_display: format_amount(entry.main_token_amount.as_yoctonear(), NEAR_DECIMALS),
+            erc20_address: entry.erc20_address.clone(),
+            ft_amount: entry.ft_escrowed,
+            ft_decimals,
+            ft_amount_display: ft_decimals.map(|decimals| format_amount(entry.ft_escrowed.0, decimals)),
+            ft_basket: self.basket_view(&entry.ft_basket),
+        }
+    }
+}
+
+/// Human-readable value of a raw amount, e.g. 1500000 with 6 decimals is "1.5".
+pub fn format_amount(raw: u128, decimals: u8) -> String {
+    if decimals == 0 {
+        return raw.to_string();
+    }
+    let digits = format!("{:0>width$}", raw, width = decimals as usize + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals as usize);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// L2eTop state of the deployed contract, only read by `migrate`.
+#[near]
+struct OldL2eTop {
+    greeting: String,
+    // spenderid -> <(ownerid, maintoken balance, token balance)>
+    balances: IterableMap<AccountId, Vector<(AccountId, NearToken, NearToken)>>,
+    // ownerid -> <(spenderid, nft tokenid, claimed true/false)>
+    nfts: IterableMap<AccountId, Vector<(AccountId, TokenId, bool)>>,
+    erc20_address: Vector<AccountId>,
+    erc721_address: Vector<AccountId>,
+    token_id_num: U128,
+    admin_address: IterableSet<AccountId>,
+    auth_token_owner: IterableSet<AccountId>,
+}
+
+/// `msg` of the `ft_transfer_call` an owner uses to fund the ft part of a grant.
+/// e.g. `{"grant_id": 3}`, the grant_id returned by `approve_for_spender`.
+/// With `"top_up": true` the whole amount is added to the pledge of the grant, see `top_up_grant`.
+#[near(serializers = [json])]
+pub struct FtOnTransferMsg {
+    pub grant_id: GrantId,
+    #[serde(default)]
+    pub top_up: bool,
+}
+
+#[near]
+impl FungibleTokenReceiver for L2eTop {
+    /// Fund the ft pledged by `approve_for_spender` with tokens that really arrived.
+    /// `sender_id` is the owner of the grant and the calling ft contract must be the one the grant was approved with or a token of its ft_basket.
+    /// Everything above the outstanding pledge is returned to the owner by `ft_resolve_transfer`.
+    /// With `{"merkle_drop_id": <drop_id>}` as msg it funds the ft of a merkle drop of `sender_id` instead.
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let ft_contract = env::predecessor_account_id();
+        let owner = sender_id;
+
+        if !self.erc20_address.iter().any(|x| x == &ft_contract) {
+            log!("ft_on_transfer: {} is not a registered ft contract.", ft_contract);
+            return PromiseOrValue::Value(amount);
+        }
+
+        if let Ok(MerkleDropFundMsg { merkle_drop_id }) = serde_json::from_str::<MerkleDropFundMsg>(&msg) {
+            return self.fund_merkle_drop(&owner, &ft_contract, merkle_drop_id, amount);
+        }
+        let FtOnTransferMsg { grant_id, top_up } = match serde_json::from_str::<FtOnTransferMsg>(&msg) {
+            Ok(fund_msg) => fund_msg,
+            Err(_) => {
+                log!("ft_on_transfer: msg should be {{\"grant_id\": <grant_id>}}.");
+                return PromiseOrValue::Value(amount);
+            }
+        };
+
+        let GrantRecord { campaign_id, spender, .. } = match self.grants.get(&grant_id) {
+            Some(record) if record.owner == owner => record.clone(),
+            _ => {
+                log!("ft_on_transfer: {} has not approved grant {}.", owner, grant_id);
+                return PromiseOrValue::Value(amount);
+            }
+        };
+        let pending = self.nft_entry(grant_id).is_some_and(|x| self.pending_mints.contains(&x.token_id));
+        if pending {
+            log!("ft_on_transfer: nft for {} is not minted yet.", spender);
+            return PromiseOrValue::Value(amount);
+        }
+        let campaign_token = self.balance_entry(grant_id).is_some_and(|x| x.erc20_address == ft_contract);
+        if top_up {
+            if let Some(reason) = self.grant_change_blocker(grant_id) {
+                log!("ft_on_transfer: grant {} can't be topped up, {}.", grant_id, reason);
+                return PromiseOrValue::Value(amount);
+            }
+            let over_budget = self
+                .campaigns
+                .get(&campaign_id)
+                .is_some_and(|x| !x.has_budget_for(NearToken::from_near(0), amount));
+            if campaign_token && over_budget {
+                log!("ft_on_transfer: campaign {} budget exceeded.", campaign_id);
+                return PromiseOrValue::Value(amount);
+            }
+        }
+
+        let owner_value = match self.balances.get_mut(&spender) {
+            Some(owner_value) => owner_value,
+            None => {
+                log!("ft_on_transfer: no balance approved for spender {}.", spender);
+                return PromiseOrValue::Value(amount);
+            }
+        };
+        let index = match owner_value.iter().position(|x| x.grant_id == grant_id) {
+            Some(index) => index as u32,
+            None => {
+                log!("ft_on_transfer: grant {} is already paid or returned.", grant_id);
+                return PromiseOrValue::Value(amount);
+            }
+        };
+        if owner_value[index].is_expired(env::block_timestamp()) {
+            log!("ft_on_transfer: grant of {} for {} is expired.", owner, spender);
+            return PromiseOrValue::Value(amount);
+        }
+
+        // the erc20 of the grant or a token of its ft_basket, only the outstanding pledge is escrowed
+        let balance_entry = &mut owner_value[index];
+        let (ft_pledged, ft_escrowed) = if campaign_token {
+            (&mut balance_entry.ft_pledged, &mut balance_entry.ft_escrowed)
+        } else if let Some(basket_entry) = balance_entry.ft_basket.iter_mut().find(|x| x.ft_contract == ft_contract) {
+            (&mut basket_entry.ft_pledged, &mut basket_entry.ft_escrowed)
+        } else {
+            log!("ft_on_transfer: grant of {} is funded with {}.", spender, balance_entry.erc20_address);
+            return PromiseOrValue::Value(amount);
+        };
+        if top_up {
+            *ft_pledged = U128::from(ft_pledged.0 + amount.0);
+        }
+        let outstanding = ft_pledged.0.saturating_sub(ft_escrowed.0);
+        let accepted = std::cmp::min(amount.0, outstanding);
+        *ft_escrowed = U128::from(ft_escrowed.0 + accepted);
+        if accepted > 0 {
+            self.escrow_deposit(&owner, EscrowAsset::Ft(ft_contract.clone()), accepted);
+        }
+        if top_up {
+            // the campaign budget only counts the erc20 of the grant
+            if campaign_token {
+                self.charge_campaign(campaign_id, NearToken::from_near(0), amount.0);
+            }
+            self.log_grant_change(grant_id, true, NearToken::from_near(0), amount.0);
+        }
+
+        let unused = amount.0 - accepted;
+        let ft_on_transfer_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: EventLogVariant::FtOnTransfer(vec![FtFundLog {
+                ft_contract_id: ft_contract.to_string(),
+                owner_id: owner.to_string(),
+                spender_id: spender.to_string(),
+                ft_token_amount: accepted.to_string(),
+                unused_amount: unused.to_string(),
+                grant_id: Some(grant_id),
+                campaign_id: Some(campaign_id),
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+
+        env::log_str(&ft_on_transfer_log.to_string());
+
+        PromiseOrValue::Value(U128::from(unused))
+    }
+}
+
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::Deserialize;
+use near_sdk::serde_json;
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
 struct Input {
     keys: Vec<Base64VecU8>,
 }
@@ -783,6 +2145,8 @@ pub fn clean() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::*;
+    use near_sdk::{testing_env, PromiseResult};
 
     #[test]
     fn test_init_contract() {
@@ -841,9 +2205,9 @@ mod tests {
         assert_eq!(contract.get_admin_address(), vec![ env::predecessor_account_id().to_string(), "new_admin.near".to_owned()]);
         assert_eq!(contract.get_auth_token_owner(), vec![ env::predecessor_account_id().to_string(), "new_auth.near".to_owned()]);
 
-        assert_eq!(contract.get_all_spender_claim_for_owner(), Some(vec![("bob.near".to_string(), "0".to_string(), false)]));
-        assert_eq!(contract.get_all_owner_rewards_for_spender(), Some(vec![("bob.near".to_string(), 0, 0)]));
-        assert_eq!(contract.get_allowances_for_spender("owner.near".parse().unwrap()), Some((0,0)));
+        assert_eq!(contract.get_all_spender_claim_for_owner(None), Some(vec![("bob.near".to_string(), "0".to_string(), false, 0)]));
+        assert_eq!(contract.get_all_owner_rewards_for_spender(None), Some(vec![("bob.near".to_string(), U128::from(0), U128::from(0), 0)]));
+        assert_eq!(contract.get_allowances_for_spender("owner.near".parse().unwrap(), None), Some((U128::from(0), U128::from(0))));
 
         assert_eq!(contract.balances.len(), 2);
         assert!(contract.balances.contains_key(&env::predecessor_account_id()));
@@ -854,4 +2218,667 @@ mod tests {
         assert!(contract.balances.contains_key(&new_auth));
     }
 
+    #[test]
+    fn test_ft_on_transfer_escrows_pledged_amount() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let erc721: AccountId = "erc721.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init(erc20.clone(), erc721);
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(100));
+
+        // unregistered ft contract is refunded in full
+        testing_env!(context(&"fake.near".parse().unwrap()).build());
+        let msg = format!(r#"{{"grant_id":{}}}"#, grant_id);
+        match contract.ft_on_transfer(owner.clone(), U128::from(150), msg.clone()) {
+            PromiseOrValue::Value(unused) => assert_eq!(unused, U128::from(150)),
+            PromiseOrValue::Promise(_) => panic!("ft_on_transfer should not return a promise"),
+        }
+
+        // only the owner of the grant can fund it
+        testing_env!(context(&erc20).build());
+        match contract.ft_on_transfer(spender.clone(), U128::from(150), msg.clone()) {
+            PromiseOrValue::Value(unused) => assert_eq!(unused, U128::from(150)),
+            PromiseOrValue::Promise(_) => panic!("ft_on_transfer should not return a promise"),
+        }
+
+        // only the pledged amount is kept, the rest is returned
+        match contract.ft_on_transfer(owner.clone(), U128::from(150), msg) {
+            PromiseOrValue::Value(unused) => assert_eq!(unused, U128::from(50)),
+            PromiseOrValue::Promise(_) => panic!("ft_on_transfer should not return a promise"),
+        }
+
+        let grant = contract
+            .balances
+            .get(&spender)
+            .unwrap()
+            .iter()
+            .find(|x| x.owner == owner)
+            .unwrap()
+            .clone();
+        assert_eq!(grant.ft_pledged, U128::from(100));
+        assert_eq!(grant.ft_escrowed, U128::from(100));
+    }
+
+    #[test]
+    fn test_transfer_balances_callback_restores_failed_leg() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let erc721: AccountId = "erc721.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init(erc20.clone(), erc721);
+        let (grant_id, token_id) =
+            approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(100));
+
+        testing_env!(context(&erc20).build());
+        let msg = format!(r#"{{"grant_id":{}}}"#, grant_id);
+        let _ = contract.ft_on_transfer(owner.clone(), U128::from(100), msg);
+
+        testing_env!(context(&spender).build());
+        contract.transfer_nft_from(grant_id, None, None, None);
+        contract.nft_transfer_callback(grant_id, None, Ok(()));
+
+        assert!(contract.transfer_balances_from(grant_id, None));
+        assert!(contract.balances.get(&spender).unwrap().iter().all(|x| x.owner != owner));
+
+        // main token transfer succeeded, ft_transfer failed
+        callback_env(vec![PromiseResult::Successful(vec![]), PromiseResult::Failed]);
+        assert!(!contract.transfer_balances_callback(
+            spender.clone(),
+            BalanceEntry {
+                grant_id,
+                campaign_id: DEFAULT_CAMPAIGN_ID,
+                owner: owner.clone(),
+                main_token_amount: NearToken::from_near(1),
+                ft_pledged: U128::from(100),
+                ft_escrowed: U128::from(100),
+                erc20_address: "erc20.near".parse().unwrap(),
+                expires_at: None,
+                stages: vec![],
+                vesting: None,
+                ft_basket: vec![],
+            },
+            NftEntry {
+                grant_id,
+                campaign_id: DEFAULT_CAMPAIGN_ID,
+                spender: spender.clone(),
+                token_id: token_id.clone(),
+                claimed: true,
+                erc721_address: "erc721.near".parse().unwrap(),
+            },
+            None,
+        ));
+
+        let grant = contract
+            .balances
+            .get(&spender)
+            .unwrap()
+            .iter()
+            .find(|x| x.owner == owner)
+            .unwrap()
+            .clone();
+        assert_eq!(grant.main_token_amount, NearToken::from_near(0));
+        assert_eq!(grant.ft_escrowed, U128::from(100));
+        assert!(contract.nfts.get(&owner).unwrap().iter().any(|x| x.spender == spender && x.token_id == token_id && x.claimed));
+    }
+
+    #[test]
+    fn test_nft_claimed_only_after_transfer_callback() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let erc721: AccountId = "erc721.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init(erc20, erc721);
+        let (grant_id, token_id) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        testing_env!(context(&spender).build());
+        assert!(contract.transfer_nft_from(grant_id, None, None, None));
+        assert!(contract.nft_claims_in_flight.contains(&token_id));
+        assert!(contract.nfts.get(&owner).unwrap().iter().any(|x| x.token_id == token_id && !x.claimed));
+
+        // a failed nft_transfer keeps the nft unclaimed and releases the lock
+        assert!(!contract.nft_transfer_callback(grant_id, None, Err(PromiseError::Failed)));
+        assert!(!contract.nft_claims_in_flight.contains(&token_id));
+        assert!(contract.nfts.get(&owner).unwrap().iter().any(|x| x.token_id == token_id && !x.claimed));
+
+        assert!(contract.transfer_nft_from(grant_id, None, None, None));
+        assert!(contract.nft_transfer_callback(grant_id, None, Ok(())));
+        assert!(!contract.nft_claims_in_flight.contains(&token_id));
+        assert!(contract.nfts.get(&owner).unwrap().iter().any(|x| x.token_id == token_id && x.claimed));
+    }
+
+    #[test]
+    fn test_claim_to_other_receiver_logs_spender_and_receiver() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+        let receiver: AccountId = "fund.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        testing_env!(context(&spender).build());
+        assert!(contract.transfer_nft_from(grant_id, Some(receiver.clone()), None, None));
+        assert!(contract.nft_transfer_callback(grant_id, Some(receiver.clone()), Ok(())));
+        let logs = near_sdk::test_utils::get_logs();
+        let nft_log = logs.iter().find(|x| x.contains(r#""event":"transfer_nft_from""#)).unwrap();
+        assert!(nft_log.contains(r#""new_owner_id":"fund.near","token_ids":["#));
+        assert!(nft_log.contains(r#""spender_id":"spender.near""#));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::NftClaimed));
+
+        // the receiver stays the spender when it is given as receiver_id
+        assert_eq!(L2eTop::claim_receiver(&spender, Some(spender.clone())), None);
+
+        let balance_entry = contract.balance_entry(grant_id).unwrap();
+        let nft_entry = contract.nft_entry(grant_id).unwrap();
+        assert!(contract.transfer_balances_from(grant_id, Some(receiver.clone())));
+        callback_env(vec![PromiseResult::Successful(vec![])]);
+        assert!(contract.transfer_balances_callback(spender, balance_entry, nft_entry, Some(receiver)));
+        let logs = near_sdk::test_utils::get_logs();
+        let balances_log = logs.iter().find(|x| x.contains(r#""event":"transfer_balacnes_from""#)).unwrap();
+        assert!(balances_log.contains(r#""new_owner_id":"fund.near""#));
+        assert!(balances_log.contains(r#""spender_id":"spender.near""#));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::Paid));
+    }
+
+    #[test]
+    #[should_panic(expected = "Nft claim is already in progress")]
+    fn test_transfer_nft_from_rejects_claim_in_flight() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        testing_env!(context(&spender).build());
+        contract.transfer_nft_from(grant_id, None, None, None);
+        contract.transfer_nft_from(grant_id, None, None, None);
+    }
+
+    #[test]
+    fn test_nft_mint_callback_reverts_grant_on_failure() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_near(2)).build());
+        let grant_id = contract
+            .approve_for_spender(DEFAULT_CAMPAIGN_ID, spender.clone(), NearToken::from_near(1), U128::from(0), None, None, None, None, None)
+            .unwrap();
+        let token_id = contract.nft_entry(grant_id).unwrap().token_id;
+        assert!(contract.pending_mints.contains(&token_id));
+
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::PendingMint));
+        assert!(contract.nft_mint_callback(grant_id, Err(PromiseError::Failed)).is_none());
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::Failed));
+        assert!(!contract.pending_mints.contains(&token_id));
+        assert!(contract.nfts.get(&owner).unwrap().iter().all(|x| x.token_id != token_id));
+        assert!(contract.balances.get(&spender).unwrap().iter().all(|x| x.owner != owner));
+
+        let ledger = contract.get_escrow_ledger(owner);
+        assert_eq!(ledger.main_token.refunded, U128::from(NearToken::from_near(1).as_yoctonear()));
+        assert_eq!(ledger.main_token.committed, U128::from(0));
+    }
+
+    #[test]
+    fn test_spender_grants_kept_apart_in_storage() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender1: AccountId = "spender1.near".parse().unwrap();
+        let spender2: AccountId = "spender2.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        approve_and_mint(&mut contract, &owner, &spender1, NearToken::from_near(1), U128::from(0));
+        approve_and_mint(&mut contract, &owner, &spender2, NearToken::from_near(2), U128::from(0));
+
+        // write the state and read it back like a new transaction would
+        env::state_write(&contract);
+        drop(contract);
+        let contract: L2eTop = env::state_read().unwrap();
+
+        let spender1_balances: Vec<_> = contract.balances.get(&spender1).unwrap().iter().cloned().collect();
+        let spender2_balances: Vec<_> = contract.balances.get(&spender2).unwrap().iter().cloned().collect();
+        assert_eq!(spender1_balances.len(), 1);
+        assert_eq!(spender1_balances[0].main_token_amount, NearToken::from_near(1));
+        assert_eq!(spender2_balances.len(), 1);
+        assert_eq!(spender2_balances[0].main_token_amount, NearToken::from_near(2));
+        assert_eq!(contract.nfts.get(&owner).unwrap().len(), 3);
+    }
+
+    type OldBalances = IterableMap<AccountId, Vector<(AccountId, NearToken, NearToken)>>;
+    type OldNfts = IterableMap<AccountId, Vector<(AccountId, TokenId, bool)>>;
+
+    fn old_state(balances: OldBalances, nfts: OldNfts) -> OldL2eTop {
+        let mut erc20_address = Vector::new(b"2");
+        erc20_address.push("erc20.near".parse().unwrap());
+        let mut erc721_address = Vector::new(b"7");
+        erc721_address.push("erc721.near".parse().unwrap());
+
+        OldL2eTop {
+            greeting: "Hello".to_string(),
+            balances,
+            nfts,
+            erc20_address,
+            erc721_address,
+            token_id_num: U128::from(10001),
+            admin_address: IterableSet::new(b"a"),
+            auth_token_owner: IterableSet::new(b"t"),
+        }
+    }
+
+    /// The placeholder vectors the deployed init created for the contract account.
+    fn old_placeholders() -> (OldBalances, OldNfts) {
+        let contract_account = env::current_account_id();
+        let mut old_balances = IterableMap::new(b"b");
+        let mut contract_balances = Vector::new(b"v");
+        contract_balances.push((contract_account.clone(), NearToken::from_near(0), NearToken::from_near(0)));
+        old_balances.insert(contract_account.clone(), contract_balances);
+        let mut old_nfts = IterableMap::new(b"n");
+        let mut contract_nfts = Vector::new(b"i");
+        contract_nfts.push((contract_account.clone(), TokenId::from("0"), false));
+        old_nfts.insert(contract_account, contract_nfts);
+        (old_balances, old_nfts)
+    }
+
+    #[test]
+    fn test_migrate_moves_grants_to_account_prefixes() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+        testing_env!(context(&owner).build());
+
+        // state as written by the deployed init and approve_for_spender
+        let (mut old_balances, mut old_nfts) = old_placeholders();
+        let mut spender_balances = Vector::new(b"b");
+        spender_balances.push((owner.clone(), NearToken::from_near(1), NearToken::from_near(100)));
+        old_balances.insert(spender.clone(), spender_balances);
+        let mut owner_nfts = Vector::new(b"n");
+        owner_nfts.push((spender.clone(), TokenId::from("10001"), true));
+        old_nfts.insert(owner.clone(), owner_nfts);
+
+        let old_state = old_state(old_balances, old_nfts);
+        env::state_write(&old_state);
+        drop(old_state);
+
+        let contract = L2eTop::migrate();
+        assert_eq!(contract.get_erc20_address(), vec!["erc20.near".to_owned()]);
+        assert_eq!(contract.token_id_num, U128::from(10001));
+        assert_eq!(
+            contract.balances.get(&spender).unwrap().get(0),
+            Some(&BalanceEntry {
+                grant_id: 1,
+                campaign_id: DEFAULT_CAMPAIGN_ID,
+                owner: owner.clone(),
+                main_token_amount: NearToken::from_near(1),
+                ft_pledged: U128::from(100),
+                ft_escrowed: U128::from(0),
+                erc20_address: "erc20.near".parse().unwrap(),
+                expires_at: None,
+                stages: vec![],
+                vesting: None,
+                ft_basket: vec![],
+            })
+        );
+        assert_eq!(
+            contract.nfts.get(&owner).unwrap().get(0),
+            Some(&NftEntry {
+                grant_id: 1,
+                campaign_id: DEFAULT_CAMPAIGN_ID,
+                spender: spender.clone(),
+                token_id: TokenId::from("10001"),
+                claimed: true,
+                erc721_address: "erc721.near".parse().unwrap(),
+            })
+        );
+        let grant = contract.get_grant(1).unwrap();
+        assert_eq!((grant.owner, grant.spender), (owner.clone(), spender.clone()));
+        assert_eq!(grant.status, GrantStatus::NftClaimed);
+        let placeholder = contract.get_grant(0).unwrap();
+        assert_eq!(placeholder.owner, env::current_account_id());
+        assert_eq!(placeholder.status, GrantStatus::Minted);
+        assert_eq!(contract.nfts.get(&env::current_account_id()).unwrap().get(0).unwrap().grant_id, 0);
+        assert_eq!(contract.next_grant_id, 2);
+        assert!(!env::storage_has_key(&[b"b".as_slice(), &0u32.to_le_bytes()].concat()));
+        assert!(!env::storage_has_key(&[b"n".as_slice(), &0u32.to_le_bytes()].concat()));
+        assert_eq!(
+            contract.get_escrow_ledger(owner).main_token.committed,
+            U128::from(NearToken::from_near(1).as_yoctonear())
+        );
+    }
+
+    #[test]
+    fn test_migrate_skips_entries_aliased_by_shared_prefixes() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let spenders: Vec<AccountId> = ["s1.near", "s2.near", "s3.near"].iter().map(|x| x.parse().unwrap()).collect();
+        testing_env!(context(&bob).build());
+
+        // bob grants to s1 and s2, carol to s3, each new vector overwrites index 0 of the shared prefix
+        let (mut old_balances, mut old_nfts) = old_placeholders();
+        let mut bob_nfts = Vector::new(b"n");
+        for (spender, (owner, amount)) in spenders.iter().zip([(&bob, 1), (&bob, 2), (&carol, 3)]) {
+            let mut spender_balances = Vector::new(b"b");
+            spender_balances.push((owner.clone(), NearToken::from_near(amount), NearToken::from_near(0)));
+            spender_balances.flush();
+            old_balances.insert(spender.clone(), spender_balances);
+            let token_id = TokenId::from((10000 + amount).to_string());
+            if *owner == bob {
+                bob_nfts.push((spender.clone(), token_id, false));
+                bob_nfts.flush();
+            } else {
+                let mut carol_nfts = Vector::new(b"n");
+                carol_nfts.push((spender.clone(), token_id, false));
+                carol_nfts.flush();
+                old_nfts.insert(carol.clone(), carol_nfts);
+            }
+        }
+        old_nfts.insert(bob.clone(), bob_nfts);
+
+        let old_state = old_state(old_balances, old_nfts);
+        env::state_write(&old_state);
+        drop(old_state);
+
+        // only carol's grant to s3 is listed the same way in the nfts and in the balances
+        let contract = L2eTop::migrate();
+        assert_eq!(contract.nfts.get(&bob).unwrap().len(), 0);
+        let carol_nfts: Vec<_> = contract.nfts.get(&carol).unwrap().iter().cloned().collect();
+        assert_eq!(carol_nfts.len(), 1);
+        assert_eq!((carol_nfts[0].grant_id, carol_nfts[0].token_id.as_str()), (1, "10003"));
+        assert_eq!(contract.balances.get(&spenders[0]).unwrap().len(), 0);
+        assert_eq!(contract.balances.get(&spenders[1]).unwrap().len(), 0);
+        let s3_balances: Vec<_> = contract.balances.get(&spenders[2]).unwrap().iter().cloned().collect();
+        assert_eq!(s3_balances.len(), 1);
+        assert_eq!((s3_balances[0].grant_id, s3_balances[0].main_token_amount), (1, NearToken::from_near(3)));
+        assert_eq!(contract.next_grant_id, 2);
+        assert_eq!(contract.get_grant(0).unwrap().owner, env::current_account_id());
+        assert_eq!(
+            contract.get_escrow_ledger(carol).main_token.committed,
+            U128::from(NearToken::from_near(3).as_yoctonear())
+        );
+        assert_eq!(contract.get_escrow_ledger(bob).main_token.committed, U128::from(0));
+    }
+
+    #[test]
+    fn test_grants_of_same_pair_claimed_separately() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let (first_grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+        let (second_grant_id, second_token_id) =
+            approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(2), U128::from(0));
+        assert_ne!(first_grant_id, second_grant_id);
+
+        let grant = contract.get_grant(second_grant_id).unwrap();
+        assert_eq!(grant.campaign_id, DEFAULT_CAMPAIGN_ID);
+        assert_eq!(grant.balance.unwrap().main_token_amount, NearToken::from_near(2));
+        assert_eq!(grant.nft.unwrap().token_id, second_token_id);
+
+        // claiming the second grant leaves the first one untouched
+        testing_env!(context(&spender).build());
+        assert!(contract.transfer_nft_from(second_grant_id, None, None, None));
+        assert!(contract.nft_transfer_callback(second_grant_id, None, Ok(())));
+        assert!(contract.transfer_balances_from(second_grant_id, None));
+        assert!(contract.balance_entry(second_grant_id).is_none());
+        assert!(!contract.nft_entry(first_grant_id).unwrap().claimed);
+        assert_eq!(
+            contract.get_allowances_for_spender(owner, None),
+            Some((U128::from(NearToken::from_near(1).as_yoctonear()), U128::from(0)))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Grant is not approved for spender")]
+    fn test_transfer_nft_from_rejects_grant_of_other_spender() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        testing_env!(context(&"other.near".parse().unwrap()).build());
+        contract.transfer_nft_from(grant_id, None, None, None);
+    }
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(0, 24), "0");
+        assert_eq!(format_amount(1_500_000, 6), "1.5");
+        assert_eq!(format_amount(1, 6), "0.000001");
+        assert_eq!(format_amount(10u128.pow(24), NEAR_DECIMALS), "1");
+        assert_eq!(format_amount(123, 0), "123");
+    }
+
+    #[test]
+    fn test_rewards_view_uses_cached_ft_decimals() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init(erc20.clone(), "erc721.near".parse().unwrap());
+        let (grant_id, _) =
+            approve_and_mint(&mut contract, &owner, &spender, NearToken::from_millinear(1500), U128::from(2_500_000));
+        testing_env!(context(&erc20).build());
+        let msg = format!(r#"{{"grant_id":{}}}"#, grant_id);
+        let _ = contract.ft_on_transfer(owner.clone(), U128::from(2_500_000), msg);
+
+        let rewards = contract.get_rewards_for_spender(spender.clone(), None);
+        assert_eq!(rewards.len(), 1);
+        assert_eq!(rewards[0].main_token_amount, U128::from(1_500_000_000_000_000_000_000_000));
+        assert_eq!(rewards[0].main_token_amount_display, "1.5");
+        assert_eq!(rewards[0].ft_amount, U128::from(2_500_000));
+        assert_eq!(rewards[0].ft_amount_display, None);
+
+        let metadata = FungibleTokenMetadata {
+            spec: "ft-1.0.0".to_string(),
+            name: "L2E".to_string(),
+            symbol: "L2E".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 6,
+        };
+        assert_eq!(contract.ft_metadata_callback(erc20.clone(), Ok(metadata)), Some(6));
+        assert_eq!(contract.get_ft_decimals(erc20), Some(6));
+
+        let rewards = contract.get_rewards_for_spender(spender, None);
+        assert_eq!(rewards[0].ft_decimals, Some(6));
+        assert_eq!(rewards[0].ft_amount_display, Some("2.5".to_string()));
+    }
+
+    #[test]
+    fn test_revoke_grant_returns_funds_to_owner() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+        let one_near = NearToken::from_near(1).as_yoctonear();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init(erc20.clone(), "erc721.near".parse().unwrap());
+        let (grant_id, token_id) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(100));
+        testing_env!(context(&erc20).build());
+        let msg = format!(r#"{{"grant_id":{}}}"#, grant_id);
+        let _ = contract.ft_on_transfer(owner.clone(), U128::from(100), msg);
+
+        testing_env!(context(&owner).build());
+        let balance_entry = contract.balance_entry(grant_id).unwrap();
+        let nft_entry = contract.nft_entry(grant_id).unwrap();
+        assert!(contract.revoke_grant(grant_id));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::Revoked));
+        assert!(contract.balances.get(&spender).unwrap().iter().all(|x| x.owner != owner));
+        assert!(contract.nfts.get(&owner).unwrap().iter().all(|x| x.token_id != token_id));
+
+        // main token and nft returned, ft_transfer failed
+        callback_env(vec![PromiseResult::Successful(vec![]), PromiseResult::Failed, PromiseResult::Successful(vec![])]);
+        assert!(!contract.revoke_grant_callback(spender.clone(), balance_entry, Some(nft_entry), false));
+        let grant = contract.balance_entry(grant_id).unwrap();
+        assert_eq!(grant.main_token_amount, NearToken::from_near(0));
+        assert_eq!(grant.ft_escrowed, U128::from(100));
+        assert!(contract.nfts.get(&owner).unwrap().iter().all(|x| x.token_id != token_id));
+        let ledger = contract.get_escrow_ledger(owner.clone());
+        assert_eq!(ledger.main_token.refunded, U128::from(one_near));
+        assert_eq!(ledger.main_token.committed, U128::from(0));
+        assert_eq!(ledger.ft[0].1.committed, U128::from(100));
+
+        // revoke the rest, only the ft is left to return
+        testing_env!(context(&owner).build());
+        assert!(contract.revoke_grant(grant_id));
+        callback_env(vec![PromiseResult::Successful(vec![])]);
+        assert!(contract.revoke_grant_callback(spender.clone(), grant, None, false));
+        assert!(contract.balances.get(&spender).unwrap().iter().all(|x| x.owner != owner));
+        let ledger = contract.get_escrow_ledger(owner);
+        assert_eq!(ledger.ft[0].1.refunded, U128::from(100));
+        assert_eq!(ledger.ft[0].1.committed, U128::from(0));
+    }
+
+    #[test]
+    fn test_admin_can_revoke_grant_of_owner() {
+        let admin: AccountId = "admin.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&admin).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        assert!(contract.add_auth_token_owner(owner.clone()));
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        testing_env!(context(&admin).build());
+        assert!(contract.revoke_grant(grant_id));
+        assert!(contract.balances.get(&spender).unwrap().iter().all(|x| x.owner != owner));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner or an admin can revoke a grant")]
+    fn test_revoke_grant_rejects_other_accounts() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        testing_env!(context(&spender).build());
+        contract.revoke_grant(grant_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nft is already claimed by spender")]
+    fn test_revoke_grant_rejects_claimed_nft() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+        testing_env!(context(&spender).build());
+        contract.transfer_nft_from(grant_id, None, None, None);
+        contract.nft_transfer_callback(grant_id, None, Ok(()));
+
+        testing_env!(context(&owner).build());
+        contract.revoke_grant(grant_id);
+    }
+
+    /// approve_for_spender at block timestamp 1000 with an expiry, and confirm the mint.
+    fn approve_expiring_grant(contract: &mut L2eTop, owner: &AccountId, spender: &AccountId, expires_at: u64) -> GrantId {
+        testing_env!(context(owner).attached_deposit(NearToken::from_near(1)).block_timestamp(1_000).build());
+        let grant_id = contract
+            .approve_for_spender(
+                DEFAULT_CAMPAIGN_ID,
+                spender.clone(),
+                NearToken::from_near(1),
+                U128::from(0),
+                None,
+                Some(U64::from(expires_at)),
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let token_id = contract.nft_entry(grant_id).unwrap().token_id;
+        let token = Token { token_id, owner_id: env::current_account_id(), metadata: None, approved_account_ids: None };
+        contract.nft_mint_callback(grant_id, Ok(token));
+        grant_id
+    }
+
+    #[test]
+    fn test_reclaim_expired_returns_grant_to_owner() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender1: AccountId = "spender1.near".parse().unwrap();
+        let spender2: AccountId = "spender2.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let grant_id = approve_expiring_grant(&mut contract, &owner, &spender1, 2_000);
+        approve_expiring_grant(&mut contract, &owner, &spender2, 5_000);
+
+        testing_env!(context(&"anyone.near".parse().unwrap()).block_timestamp(3_000).build());
+        assert_eq!(contract.reclaim_expired(None, None), 1);
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::Expired));
+        assert!(contract.balances.get(&spender1).unwrap().is_empty());
+        assert!(contract.nfts.get(&owner).unwrap().iter().all(|x| x.spender != spender1));
+        assert!(contract.balances.get(&spender2).unwrap().iter().any(|x| x.owner == owner));
+
+        // spender2 is the last of the 3 spenders in balances
+        testing_env!(context(&"anyone.near".parse().unwrap()).block_timestamp(5_000).build());
+        assert_eq!(contract.reclaim_expired(Some(0), Some(2)), 0);
+        assert_eq!(contract.reclaim_expired(Some(2), Some(1)), 1);
+        assert!(contract.balances.get(&spender2).unwrap().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Grant is expired")]
+    fn test_transfer_nft_from_rejects_expired_grant() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let grant_id = approve_expiring_grant(&mut contract, &owner, &spender, 2_000);
+
+        testing_env!(context(&spender).block_timestamp(2_000).build());
+        contract.transfer_nft_from(grant_id, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "expires_at should be in the future")]
+    fn test_approve_for_spender_rejects_past_expiry() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        approve_expiring_grant(&mut contract, &owner, &"spender.near".parse().unwrap(), 1_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "attached deposit does not cover main_token_amount")]
+    fn test_approve_for_spender_rejects_short_deposit() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_millinear(500)).build());
+        contract.approve_for_spender(
+            DEFAULT_CAMPAIGN_ID,
+            "spender.near".parse().unwrap(),
+            NearToken::from_near(1),
+            U128::from(0),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+    }
 }
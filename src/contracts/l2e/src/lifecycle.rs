@@ -0,0 +1,182 @@
+use near_sdk::{log, near, require};
+
+use crate::{GrantId, L2eTop, L2eTopExt};
+
+/// Where a grant is in its lifecycle, stored in `GrantRecord::status`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GrantStatus {
+    // approve_for_spender is waiting for nft_mint_callback
+    PendingMint,
+    // the nft is minted to the l2e contract, the spender can claim it
+    Minted,
+    // the spender holds the nft, the rewards can be claimed
+    NftClaimed,
+    // a transfer_balances_from, claim_stage or withdraw_vested payout is waiting for its callback
+    PaymentInFlight,
+    // everything is paid to the spender
+    Paid,
+    // revoked by the owner or an admin, what failed to return can be revoked again
+    Revoked,
+    // returned to the owner by reclaim_expired
+    Expired,
+    // nft_mint failed, the main token was refunded to the owner
+    Failed,
+}
+
+impl GrantStatus {
+    /// Whether a grant in this status can move to `next`.
+    /// A failed payout goes back to NftClaimed, a failed return keeps Revoked or Expired to be retried.
+    pub fn can_become(self, next: GrantStatus) -> bool {
+        use GrantStatus::*;
+        matches!(
+            (self, next),
+            (PendingMint, Minted)
+                | (PendingMint, Failed)
+                | (Minted, NftClaimed)
+                | (Minted, Revoked)
+                | (Minted, Expired)
+                | (NftClaimed, PaymentInFlight)
+                | (NftClaimed, Expired)
+                | (PaymentInFlight, NftClaimed)
+                | (PaymentInFlight, Paid)
+                | (Revoked, Revoked)
+                | (Revoked, Expired)
+                | (Expired, Expired)
+        )
+    }
+}
+
+#[near]
+impl L2eTop {
+    pub fn get_grant_status(&self, grant_id: GrantId) -> Option<GrantStatus> {
+        self.grants.get(&grant_id).map(|x| x.status)
+    }
+}
+
+impl L2eTop {
+    /// Move a grant to `status`, panics on a transition the lifecycle does not allow.
+    /// For public entry points, before they schedule any promise.
+    pub(crate) fn set_grant_status(&mut self, grant_id: GrantId, status: GrantStatus) {
+        let record = self.grants.get_mut(&grant_id).expect("Grant not found");
+        require!(
+            record.status.can_become(status),
+            format!("Grant can't go from {:?} to {:?}", record.status, status)
+        );
+        log!("Grant {} status: {:?} -> {:?}", grant_id, record.status, status);
+        record.status = status;
+    }
+
+    /// Move a grant to `status` from a callback, where a panic would undo what the callback settled.
+    /// Logs a transition the lifecycle does not allow and returns false, the status is kept then.
+    pub(crate) fn try_set_grant_status(&mut self, grant_id: GrantId, status: GrantStatus) -> bool {
+        let record = match self.grants.get_mut(&grant_id) {
+            Some(record) => record,
+            None => {
+                log!("Grant {} not found, its status is not set to {:?}", grant_id, status);
+                return false;
+            }
+        };
+        if !record.status.can_become(status) {
+            log!("Grant {} can't go from {:?} to {:?}", grant_id, record.status, status);
+            return false;
+        }
+        log!("Grant {} status: {:?} -> {:?}", grant_id, record.status, status);
+        record.status = status;
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_contract_standards::non_fungible_token::Token;
+    use near_sdk::json_types::U128;
+    use near_sdk::{env, testing_env, AccountId, NearToken, PromiseResult};
+
+    #[test]
+    fn test_grant_status_follows_lifecycle() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::Minted));
+
+        testing_env!(context(&spender).build());
+        assert!(contract.transfer_nft_from(grant_id, None, None, None));
+        assert!(contract.nft_transfer_callback(grant_id, None, Ok(())));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::NftClaimed));
+
+        let balance_entry = contract.balance_entry(grant_id).unwrap();
+        let nft_entry = contract.nft_entry(grant_id).unwrap();
+        assert!(contract.transfer_balances_from(grant_id, None));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::PaymentInFlight));
+
+        // a failed payout can be claimed again
+        callback_env(vec![PromiseResult::Failed]);
+        assert!(!contract.transfer_balances_callback(spender.clone(), balance_entry.clone(), nft_entry.clone(), None));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::NftClaimed));
+
+        testing_env!(context(&spender).build());
+        assert!(contract.transfer_balances_from(grant_id, None));
+        callback_env(vec![PromiseResult::Successful(vec![])]);
+        assert!(contract.transfer_balances_callback(spender, balance_entry, nft_entry, None));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::Paid));
+        assert_eq!(contract.get_grant(grant_id).unwrap().balance, None);
+        assert_eq!(contract.get_grant_status(grant_id + 1), None);
+    }
+
+    #[test]
+    fn test_grant_status_transitions() {
+        assert!(GrantStatus::PendingMint.can_become(GrantStatus::Minted));
+        assert!(GrantStatus::PaymentInFlight.can_become(GrantStatus::NftClaimed));
+        assert!(GrantStatus::Revoked.can_become(GrantStatus::Revoked));
+        assert!(!GrantStatus::PendingMint.can_become(GrantStatus::NftClaimed));
+        assert!(!GrantStatus::NftClaimed.can_become(GrantStatus::Revoked));
+        assert!(!GrantStatus::Paid.can_become(GrantStatus::PaymentInFlight));
+        assert!(!GrantStatus::Failed.can_become(GrantStatus::Minted));
+    }
+
+    #[test]
+    fn test_callback_logs_invalid_status_transition() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let (grant_id, token_id) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        // a second nft_mint_callback can't move a Minted grant to Minted again, it returns instead of panicking
+        let token = Token { token_id, owner_id: env::current_account_id(), metadata: None, approved_account_ids: None };
+        assert!(contract.nft_mint_callback(grant_id, Ok(token)).is_none());
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::Minted));
+        assert!(!contract.try_set_grant_status(grant_id, GrantStatus::Paid));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::Minted));
+    }
+
+    #[test]
+    #[should_panic(expected = "Nft can't be claimed from a Revoked grant")]
+    fn test_transfer_nft_from_rejects_revoked_grant() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let (grant_id, _) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        testing_env!(context(&owner).build());
+        let balance_entry = contract.balance_entry(grant_id).unwrap();
+        let nft_entry = contract.nft_entry(grant_id).unwrap();
+        assert!(contract.revoke_grant(grant_id));
+        // the nft is not returned, its entry is back with the revoked grant
+        callback_env(vec![PromiseResult::Successful(vec![]), PromiseResult::Failed]);
+        assert!(!contract.revoke_grant_callback(spender.clone(), balance_entry, Some(nft_entry), false));
+
+        testing_env!(context(&spender).build());
+        contract.transfer_nft_from(grant_id, None, None, None);
+    }
+}
@@ -0,0 +1,841 @@
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_contract_standards::non_fungible_token::Token;
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
+use near_sdk::{env, log, near, require, AccountId, CryptoHash, NearToken, Promise, PromiseError, PromiseOrValue};
+
+use crate::{
+    ext_nft_contract, CampaignId, EscrowAsset, EventLog, EventLogVariant, L2eTop, L2eTopExt, MerkleClaimLog,
+    MerkleDropLog, CONSTRACT_NAME, CONSTRACT_VERSION, FT_STORAGE_DEPOSIT, NFT_MINT_DEPOSIT,
+};
+
+pub type MerkleDropId = u64;
+
+/// Rewards of a cohort published as a Merkle root and funded once, each leaf is claimed by its spender with `claim_with_proof`.
+/// Nothing is written per spender until the leaf is claimed.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerkleDrop {
+    pub drop_id: MerkleDropId,
+    pub campaign_id: CampaignId,
+    pub owner: AccountId,
+    // root of the tree over the leaf hashes, see `MerkleLeaf`
+    pub root: Base58CryptoHash,
+    pub leaf_count: u64,
+    // reward contracts of the campaign when the drop was created
+    pub erc20_address: AccountId,
+    pub erc721_address: AccountId,
+    // main token deposited and ft pledged for all leaves
+    pub main_token_total: NearToken,
+    pub ft_total: U128,
+    // nft_mint and receiver storage_deposit of the leaves not claimed yet, escrowed with the main token
+    pub leaf_deposit_left: NearToken,
+    // ft that arrived through ft_on_transfer, leaves are only claimed while it covers them
+    pub ft_escrowed: U128,
+    // main token and ft of the claimed leaves, paid or waiting for a retry of claim_with_proof
+    pub main_token_claimed: NearToken,
+    pub ft_claimed: U128,
+    pub claimed_count: u64,
+    // block timestamp in nanoseconds, None if the drop never expires
+    pub expires_at: Option<U64>,
+    // closed by the owner after it expired, the unclaimed funds went back to the owner and the totals are the claimed amounts
+    pub closed: bool,
+}
+
+/// A leaf of a merkle drop, its hash is `sha256(borsh(MerkleLeaf))`.
+/// Two nodes are hashed as `sha256(a ++ b)` with the smaller hash first, so a proof needs no left or right flags.
+#[near(serializers = [borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerkleLeaf {
+    // position of the leaf, from 0 to leaf_count - 1, it is the bit of the leaf in the claimed bitmap
+    pub index: u64,
+    pub spender: AccountId,
+    // yoctoNEAR
+    pub main_token_amount: u128,
+    // raw units of the erc20 of the campaign
+    pub ft_amount: u128,
+    // sha256 of the json of the token_metadata given to claim_with_proof
+    pub metadata_hash: CryptoHash,
+}
+
+/// ft_transfer_call msg funding the ft of a merkle drop, e.g. `{"merkle_drop_id": 1}`.
+#[near(serializers = [json])]
+pub struct MerkleDropFundMsg {
+    pub merkle_drop_id: MerkleDropId,
+}
+
+#[near]
+impl L2eTop {
+    /// Publish a merkle drop in a campaign of the caller, the attached deposit covers main_token_total
+    /// and `merkle_leaf_deposit()` for each leaf, which pays its nft_mint and the storage_deposit of its receiver.
+    /// The ft_total is funded with ft_transfer_call and `{"merkle_drop_id": <drop_id>}` as msg.
+    /// The totals count against the campaign budget, the surplus deposit is refunded.
+    /// A campaign with verifier keys or a verifier contract can't have merkle drops, the leaves have no verifier step.
+    #[payable]
+    pub fn create_merkle_drop(
+        &mut self,
+        campaign_id: CampaignId,
+        root: Base58CryptoHash,
+        leaf_count: u64,
+        main_token_total: NearToken,
+        ft_total: U128,
+        expires_at: Option<U64>,
+    ) -> MerkleDropId {
+        let owner = env::predecessor_account_id();
+        require!(leaf_count > 0, "leaf_count should be greater than 0");
+        if let Some(expires_at) = expires_at {
+            require!(expires_at.0 > env::block_timestamp(), "expires_at should be in the future");
+        }
+        let attached_amount = env::attached_deposit();
+        let leaf_deposit_total = merkle_leaf_deposit().checked_mul(leaf_count as u128);
+        let escrowed_amount = leaf_deposit_total.and_then(|x| x.checked_add(main_token_total));
+        require!(
+            escrowed_amount.is_some_and(|x| attached_amount >= x),
+            "attached deposit does not cover main_token_total and the leaf deposits"
+        );
+        let leaf_deposit_total = leaf_deposit_total.unwrap_or_default();
+        let escrowed_amount = escrowed_amount.unwrap_or_default();
+        let campaign = self.campaign_for_grant(campaign_id, &owner);
+        require!(!self.is_claim_gated(campaign_id), "A campaign with a verifier can't have merkle drops");
+        require!(campaign.has_budget_for(main_token_total, ft_total), "Campaign budget exceeded");
+
+        let drop_id = self.next_merkle_drop_id;
+        self.next_merkle_drop_id += 1;
+        self.merkle_drops.insert(
+            drop_id,
+            MerkleDrop {
+                drop_id,
+                campaign_id,
+                owner: owner.clone(),
+                root,
+                leaf_count,
+                erc20_address: campaign.erc20_address,
+                erc721_address: campaign.erc721_address,
+                main_token_total,
+                ft_total,
+                leaf_deposit_left: leaf_deposit_total,
+                ft_escrowed: U128::from(0),
+                main_token_claimed: NearToken::from_near(0),
+                ft_claimed: U128::from(0),
+                claimed_count: 0,
+                expires_at,
+                closed: false,
+            },
+        );
+        self.charge_campaign(campaign_id, main_token_total, ft_total.0);
+        self.escrow_deposit(&owner, EscrowAsset::MainToken, escrowed_amount.as_yoctonear());
+
+        let refund = attached_amount.saturating_sub(escrowed_amount);
+        if refund > NearToken::from_near(0) {
+            log!("Refund unused deposit: {:?}", refund);
+            let _refund_promise = near_sdk::Promise::new(owner.clone()).transfer(refund);
+        }
+        self.log_merkle_drop(EventLogVariant::CreateMerkleDrop, drop_id, main_token_total, ft_total.0);
+
+        drop_id
+    }
+
+    /// Claim the leaf `index` of a merkle drop as its spender, with the sibling hashes from the leaf up to the root as proof.
+    /// The nft is minted with token_metadata to receiver_id, or to the spender, then the main token and ft are paid.
+    /// The receiver is registered with the erc20 before its ft_transfer.
+    /// A leaf whose payout failed is paid again by calling claim_with_proof with the same leaf, even after the drop closed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn claim_with_proof(
+        &mut self,
+        drop_id: MerkleDropId,
+        index: u64,
+        main_token_amount: NearToken,
+        ft_amount: U128,
+        token_metadata: TokenMetadata,
+        proof: Vec<Base58CryptoHash>,
+        receiver_id: Option<AccountId>,
+    ) -> bool {
+        let spender = env::predecessor_account_id();
+        let merkle_drop = self.merkle_drops.get(&drop_id).expect("Merkle drop not found").clone();
+        require!(index < merkle_drop.leaf_count, "Leaf index is out of the drop");
+        let metadata_hash = env::sha256_array(
+            near_sdk::serde_json::to_vec(&token_metadata).expect("Failed to serialize token_metadata"),
+        );
+        let leaf = MerkleLeaf {
+            index,
+            spender: spender.clone(),
+            main_token_amount: main_token_amount.as_yoctonear(),
+            ft_amount: ft_amount.0,
+            metadata_hash,
+        };
+        require!(
+            verify_proof(&leaf, &proof, &CryptoHash::from(merkle_drop.root)),
+            "Invalid merkle proof"
+        );
+        let receiver_id = Self::claim_receiver(&spender, receiver_id);
+
+        if self.is_merkle_claimed(drop_id, U64::from(index)) {
+            let (main_token_amount, ft_amount) =
+                self.merkle_unpaid.remove(&(drop_id, index)).expect("Leaf is already claimed");
+            self.start_merkle_payout(drop_id, index, spender, receiver_id, main_token_amount, ft_amount);
+            return true;
+        }
+
+        require!(!merkle_drop.closed, "Merkle drop is closed");
+        let expired = merkle_drop.expires_at.is_some_and(|x| x.0 <= env::block_timestamp());
+        require!(!expired, "Merkle drop is expired");
+        require!(!self.is_claim_gated(merkle_drop.campaign_id), "Claims of this campaign need a verifier");
+        let main_token_claimed = merkle_drop.main_token_claimed.saturating_add(main_token_amount);
+        require!(main_token_claimed <= merkle_drop.main_token_total, "Merkle drop main token is used up");
+        let ft_claimed = merkle_drop.ft_claimed.0.checked_add(ft_amount.0);
+        require!(
+            ft_claimed.is_some_and(|x| x <= merkle_drop.ft_escrowed.0),
+            "Ft of the merkle drop is not funded yet"
+        );
+        let ft_claimed = ft_claimed.unwrap_or_default();
+        let leaf_deposit_left = merkle_drop.leaf_deposit_left.checked_sub(merkle_leaf_deposit());
+        require!(leaf_deposit_left.is_some(), "Merkle drop leaf deposits are used up");
+
+        // the leaf is claimed now, merkle_mint_callback gives it back if the mint fails
+        self.set_merkle_claimed(drop_id, index, true);
+        let merkle_drop = self.merkle_drops.get_mut(&drop_id).expect("Merkle drop not found");
+        merkle_drop.main_token_claimed = main_token_claimed;
+        merkle_drop.ft_claimed = U128::from(ft_claimed);
+        merkle_drop.leaf_deposit_left = leaf_deposit_left.unwrap_or_default();
+        merkle_drop.claimed_count += 1;
+        let erc721_address = merkle_drop.erc721_address.clone();
+
+        let token_id = self.next_token_id();
+        let receiver = receiver_id.clone().unwrap_or_else(|| spender.clone());
+        let _mint_nft_promise = ext_nft_contract::ext(erc721_address)
+            .with_attached_deposit(NFT_MINT_DEPOSIT)
+            .nft_mint(token_id, receiver, token_metadata)
+            .then(Self::ext(env::current_account_id()).merkle_mint_callback(
+                drop_id,
+                index,
+                spender,
+                receiver_id,
+                main_token_amount,
+                ft_amount,
+            ));
+
+        true
+    }
+
+    /// Pay the leaf once its nft is minted, or give the leaf back to the drop if nft_mint failed.
+    /// The leaf deposit is paid with the mint, a leaf given back to a closed drop is refunded to the owner.
+    #[private] // Public - but only callable by env::current_account_id()
+    #[allow(clippy::too_many_arguments)]
+    pub fn merkle_mint_callback(
+        &mut self,
+        drop_id: MerkleDropId,
+        index: u64,
+        spender: AccountId,
+        receiver_id: Option<AccountId>,
+        main_token_amount: NearToken,
+        ft_amount: U128,
+        #[callback_result] call_result: Result<Token, PromiseError>,
+    ) -> bool {
+        match call_result {
+            Ok(token) => {
+                log!("merkle_mint_callback minted {}", token.token_id);
+                let owner = self.merkle_drops.get(&drop_id).expect("Merkle drop not found").owner.clone();
+                self.escrow_pay(&owner, EscrowAsset::MainToken, merkle_leaf_deposit().as_yoctonear());
+                self.start_merkle_payout(drop_id, index, spender, receiver_id, main_token_amount, ft_amount);
+                true
+            }
+            Err(e) => {
+                log!("There was an error contacting NFT contract nft_mint: {:#?}", e);
+                self.set_merkle_claimed(drop_id, index, false);
+                let merkle_drop = self.merkle_drops.get_mut(&drop_id).expect("Merkle drop not found");
+                merkle_drop.main_token_claimed = merkle_drop.main_token_claimed.saturating_sub(main_token_amount);
+                merkle_drop.ft_claimed = U128::from(merkle_drop.ft_claimed.0.saturating_sub(ft_amount.0));
+                merkle_drop.leaf_deposit_left = merkle_drop.leaf_deposit_left.saturating_add(merkle_leaf_deposit());
+                merkle_drop.claimed_count = merkle_drop.claimed_count.saturating_sub(1);
+                if merkle_drop.closed {
+                    self.refund_merkle_drop(drop_id);
+                }
+                self.log_merkle_claim(
+                    EventLogVariant::ClaimWithProofFailed,
+                    drop_id,
+                    index,
+                    &spender,
+                    receiver_id.as_ref(),
+                    main_token_amount,
+                    ft_amount.0,
+                );
+                false
+            }
+        }
+    }
+
+    /// Resolve the payout of a leaf. The promise results are in order: main token transfer, ft_transfer.
+    /// A zero leg is not part of the results. The failed legs are kept for the next claim_with_proof of the leaf.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn merkle_payout_callback(
+        &mut self,
+        drop_id: MerkleDropId,
+        index: u64,
+        spender: AccountId,
+        receiver_id: Option<AccountId>,
+        main_token_amount: NearToken,
+        ft_amount: U128,
+    ) -> bool {
+        let (main_token_paid, ft_paid) = Self::payout_results(main_token_amount, ft_amount);
+        let owner = self.merkle_drops.get(&drop_id).expect("Merkle drop not found").owner.clone();
+        self.settle_merkle_payout(drop_id, index, &owner, main_token_paid, ft_paid, main_token_amount, ft_amount);
+
+        if main_token_paid && ft_paid {
+            self.log_merkle_claim(
+                EventLogVariant::ClaimWithProof,
+                drop_id,
+                index,
+                &spender,
+                receiver_id.as_ref(),
+                main_token_amount,
+                ft_amount.0,
+            );
+            return true;
+        }
+
+        let unpaid_main_token = if main_token_paid { NearToken::from_near(0) } else { main_token_amount };
+        let unpaid_ft = if ft_paid { 0 } else { ft_amount.0 };
+        self.log_merkle_claim(
+            EventLogVariant::ClaimWithProofFailed,
+            drop_id,
+            index,
+            &spender,
+            receiver_id.as_ref(),
+            unpaid_main_token,
+            unpaid_ft,
+        );
+        false
+    }
+
+    /// Return the unclaimed main token, escrowed ft and leaf deposits of a drop to its owner, called by the owner
+    /// once the drop expired, or at any time for a drop without expires_at.
+    /// Leaves can't be claimed anymore, the failed payouts of claimed leaves can still be retried.
+    /// The totals of the drop are lowered to the claimed amounts, merkle_close_callback restores a failed refund.
+    pub fn close_merkle_drop(&mut self, drop_id: MerkleDropId) -> bool {
+        let merkle_drop = self.merkle_drops.get_mut(&drop_id).expect("Merkle drop not found");
+        require!(merkle_drop.owner == env::predecessor_account_id(), "Only the owner can close a merkle drop");
+        require!(!merkle_drop.closed, "Merkle drop is closed");
+        let closable = match merkle_drop.expires_at {
+            Some(expires_at) => expires_at.0 <= env::block_timestamp(),
+            None => true,
+        };
+        require!(closable, "Merkle drop can only be closed once it is expired");
+        merkle_drop.closed = true;
+        self.refund_merkle_drop(drop_id);
+
+        true
+    }
+
+    /// Resolve the refunds of close_merkle_drop, in order: main token transfer, ft_transfer. A zero leg is skipped.
+    /// The main token transfer holds refund_main_token and refund_leaf_deposit.
+    /// A failed leg is added back to the drop, which is open again so the owner can close it again.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn merkle_close_callback(
+        &mut self,
+        drop_id: MerkleDropId,
+        refund_main_token: NearToken,
+        refund_ft: U128,
+        refund_leaf_deposit: NearToken,
+    ) -> bool {
+        let refund_near = refund_main_token.saturating_add(refund_leaf_deposit);
+        let (main_token_returned, ft_returned) = Self::payout_results(refund_near, refund_ft);
+        let merkle_drop = self.merkle_drops.get(&drop_id).expect("Merkle drop not found").clone();
+        if main_token_returned && refund_near > NearToken::from_near(0) {
+            self.escrow_refund(&merkle_drop.owner, EscrowAsset::MainToken, refund_near.as_yoctonear());
+        }
+        if ft_returned && refund_ft.0 > 0 {
+            self.escrow_refund(&merkle_drop.owner, EscrowAsset::Ft(merkle_drop.erc20_address.clone()), refund_ft.0);
+        }
+        if main_token_returned && ft_returned {
+            return true;
+        }
+
+        let unreturned_main_token = if main_token_returned { NearToken::from_near(0) } else { refund_main_token };
+        let unreturned_leaf_deposit = if main_token_returned { NearToken::from_near(0) } else { refund_leaf_deposit };
+        let unreturned_ft = if ft_returned { 0 } else { refund_ft.0 };
+        let merkle_drop = self.merkle_drops.get_mut(&drop_id).expect("Merkle drop not found");
+        merkle_drop.closed = false;
+        merkle_drop.main_token_total = merkle_drop.main_token_total.saturating_add(unreturned_main_token);
+        merkle_drop.leaf_deposit_left = merkle_drop.leaf_deposit_left.saturating_add(unreturned_leaf_deposit);
+        merkle_drop.ft_total = U128::from(merkle_drop.ft_total.0.saturating_add(unreturned_ft));
+        merkle_drop.ft_escrowed = U128::from(merkle_drop.ft_escrowed.0.saturating_add(unreturned_ft));
+        let campaign_id = merkle_drop.campaign_id;
+        self.charge_campaign(campaign_id, unreturned_main_token, unreturned_ft);
+        self.log_merkle_drop(
+            EventLogVariant::CloseMerkleDropFailed,
+            drop_id,
+            unreturned_main_token.saturating_add(unreturned_leaf_deposit),
+            unreturned_ft,
+        );
+
+        false
+    }
+
+    pub fn get_merkle_drop(&self, drop_id: MerkleDropId) -> Option<MerkleDrop> {
+        self.merkle_drops.get(&drop_id).cloned()
+    }
+
+    /// Whether the leaf `index` of a drop is claimed, its payout may still wait for a retry.
+    pub fn is_merkle_claimed(&self, drop_id: MerkleDropId, index: U64) -> bool {
+        let word = self.merkle_claimed.get(&(drop_id, index.0 / 128)).copied().unwrap_or(0);
+        word & (1u128 << (index.0 % 128)) != 0
+    }
+}
+
+impl L2eTop {
+    /// Escrow the ft of a merkle drop of owner, up to its ft_total. Returns the unused amount for ft_resolve_transfer.
+    pub(crate) fn fund_merkle_drop(
+        &mut self,
+        owner: &AccountId,
+        ft_contract: &AccountId,
+        drop_id: MerkleDropId,
+        amount: U128,
+    ) -> PromiseOrValue<U128> {
+        let merkle_drop = match self.merkle_drops.get_mut(&drop_id) {
+            Some(merkle_drop) if &merkle_drop.owner == owner && !merkle_drop.closed => merkle_drop,
+            _ => {
+                log!("ft_on_transfer: {} has no open merkle drop {}.", owner, drop_id);
+                return PromiseOrValue::Value(amount);
+            }
+        };
+        if &merkle_drop.erc20_address != ft_contract {
+            log!("ft_on_transfer: merkle drop {} is funded with {}.", drop_id, merkle_drop.erc20_address);
+            return PromiseOrValue::Value(amount);
+        }
+        let accepted = std::cmp::min(amount.0, merkle_drop.ft_total.0.saturating_sub(merkle_drop.ft_escrowed.0));
+        merkle_drop.ft_escrowed = U128::from(merkle_drop.ft_escrowed.0.saturating_add(accepted));
+        if accepted > 0 {
+            self.escrow_deposit(owner, EscrowAsset::Ft(ft_contract.clone()), accepted);
+        }
+        log!("ft_on_transfer: merkle drop {} escrowed {}.", drop_id, accepted);
+
+        PromiseOrValue::Value(U128::from(amount.0.saturating_sub(accepted)))
+    }
+
+    /// Refund what a closed drop holds beyond its claimed leaves to the owner, resolved by merkle_close_callback.
+    /// The totals of the drop are lowered to the claimed amounts.
+    fn refund_merkle_drop(&mut self, drop_id: MerkleDropId) {
+        let merkle_drop = self.merkle_drops.get_mut(&drop_id).expect("Merkle drop not found");
+        let refund_main_token = merkle_drop.main_token_total.checked_sub(merkle_drop.main_token_claimed);
+        let refund_ft = merkle_drop.ft_escrowed.0.checked_sub(merkle_drop.ft_claimed.0);
+        let released_ft = merkle_drop.ft_total.0.checked_sub(merkle_drop.ft_claimed.0);
+        require!(
+            refund_main_token.is_some() && refund_ft.is_some() && released_ft.is_some(),
+            "Merkle drop claimed more than it holds"
+        );
+        let refund_main_token = refund_main_token.unwrap_or_default();
+        let refund_ft = refund_ft.unwrap_or_default();
+        let refund_leaf_deposit = merkle_drop.leaf_deposit_left;
+        merkle_drop.main_token_total = merkle_drop.main_token_claimed;
+        merkle_drop.ft_total = merkle_drop.ft_claimed;
+        merkle_drop.ft_escrowed = merkle_drop.ft_claimed;
+        merkle_drop.leaf_deposit_left = NearToken::from_near(0);
+        let merkle_drop = merkle_drop.clone();
+        self.release_campaign(merkle_drop.campaign_id, refund_main_token, released_ft.unwrap_or_default());
+
+        // the ledger records the refunds once they resolved, in merkle_close_callback
+        let refund_near = refund_main_token.saturating_add(refund_leaf_deposit);
+        let mut refund: Option<Promise> = None;
+        if refund_near > NearToken::from_near(0) {
+            refund = Some(Promise::new(merkle_drop.owner.clone()).transfer(refund_near));
+        }
+        if refund_ft > 0 {
+            let ft_promise = Self::ft_transfer_to(
+                &merkle_drop.erc20_address,
+                &merkle_drop.owner,
+                U128::from(refund_ft),
+                Some("L2E merkle drop closed".to_string()),
+                false,
+            );
+            refund = Some(match refund {
+                Some(promise) => promise.and(ft_promise),
+                None => ft_promise,
+            });
+        }
+        if let Some(refund) = refund {
+            let _close_merkle_drop_promise = refund.then(Self::ext(env::current_account_id()).merkle_close_callback(
+                drop_id,
+                refund_main_token,
+                U128::from(refund_ft),
+                refund_leaf_deposit,
+            ));
+        }
+        self.log_merkle_drop(EventLogVariant::CloseMerkleDrop, drop_id, refund_near, refund_ft);
+    }
+
+    /// Pay a claimed leaf to receiver_id or the spender, resolved by merkle_payout_callback.
+    fn start_merkle_payout(
+        &mut self,
+        drop_id: MerkleDropId,
+        index: u64,
+        spender: AccountId,
+        receiver_id: Option<AccountId>,
+        main_token_amount: NearToken,
+        ft_amount: U128,
+    ) {
+        let erc20_address = self.merkle_drops.get(&drop_id).expect("Merkle drop not found").erc20_address.clone();
+        // the receiver of a leaf was never registered by approve_for_spender
+        let receiver = receiver_id.as_ref().unwrap_or(&spender);
+        match Self::payout(receiver, main_token_amount, &erc20_address, ft_amount, true) {
+            Some(payout) => {
+                let _merkle_payout_promise = payout.then(Self::ext(env::current_account_id()).merkle_payout_callback(
+                    drop_id,
+                    index,
+                    spender,
+                    receiver_id,
+                    main_token_amount,
+                    ft_amount,
+                ));
+            }
+            None => self.log_merkle_claim(
+                EventLogVariant::ClaimWithProof,
+                drop_id,
+                index,
+                &spender,
+                receiver_id.as_ref(),
+                main_token_amount,
+                ft_amount.0,
+            ),
+        }
+    }
+
+    /// Record the paid legs of a leaf in the escrow ledger of the owner and keep the unpaid ones for a retry.
+    #[allow(clippy::too_many_arguments)]
+    fn settle_merkle_payout(
+        &mut self,
+        drop_id: MerkleDropId,
+        index: u64,
+        owner: &AccountId,
+        main_token_paid: bool,
+        ft_paid: bool,
+        main_token_amount: NearToken,
+        ft_amount: U128,
+    ) {
+        let erc20_address = self.merkle_drops.get(&drop_id).expect("Merkle drop not found").erc20_address.clone();
+        if main_token_paid && main_token_amount > NearToken::from_near(0) {
+            self.escrow_pay(owner, EscrowAsset::MainToken, main_token_amount.as_yoctonear());
+        }
+        if ft_paid && ft_amount.0 > 0 {
+            self.escrow_pay(owner, EscrowAsset::Ft(erc20_address), ft_amount.0);
+        }
+        if !(main_token_paid && ft_paid) {
+            let unpaid_main_token = if main_token_paid { NearToken::from_near(0) } else { main_token_amount };
+            let unpaid_ft = if ft_paid { U128::from(0) } else { ft_amount };
+            self.merkle_unpaid.insert((drop_id, index), (unpaid_main_token, unpaid_ft));
+        }
+    }
+
+    fn set_merkle_claimed(&mut self, drop_id: MerkleDropId, index: u64, claimed: bool) {
+        let key = (drop_id, index / 128);
+        let bit = 1u128 << (index % 128);
+        let word = self.merkle_claimed.get(&key).copied().unwrap_or(0);
+        let word = if claimed { word | bit } else { word & !bit };
+        self.merkle_claimed.insert(key, word);
+    }
+
+    fn log_merkle_drop(
+        &self,
+        event: fn(Vec<MerkleDropLog>) -> EventLogVariant,
+        drop_id: MerkleDropId,
+        main_token_amount: NearToken,
+        ft_amount: u128,
+    ) {
+        let merkle_drop = self.merkle_drops.get(&drop_id).expect("Merkle drop not found");
+        let merkle_drop_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: event(vec![MerkleDropLog {
+                drop_id,
+                campaign_id: merkle_drop.campaign_id,
+                owner_id: merkle_drop.owner.to_string(),
+                root: String::from(&merkle_drop.root),
+                main_token_amount: main_token_amount.as_yoctonear().to_string(),
+                ft_token_amount: ft_amount.to_string(),
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+
+        env::log_str(&merkle_drop_log.to_string());
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn log_merkle_claim(
+        &self,
+        event: fn(Vec<MerkleClaimLog>) -> EventLogVariant,
+        drop_id: MerkleDropId,
+        index: u64,
+        spender: &AccountId,
+        receiver_id: Option<&AccountId>,
+        main_token_amount: NearToken,
+        ft_amount: u128,
+    ) {
+        let campaign_id = self.merkle_drops.get(&drop_id).map(|x| x.campaign_id);
+        let merkle_claim_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: event(vec![MerkleClaimLog {
+                drop_id,
+                index,
+                spender_id: spender.to_string(),
+                receiver_id: receiver_id.map(|x| x.to_string()),
+                main_token_amount: main_token_amount.as_yoctonear().to_string(),
+                ft_token_amount: ft_amount.to_string(),
+                campaign_id,
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+
+        env::log_str(&merkle_claim_log.to_string());
+    }
+}
+
+/// Deposit escrowed for each leaf of a merkle drop, the nft_mint of the leaf and the storage_deposit of its receiver.
+pub fn merkle_leaf_deposit() -> NearToken {
+    NFT_MINT_DEPOSIT.saturating_add(FT_STORAGE_DEPOSIT)
+}
+
+/// Hash of a leaf of a merkle drop.
+pub fn merkle_leaf_hash(leaf: &MerkleLeaf) -> CryptoHash {
+    env::sha256_array(near_sdk::borsh::to_vec(leaf).expect("Failed to serialize the merkle leaf"))
+}
+
+/// Hash of two nodes, the smaller one first.
+pub fn merkle_node_hash(a: &CryptoHash, b: &CryptoHash) -> CryptoHash {
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    env::sha256_array([first.as_slice(), second.as_slice()].concat())
+}
+
+/// Whether the proof leads from the hash of leaf to root.
+fn verify_proof(leaf: &MerkleLeaf, proof: &[Base58CryptoHash], root: &CryptoHash) -> bool {
+    let computed = proof
+        .iter()
+        .fold(merkle_leaf_hash(leaf), |node, sibling| merkle_node_hash(&node, &CryptoHash::from(*sibling)));
+    &computed == root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::DEFAULT_CAMPAIGN_ID;
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::{testing_env, PromiseResult};
+
+    /// Token metadata, leaf hashes and root of a merkle drop paying spender 1 NEAR and 60 ft, and other.near 1 NEAR and 40 ft.
+    fn merkle_tree(spender: &AccountId) -> (TokenMetadata, CryptoHash, CryptoHash, CryptoHash) {
+        let token_metadata = TokenMetadata {
+            title: Some("Rust 101".to_string()),
+            description: None,
+            media: None,
+            media_hash: None,
+            copies: Some(1),
+            issued_at: None,
+            expires_at: None,
+            starts_at: None,
+            updated_at: None,
+            extra: None,
+            reference: None,
+            reference_hash: None,
+        };
+        let metadata_hash = env::sha256_array(serde_json::to_vec(&token_metadata).unwrap());
+        let leaf = |index: u64, spender: &AccountId, ft_amount: u128| {
+            merkle_leaf_hash(&MerkleLeaf {
+                index,
+                spender: spender.clone(),
+                main_token_amount: NearToken::from_near(1).as_yoctonear(),
+                ft_amount,
+                metadata_hash,
+            })
+        };
+        let spender_leaf = leaf(0, spender, 60);
+        let other_leaf = leaf(1, &"other.near".parse().unwrap(), 40);
+        (token_metadata, spender_leaf, other_leaf, merkle_node_hash(&spender_leaf, &other_leaf))
+    }
+
+    #[test]
+    fn test_claim_with_proof_mints_and_pays_leaf() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+        let (token_metadata, _, other_leaf, root) = merkle_tree(&spender);
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_millinear(2_080)).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let drop_id = contract.create_merkle_drop(
+            DEFAULT_CAMPAIGN_ID,
+            root.into(),
+            2,
+            NearToken::from_near(2),
+            U128::from(100),
+            None,
+        );
+
+        // only the ft pledged for the drop is escrowed
+        testing_env!(context(&"erc20.near".parse().unwrap()).build());
+        let msg = format!("{{\"merkle_drop_id\": {}}}", drop_id);
+        match contract.ft_on_transfer(owner.clone(), U128::from(150), msg) {
+            PromiseOrValue::Value(unused) => assert_eq!(unused, U128::from(50)),
+            PromiseOrValue::Promise(_) => panic!("ft_on_transfer should not return a promise"),
+        }
+
+        testing_env!(context(&spender).build());
+        let proof = vec![other_leaf.into()];
+        assert!(contract.claim_with_proof(
+            drop_id,
+            0,
+            NearToken::from_near(1),
+            U128::from(60),
+            token_metadata.clone(),
+            proof.clone(),
+            None,
+        ));
+        assert!(contract.is_merkle_claimed(drop_id, U64::from(0)));
+        assert!(!contract.is_merkle_claimed(drop_id, U64::from(1)));
+
+        let token = Token { token_id: "10001".to_string(), owner_id: spender.clone(), metadata: None, approved_account_ids: None };
+        assert!(contract.merkle_mint_callback(drop_id, 0, spender.clone(), None, NearToken::from_near(1), U128::from(60), Ok(token)));
+
+        // the failed ft leg is paid by the next claim of the leaf
+        callback_env(vec![PromiseResult::Successful(vec![]), PromiseResult::Failed]);
+        assert!(!contract.merkle_payout_callback(drop_id, 0, spender.clone(), None, NearToken::from_near(1), U128::from(60)));
+        assert_eq!(contract.merkle_unpaid.get(&(drop_id, 0)), Some(&(NearToken::from_near(0), U128::from(60))));
+
+        testing_env!(context(&spender).build());
+        assert!(contract.claim_with_proof(drop_id, 0, NearToken::from_near(1), U128::from(60), token_metadata, proof, None));
+        callback_env(vec![PromiseResult::Successful(vec![])]);
+        assert!(contract.merkle_payout_callback(drop_id, 0, spender, None, NearToken::from_near(0), U128::from(60)));
+        assert_eq!(contract.merkle_unpaid.get(&(drop_id, 0)), None);
+
+        let merkle_drop = contract.get_merkle_drop(drop_id).unwrap();
+        assert_eq!(merkle_drop.claimed_count, 1);
+        assert_eq!(merkle_drop.main_token_claimed, NearToken::from_near(1));
+        assert_eq!(merkle_drop.ft_claimed, U128::from(60));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid merkle proof")]
+    fn test_claim_with_proof_rejects_leaf_of_other_spender() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+        let (token_metadata, _, other_leaf, root) = merkle_tree(&spender);
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_millinear(2_080)).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let drop_id =
+            contract.create_merkle_drop(DEFAULT_CAMPAIGN_ID, root.into(), 2, NearToken::from_near(2), U128::from(0), None);
+
+        testing_env!(context(&"thief.near".parse().unwrap()).build());
+        contract.claim_with_proof(
+            drop_id,
+            0,
+            NearToken::from_near(1),
+            U128::from(60),
+            token_metadata,
+            vec![other_leaf.into()],
+            None,
+        );
+    }
+
+    #[test]
+    fn test_merkle_close_callback_reopens_drop_with_failed_refund() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let (_, _, _, root) = merkle_tree(&"spender.near".parse().unwrap());
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_millinear(2_080)).build());
+        let mut contract = L2eTop::init(erc20.clone(), "erc721.near".parse().unwrap());
+        let drop_id = contract.create_merkle_drop(
+            DEFAULT_CAMPAIGN_ID,
+            root.into(),
+            2,
+            NearToken::from_near(2),
+            U128::from(100),
+            Some(U64::from(1_000)),
+        );
+        testing_env!(context(&erc20).build());
+        let _ = contract.ft_on_transfer(owner.clone(), U128::from(100), format!("{{\"merkle_drop_id\": {}}}", drop_id));
+
+        testing_env!(context(&owner).block_timestamp(2_000).build());
+        assert!(contract.close_merkle_drop(drop_id));
+        let merkle_drop = contract.get_merkle_drop(drop_id).unwrap();
+        assert!(merkle_drop.closed);
+        assert_eq!(merkle_drop.main_token_total, NearToken::from_near(0));
+        assert_eq!(merkle_drop.ft_escrowed, U128::from(0));
+
+        // main token refund succeeded, ft_transfer failed
+        callback_env(vec![PromiseResult::Successful(vec![]), PromiseResult::Failed]);
+        assert!(!contract.merkle_close_callback(drop_id, NearToken::from_near(2), U128::from(100), merkle_leaf_deposit().saturating_mul(2)));
+
+        let merkle_drop = contract.get_merkle_drop(drop_id).unwrap();
+        assert!(!merkle_drop.closed);
+        assert_eq!(merkle_drop.main_token_total, NearToken::from_near(0));
+        assert_eq!(merkle_drop.ft_total, U128::from(100));
+        assert_eq!(merkle_drop.ft_escrowed, U128::from(100));
+        assert_eq!(contract.get_campaign(DEFAULT_CAMPAIGN_ID).unwrap().ft_granted, U128::from(100));
+        let ledger = contract.get_escrow_ledger(owner.clone());
+        assert_eq!(ledger.main_token.committed, U128::from(0));
+        assert_eq!(ledger.ft_committed(&erc20), 100);
+
+        // the owner closes it again for the ft
+        testing_env!(context(&owner).block_timestamp(2_000).build());
+        assert!(contract.close_merkle_drop(drop_id));
+    }
+
+    #[test]
+    #[should_panic(expected = "attached deposit does not cover main_token_total and the leaf deposits")]
+    fn test_create_merkle_drop_rejects_deposit_without_leaf_deposits() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let (_, _, _, root) = merkle_tree(&"spender.near".parse().unwrap());
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_near(2)).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        contract.create_merkle_drop(DEFAULT_CAMPAIGN_ID, root.into(), 2, NearToken::from_near(2), U128::from(0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "A campaign with a verifier can't have merkle drops")]
+    fn test_create_merkle_drop_rejects_campaign_with_verifier() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let (_, _, _, root) = merkle_tree(&"spender.near".parse().unwrap());
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_millinear(2_080)).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        contract.set_verifier_contract(DEFAULT_CAMPAIGN_ID, Some("verifier.near".parse().unwrap()));
+        contract.create_merkle_drop(DEFAULT_CAMPAIGN_ID, root.into(), 2, NearToken::from_near(2), U128::from(0), None);
+    }
+
+    #[test]
+    fn test_close_merkle_drop_without_expiry_refunds_leaf_deposits() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+        let (token_metadata, _, other_leaf, root) = merkle_tree(&spender);
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_millinear(2_080)).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let drop_id =
+            contract.create_merkle_drop(DEFAULT_CAMPAIGN_ID, root.into(), 2, NearToken::from_near(2), U128::from(100), None);
+        testing_env!(context(&"erc20.near".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(owner.clone(), U128::from(100), format!("{{\"merkle_drop_id\": {}}}", drop_id));
+        assert_eq!(
+            contract.get_escrow_ledger(owner.clone()).main_token.committed,
+            U128::from(NearToken::from_millinear(2_080).as_yoctonear())
+        );
+
+        // the claimed leaf pays its deposit with the mint
+        testing_env!(context(&spender).build());
+        let proof = vec![other_leaf.into()];
+        assert!(contract.claim_with_proof(drop_id, 0, NearToken::from_near(1), U128::from(60), token_metadata, proof, None));
+        let token = Token { token_id: "10001".to_string(), owner_id: spender.clone(), metadata: None, approved_account_ids: None };
+        assert!(contract.merkle_mint_callback(drop_id, 0, spender, None, NearToken::from_near(1), U128::from(60), Ok(token)));
+
+        testing_env!(context(&owner).build());
+        assert!(contract.close_merkle_drop(drop_id));
+        let merkle_drop = contract.get_merkle_drop(drop_id).unwrap();
+        assert_eq!(merkle_drop.main_token_total, NearToken::from_near(1));
+        assert_eq!(merkle_drop.leaf_deposit_left, NearToken::from_near(0));
+
+        callback_env(vec![PromiseResult::Successful(vec![]), PromiseResult::Successful(vec![])]);
+        assert!(contract.merkle_close_callback(drop_id, NearToken::from_near(1), U128::from(40), merkle_leaf_deposit()));
+        let ledger = contract.get_escrow_ledger(owner);
+        assert_eq!(ledger.main_token.committed, U128::from(NearToken::from_near(1).as_yoctonear()));
+        assert_eq!(ledger.main_token.paid, U128::from(merkle_leaf_deposit().as_yoctonear()));
+    }
+}
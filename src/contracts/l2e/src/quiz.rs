@@ -0,0 +1,548 @@
+use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
+use near_sdk::json_types::{Base58CryptoHash, U128};
+use near_sdk::{env, log, near, require, AccountId, CryptoHash, NearToken, Promise};
+
+use crate::{
+    CampaignId, EventLog, EventLogVariant, GrantId, GrantStatus, L2eTop, L2eTopExt, QuizLog, QuizRevealLog, StageStatus,
+    StageUnlock, CONSTRACT_NAME, CONSTRACT_VERSION,
+};
+
+/// Failed reveals an account has for the quiz of a campaign, the answer hashes can't be tried one guess at a time.
+pub const QUIZ_MAX_ATTEMPTS: u32 = 3;
+
+/// Knowledge check of a campaign, answered with commit_quiz_answers then reveal_quiz_answers in a later block.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quiz {
+    pub campaign_id: CampaignId,
+    // quiz_answer_hash of the correct answer of each question, in order
+    pub answer_hashes: Vec<Base58CryptoHash>,
+    // public salt of answer_hashes
+    pub salt: String,
+    // correct answers needed to pass
+    pub passing_score: u32,
+    // grant created for a participant who passed and has no ByOwner stage to unlock, None to only unlock stages
+    pub reward: Option<QuizReward>,
+    // main token deposited with set_quiz for the rewards not granted yet
+    pub main_token_pool: NearToken,
+    pub passed_count: u64,
+}
+
+/// Grant created by a passing reveal, like an approve_for_spender of the campaign owner.
+/// The ft is pledged, the owner funds it with ft_transfer_call as for any grant.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuizReward {
+    pub main_token_amount: NearToken,
+    pub ft_amount: U128,
+    pub token_metadata: Option<TokenMetadata>,
+}
+
+/// Commitment of a participant, revealed from the next block on.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuizCommit {
+    pub commitment: Base58CryptoHash,
+    pub block_height: u64,
+}
+
+/// What the hash of a correct answer is made of, borsh serialized.
+#[near(serializers = [borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuizAnswer {
+    pub salt: String,
+    // index of the question in answer_hashes
+    pub question: u32,
+    pub answer: String,
+}
+
+/// What a participant commits to, borsh serialized. salt is the secret of the participant, not the one of the quiz.
+#[near(serializers = [borsh])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuizCommitment {
+    pub campaign_id: CampaignId,
+    pub account: AccountId,
+    pub answers: Vec<String>,
+    pub salt: String,
+}
+
+#[near]
+impl L2eTop {
+    /// Set the quiz of a campaign of the caller, replacing the previous one.
+    /// The attached deposit goes to the main token pool of the rewards, the pool of a replaced quiz is kept.
+    /// The salt and answer_hashes are public, anyone can hash candidate answers offline and compare them.
+    /// Answers have to be high-entropy, e.g. free text or many choices per question, not a short list of options.
+    #[payable]
+    pub fn set_quiz(
+        &mut self,
+        campaign_id: CampaignId,
+        answer_hashes: Vec<Base58CryptoHash>,
+        salt: String,
+        passing_score: u32,
+        reward: Option<QuizReward>,
+    ) -> bool {
+        let owner = env::predecessor_account_id();
+        let campaign = self.campaigns.get(&campaign_id).expect("Campaign not found");
+        require!(campaign.owner == owner, "Only the campaign owner can set its quiz");
+        require!(
+            passing_score > 0 && passing_score as usize <= answer_hashes.len(),
+            "passing_score should be between 1 and the number of questions"
+        );
+        if let Some(reward) = &reward {
+            require!(
+                reward.main_token_amount > NearToken::from_near(0),
+                "main_token_amount should be greater than 0"
+            );
+        }
+
+        let (main_token_pool, passed_count) = self
+            .quizzes
+            .get(&campaign_id)
+            .map_or((NearToken::from_near(0), 0), |x| (x.main_token_pool, x.passed_count));
+        let quiz = Quiz {
+            campaign_id,
+            answer_hashes,
+            salt,
+            passing_score,
+            reward,
+            main_token_pool: main_token_pool.saturating_add(env::attached_deposit()),
+            passed_count,
+        };
+        self.quiz_pool_total = self.quiz_pool_total.saturating_add(env::attached_deposit());
+        self.log_quiz(EventLogVariant::SetQuiz, &quiz, quiz.main_token_pool);
+        self.quizzes.insert(campaign_id, quiz);
+
+        true
+    }
+
+    /// Remove the quiz of a campaign of the caller and refund its main token pool.
+    /// Open commitments can't be revealed anymore. A failed refund is given back by remove_quiz_callback.
+    pub fn remove_quiz(&mut self, campaign_id: CampaignId) -> bool {
+        let owner = env::predecessor_account_id();
+        let campaign = self.campaigns.get(&campaign_id).expect("Campaign not found");
+        require!(campaign.owner == owner, "Only the campaign owner can remove its quiz");
+        let quiz = self.quizzes.remove(&campaign_id).expect("Quiz not found");
+        self.quiz_pool_total = self.quiz_pool_total.saturating_sub(quiz.main_token_pool);
+
+        if quiz.main_token_pool > NearToken::from_near(0) {
+            let _refund_promise = Promise::new(owner)
+                .transfer(quiz.main_token_pool)
+                .then(Self::ext(env::current_account_id()).remove_quiz_callback(quiz.clone()));
+        }
+        self.log_quiz(EventLogVariant::RemoveQuiz, &quiz, quiz.main_token_pool);
+
+        true
+    }
+
+    /// Resolve the pool refund of remove_quiz. A failed refund puts the quiz back,
+    /// or adds its pool to the quiz the owner set meanwhile, and can be removed again.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn remove_quiz_callback(&mut self, quiz: Quiz) -> bool {
+        if Self::promise_succeeded(0) {
+            log!("Refund quiz pool of campaign {}: {:?}", quiz.campaign_id, quiz.main_token_pool);
+            return true;
+        }
+
+        log!("Refund quiz pool of campaign {} failed: {:?}", quiz.campaign_id, quiz.main_token_pool);
+        self.quiz_pool_total = self.quiz_pool_total.saturating_add(quiz.main_token_pool);
+        match self.quizzes.get_mut(&quiz.campaign_id) {
+            Some(current) => current.main_token_pool = current.main_token_pool.saturating_add(quiz.main_token_pool),
+            None => {
+                self.quizzes.insert(quiz.campaign_id, quiz.clone());
+            }
+        }
+        self.log_quiz(EventLogVariant::RemoveQuizFailed, &quiz, quiz.main_token_pool);
+
+        false
+    }
+
+    /// Commit to the answers of the quiz of a campaign with quiz_commitment, replacing an unrevealed commitment.
+    /// An account has QUIZ_MAX_ATTEMPTS failed reveals per campaign.
+    pub fn commit_quiz_answers(&mut self, campaign_id: CampaignId, commitment: Base58CryptoHash) -> bool {
+        let spender = env::predecessor_account_id();
+        require!(self.quizzes.contains_key(&campaign_id), "Quiz not found");
+        require!(
+            !self.quiz_passed.contains(&(campaign_id, spender.clone())),
+            "Quiz is already passed"
+        );
+        require!(
+            self.get_quiz_attempts(campaign_id, spender.clone()) < QUIZ_MAX_ATTEMPTS,
+            "Quiz attempts are used up"
+        );
+        self.quiz_commits.insert(
+            (campaign_id, spender),
+            QuizCommit {
+                commitment,
+                block_height: env::block_height(),
+            },
+        );
+
+        true
+    }
+
+    /// Reveal the committed answers, from a block after the commitment so they can't be copied from a pending commit.
+    /// A failing reveal uses up one of the QUIZ_MAX_ATTEMPTS of the caller.
+    /// A passing reveal unlocks the first locked ByOwner stage of a grant of the caller in the campaign,
+    /// or creates a grant of the quiz reward from the pool. Returns whether the caller passed.
+    /// Without a stage to unlock, the pool and the campaign budget have to cover the reward before the answers
+    /// are scored, the commitment is kept until they do.
+    pub fn reveal_quiz_answers(&mut self, campaign_id: CampaignId, answers: Vec<String>, salt: String) -> bool {
+        let spender = env::predecessor_account_id();
+        let quiz = self.quizzes.get(&campaign_id).expect("Quiz not found").clone();
+        let commit = self
+            .quiz_commits
+            .get(&(campaign_id, spender.clone()))
+            .expect("No quiz commitment found")
+            .clone();
+        require!(
+            env::block_height() > commit.block_height,
+            "Answers are revealed in a later block than their commitment"
+        );
+        require!(answers.len() == quiz.answer_hashes.len(), "One answer is needed per question");
+        let stage_to_unlock = self.quiz_stage_to_unlock(campaign_id, &spender);
+        if stage_to_unlock.is_none() {
+            self.require_quiz_reward_covered(&quiz);
+        }
+        let commitment = quiz_commitment(&QuizCommitment {
+            campaign_id,
+            account: spender.clone(),
+            answers: answers.clone(),
+            salt,
+        });
+        require!(
+            commitment == CryptoHash::from(commit.commitment),
+            "Answers do not match the commitment"
+        );
+
+        let score = answers
+            .into_iter()
+            .zip(quiz.answer_hashes.iter())
+            .enumerate()
+            .filter(|(question, (answer, answer_hash))| {
+                quiz_answer_hash(&QuizAnswer {
+                    salt: quiz.salt.clone(),
+                    question: *question as u32,
+                    answer: answer.clone(),
+                }) == CryptoHash::from(**answer_hash)
+            })
+            .count() as u32;
+        let passed = score >= quiz.passing_score;
+        self.quiz_commits.remove(&(campaign_id, spender.clone()));
+        let mut grant_id = None;
+        let mut stage = None;
+        if passed {
+            self.quiz_passed.insert((campaign_id, spender.clone()));
+            self.quizzes.get_mut(&campaign_id).expect("Quiz not found").passed_count += 1;
+            match stage_to_unlock {
+                Some((locked_grant_id, locked_stage)) => {
+                    self.unlock_owner_stage(locked_grant_id, locked_stage);
+                    grant_id = Some(locked_grant_id);
+                    stage = Some(locked_stage);
+                }
+                None => grant_id = self.create_quiz_grant(&quiz, &spender),
+            }
+        } else {
+            let attempts = self.get_quiz_attempts(campaign_id, spender.clone());
+            self.quiz_attempts.insert((campaign_id, spender.clone()), attempts + 1);
+        }
+
+        let reveal_quiz_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: EventLogVariant::RevealQuiz(vec![QuizRevealLog {
+                campaign_id,
+                spender_id: spender.to_string(),
+                score,
+                passing_score: quiz.passing_score,
+                passed,
+                grant_id,
+                stage,
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+        env::log_str(&reveal_quiz_log.to_string());
+
+        passed
+    }
+
+    pub fn get_quiz(&self, campaign_id: CampaignId) -> Option<Quiz> {
+        self.quizzes.get(&campaign_id).cloned()
+    }
+
+    pub fn get_quiz_commit(&self, campaign_id: CampaignId, account_id: AccountId) -> Option<QuizCommit> {
+        self.quiz_commits.get(&(campaign_id, account_id)).cloned()
+    }
+
+    pub fn has_passed_quiz(&self, campaign_id: CampaignId, account_id: AccountId) -> bool {
+        self.quiz_passed.contains(&(campaign_id, account_id))
+    }
+
+    /// Failed reveals of account_id for the quiz of a campaign.
+    pub fn get_quiz_attempts(&self, campaign_id: CampaignId, account_id: AccountId) -> u32 {
+        self.quiz_attempts.get(&(campaign_id, account_id)).copied().unwrap_or(0)
+    }
+}
+
+impl L2eTop {
+    /// First locked ByOwner stage of an open grant of spender from the campaign owner.
+    fn quiz_stage_to_unlock(&self, campaign_id: CampaignId, spender: &AccountId) -> Option<(GrantId, u32)> {
+        let campaign_owner = &self.campaigns.get(&campaign_id).expect("Campaign not found").owner;
+        self.balances.get(spender)?.iter().find_map(|x| {
+            let open = self.grants.get(&x.grant_id).is_some_and(|record| {
+                matches!(
+                    record.status,
+                    GrantStatus::PendingMint | GrantStatus::Minted | GrantStatus::NftClaimed | GrantStatus::PaymentInFlight
+                )
+            });
+            if x.campaign_id != campaign_id || &x.owner != campaign_owner || !open {
+                return None;
+            }
+            x.stages
+                .iter()
+                .position(|stage| stage.unlock == StageUnlock::ByOwner && stage.status == StageStatus::Locked)
+                .map(|stage| (x.grant_id, stage as u32))
+        })
+    }
+
+    /// Panics while the pool or the campaign budget can't cover the quiz reward, a quiz without reward is covered.
+    fn require_quiz_reward_covered(&self, quiz: &Quiz) {
+        let reward = match &quiz.reward {
+            Some(reward) => reward,
+            None => return,
+        };
+        require!(
+            quiz.main_token_pool >= reward.main_token_amount,
+            "Quiz pool does not cover the reward"
+        );
+        let campaign = self.campaigns.get(&quiz.campaign_id).expect("Campaign not found");
+        require!(
+            campaign.has_budget_for(reward.main_token_amount, reward.ft_amount),
+            "Campaign budget exceeded"
+        );
+    }
+
+    /// Grant the quiz reward to spender from the pool, on behalf of the campaign owner.
+    /// reveal_quiz_answers checked with require_quiz_reward_covered that the pool covers it.
+    fn create_quiz_grant(&mut self, quiz: &Quiz, spender: &AccountId) -> Option<GrantId> {
+        let reward = quiz.reward.clone()?;
+        let owner = self.campaigns.get(&quiz.campaign_id).expect("Campaign not found").owner.clone();
+        let pool = &mut self.quizzes.get_mut(&quiz.campaign_id).expect("Quiz not found").main_token_pool;
+        *pool = pool.saturating_sub(reward.main_token_amount);
+        self.quiz_pool_total = self.quiz_pool_total.saturating_sub(reward.main_token_amount);
+
+        Some(self.create_grant(
+            &owner,
+            quiz.campaign_id,
+            spender.clone(),
+            reward.main_token_amount,
+            reward.ft_amount,
+            reward.token_metadata,
+            None,
+            None,
+            None,
+            None,
+        ))
+    }
+
+    fn log_quiz(&self, event: fn(Vec<QuizLog>) -> EventLogVariant, quiz: &Quiz, main_token_amount: NearToken) {
+        let owner = &self.campaigns.get(&quiz.campaign_id).expect("Campaign not found").owner;
+        let quiz_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: event(vec![QuizLog {
+                campaign_id: quiz.campaign_id,
+                owner_id: owner.to_string(),
+                questions: quiz.answer_hashes.len() as u32,
+                passing_score: quiz.passing_score,
+                main_token_amount: main_token_amount.as_yoctonear().to_string(),
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+
+        env::log_str(&quiz_log.to_string());
+    }
+}
+
+/// Hash of a correct answer of a quiz.
+pub fn quiz_answer_hash(answer: &QuizAnswer) -> CryptoHash {
+    env::sha256_array(near_sdk::borsh::to_vec(answer).expect("Failed to serialize the quiz answer"))
+}
+
+/// Hash a participant commits to before revealing the answers.
+pub fn quiz_commitment(commitment: &QuizCommitment) -> CryptoHash {
+    env::sha256_array(near_sdk::borsh::to_vec(commitment).expect("Failed to serialize the quiz commitment"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::DEFAULT_CAMPAIGN_ID;
+    use near_sdk::{testing_env, PromiseResult};
+
+    /// Quiz of the default campaign with the correct answers "a", "b", "c", 2 needed to pass.
+    fn set_default_quiz(contract: &mut L2eTop, owner: &AccountId, reward: Option<QuizReward>, pool: NearToken) {
+        let answer_hashes = ["a", "b", "c"]
+            .iter()
+            .enumerate()
+            .map(|(question, answer)| {
+                quiz_answer_hash(&QuizAnswer {
+                    salt: "rust-101".to_string(),
+                    question: question as u32,
+                    answer: answer.to_string(),
+                })
+                .into()
+            })
+            .collect();
+        testing_env!(context(owner).attached_deposit(pool).build());
+        assert!(contract.set_quiz(DEFAULT_CAMPAIGN_ID, answer_hashes, "rust-101".to_string(), 2, reward));
+    }
+
+    /// Commit answers at block 10 and reveal them at reveal_block as spender.
+    fn commit_and_reveal(contract: &mut L2eTop, spender: &AccountId, answers: &[&str], reveal_block: u64) -> bool {
+        let answers: Vec<String> = answers.iter().map(|x| x.to_string()).collect();
+        let commitment = quiz_commitment(&QuizCommitment {
+            campaign_id: DEFAULT_CAMPAIGN_ID,
+            account: spender.clone(),
+            answers: answers.clone(),
+            salt: "secret".to_string(),
+        });
+        testing_env!(context(spender).block_height(10).build());
+        assert!(contract.commit_quiz_answers(DEFAULT_CAMPAIGN_ID, commitment.into()));
+
+        testing_env!(context(spender).block_height(reveal_block).build());
+        contract.reveal_quiz_answers(DEFAULT_CAMPAIGN_ID, answers, "secret".to_string())
+    }
+
+    #[test]
+    fn test_reveal_quiz_answers_creates_grant_from_pool() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let reward = QuizReward {
+            main_token_amount: NearToken::from_near(1),
+            ft_amount: U128::from(0),
+            token_metadata: None,
+        };
+        set_default_quiz(&mut contract, &owner, Some(reward), NearToken::from_near(2));
+
+        assert!(!commit_and_reveal(&mut contract, &spender, &["a", "x", "y"], 11));
+        assert!(!contract.has_passed_quiz(DEFAULT_CAMPAIGN_ID, spender.clone()));
+        assert_eq!(contract.get_quiz_commit(DEFAULT_CAMPAIGN_ID, spender.clone()), None);
+
+        let grant_id = contract.next_grant_id;
+        assert!(commit_and_reveal(&mut contract, &spender, &["a", "x", "c"], 11));
+        assert!(contract.has_passed_quiz(DEFAULT_CAMPAIGN_ID, spender.clone()));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::PendingMint));
+        assert_eq!(contract.balance_entry(grant_id).unwrap().main_token_amount, NearToken::from_near(1));
+        let quiz = contract.get_quiz(DEFAULT_CAMPAIGN_ID).unwrap();
+        assert_eq!(quiz.main_token_pool, NearToken::from_near(1));
+        assert_eq!(quiz.passed_count, 1);
+    }
+
+    #[test]
+    fn test_reveal_quiz_answers_unlocks_owner_stage() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let grant_id = approve_staged_grant(
+            &mut contract,
+            &owner,
+            &spender,
+            U128::from(0),
+            vec![stage(1, 0, StageUnlock::AfterPreviousStage), stage(1, 0, StageUnlock::ByOwner)],
+        );
+        set_default_quiz(&mut contract, &owner, None, NearToken::from_near(0));
+
+        let next_grant_id = contract.next_grant_id;
+        assert!(commit_and_reveal(&mut contract, &spender, &["a", "b", "c"], 11));
+        assert_eq!(contract.balance_entry(grant_id).unwrap().stages[1].status, StageStatus::Unlocked);
+        assert_eq!(contract.next_grant_id, next_grant_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Answers are revealed in a later block than their commitment")]
+    fn test_reveal_quiz_answers_waits_for_later_block() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        set_default_quiz(&mut contract, &owner, None, NearToken::from_near(0));
+
+        commit_and_reveal(&mut contract, &spender, &["a", "b", "c"], 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Quiz pool does not cover the reward")]
+    fn test_reveal_quiz_answers_requires_covered_reward() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let reward = QuizReward {
+            main_token_amount: NearToken::from_near(1),
+            ft_amount: U128::from(0),
+            token_metadata: None,
+        };
+        set_default_quiz(&mut contract, &owner, Some(reward), NearToken::from_near(0));
+
+        commit_and_reveal(&mut contract, &spender, &["a", "b", "c"], 11);
+    }
+
+    #[test]
+    fn test_remove_quiz_callback_restores_quiz() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        set_default_quiz(&mut contract, &owner, None, NearToken::from_near(2));
+        let quiz = contract.get_quiz(DEFAULT_CAMPAIGN_ID).unwrap();
+        testing_env!(context(&owner).build());
+        assert!(contract.remove_quiz(DEFAULT_CAMPAIGN_ID));
+        assert_eq!(contract.get_quiz(DEFAULT_CAMPAIGN_ID), None);
+
+        callback_env(vec![PromiseResult::Failed]);
+        assert!(!contract.remove_quiz_callback(quiz.clone()));
+        assert_eq!(contract.get_quiz(DEFAULT_CAMPAIGN_ID), Some(quiz));
+    }
+
+    #[test]
+    #[should_panic(expected = "Quiz attempts are used up")]
+    fn test_commit_quiz_answers_rejects_after_max_attempts() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        set_default_quiz(&mut contract, &owner, None, NearToken::from_near(0));
+
+        for _ in 0..QUIZ_MAX_ATTEMPTS {
+            assert!(!commit_and_reveal(&mut contract, &spender, &["x", "y", "z"], 11));
+        }
+        assert_eq!(contract.get_quiz_attempts(DEFAULT_CAMPAIGN_ID, spender.clone()), QUIZ_MAX_ATTEMPTS);
+        commit_and_reveal(&mut contract, &spender, &["a", "b", "c"], 11);
+    }
+
+    #[test]
+    fn test_escrow_report_counts_quiz_pool() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        set_default_quiz(&mut contract, &owner, None, NearToken::from_near(2));
+        assert_eq!(
+            contract.escrow_report().main_token.liabilities,
+            U128::from(NearToken::from_near(2).as_yoctonear())
+        );
+
+        testing_env!(context(&owner).build());
+        assert!(contract.remove_quiz(DEFAULT_CAMPAIGN_ID));
+        assert_eq!(contract.escrow_report().main_token.liabilities, U128::from(0));
+    }
+}
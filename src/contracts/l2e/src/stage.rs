@@ -0,0 +1,652 @@
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, log, near, require, AccountId, NearToken};
+
+use crate::{
+    Attestation, BalanceEntry, CampaignId, EscrowAsset, EventLog, EventLogVariant, GrantId, GrantRecord, GrantStatus,
+    L2eTop, L2eTopExt, NftEntry, StageLog, CONSTRACT_NAME, CONSTRACT_VERSION,
+};
+
+/// Condition for a stage to unlock, on top of the previous stage being claimed.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub enum StageUnlock {
+    // as soon as the previous stage is claimed
+    AfterPreviousStage,
+    // from a block timestamp in nanoseconds
+    At(U64),
+    // once the owner confirmed the stage is completed with unlock_stage
+    ByOwner,
+}
+
+/// Stored state of a stage, `get_stages_for_spender` shows a locked stage as unlocked once it can be claimed.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StageStatus {
+    Locked,
+    Unlocked,
+    // claim_stage payout in flight, locked until claim_stage_callback
+    Paying,
+    Claimed,
+}
+
+/// A stage of a grant as given to approve_for_spender.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct StageSchedule {
+    pub main_token_amount: NearToken,
+    // raw units of the ft contract of the grant
+    pub ft_amount: U128,
+    pub unlock: StageUnlock,
+}
+
+/// A stage of a grant, stored in order in `BalanceEntry::stages`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrantStage {
+    // unpaid amounts of the stage, a failed claim leaves only the unpaid legs
+    pub main_token_amount: NearToken,
+    pub ft_amount: U128,
+    pub unlock: StageUnlock,
+    pub status: StageStatus,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct StageView {
+    pub stage: u32,
+    pub main_token_amount: U128,
+    pub ft_amount: U128,
+    pub unlock: StageUnlock,
+    pub status: StageStatus,
+}
+
+/// Stages of one grant for the views of spenders and owners.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrantStagesView {
+    pub grant_id: GrantId,
+    pub campaign_id: CampaignId,
+    pub owner: AccountId,
+    pub spender: AccountId,
+    pub stages: Vec<StageView>,
+}
+
+impl GrantStage {
+    fn new(schedule: StageSchedule) -> Self {
+        GrantStage {
+            main_token_amount: schedule.main_token_amount,
+            ft_amount: schedule.ft_amount,
+            unlock: schedule.unlock,
+            status: StageStatus::Locked,
+        }
+    }
+}
+
+#[near]
+impl L2eTop {
+    /// Pay the main token and ft of one stage of a grant, once its nft is claimed.
+    /// Stages are claimed in order, a stage also waits for its unlock condition.
+    /// The grant is removed when its last stage is paid. The stage is paid to receiver_id if given.
+    /// In a campaign with verifier keys the claim needs an attestation of a verifier for the stage, see `Attestation`.
+    /// In a campaign with a verifier contract the stage is paid once it verified the proof, see `verify_stage_callback`.
+    pub fn claim_stage(
+        &mut self,
+        grant_id: GrantId,
+        stage: u32,
+        receiver_id: Option<AccountId>,
+        attestation: Option<Attestation>,
+        proof: Option<String>,
+    ) -> bool {
+        let spender = env::predecessor_account_id();
+        let campaign_id = self.grant_record(grant_id).campaign_id;
+        require!(self.grant_record(grant_id).spender == spender, "Grant is not approved for spender");
+        require!(self.is_nft_claimed(grant_id), "No claimed nft found for spender");
+
+        let now = env::block_timestamp();
+        let balance_entry = self.grant_mut(grant_id);
+        require!(!balance_entry.is_expired(now), "Grant is expired");
+        require!((stage as usize) < balance_entry.stages.len(), "Stage not found");
+        let grant_stage = balance_entry.stages[stage as usize].clone();
+        require!(grant_stage.status != StageStatus::Claimed, "Stage is already claimed");
+        require!(grant_stage.status != StageStatus::Paying, "Stage claim is already in progress");
+        require!(
+            balance_entry.stages[..stage as usize].iter().all(|x| x.status == StageStatus::Claimed),
+            "Previous stage is not claimed yet"
+        );
+        require!(is_unlocked(&grant_stage, now), "Stage is locked");
+        require!(balance_entry.ft_escrowed.0 >= grant_stage.ft_amount.0, "Ft of the stage is not funded yet");
+        let status = self.grant_record(grant_id).status;
+        require!(
+            status.can_become(GrantStatus::PaymentInFlight),
+            format!("Stage can't be claimed from a {:?} grant", status)
+        );
+        self.use_attestation(campaign_id, &spender, Some(stage), attestation);
+        let receiver_id = Self::claim_receiver(&spender, receiver_id);
+
+        if let Some(verifier_contract) = self.verifier_contract(campaign_id) {
+            // Paying keeps the stage from a second claim until verify_stage_callback
+            self.grant_mut(grant_id).stages[stage as usize].status = StageStatus::Paying;
+            let _verify_promise = Self::verify_claim(&verifier_contract, campaign_id, &spender, proof).then(
+                Self::ext(env::current_account_id()).verify_stage_callback(grant_id, stage, grant_stage.status, receiver_id),
+            );
+            return true;
+        }
+        self.start_stage_payout(grant_id, stage, receiver_id)
+    }
+
+    /// Resolve the payout started by `claim_stage`.
+    /// The promise results are in order: main token transfer, ft_transfer. A zero leg is not part of the results.
+    /// Failed legs are put back into the grant and the stage, which can be claimed again.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn claim_stage_callback(
+        &mut self,
+        grant_id: GrantId,
+        stage: u32,
+        grant_stage: GrantStage,
+        receiver_id: Option<AccountId>,
+    ) -> bool {
+        let GrantRecord { campaign_id, owner, spender, .. } = self.grant_record(grant_id);
+        let (main_token_paid, ft_paid) = Self::payout_results(grant_stage.main_token_amount, grant_stage.ft_amount);
+        let unpaid_main_token = if main_token_paid { NearToken::from_near(0) } else { grant_stage.main_token_amount };
+        let unpaid_ft = if ft_paid { 0 } else { grant_stage.ft_amount.0 };
+        let erc20_address = self.grant_mut(grant_id).erc20_address.clone();
+        let paid_main_token = grant_stage.main_token_amount.saturating_sub(unpaid_main_token);
+        let paid_ft = grant_stage.ft_amount.0.saturating_sub(unpaid_ft);
+        if paid_main_token > NearToken::from_near(0) {
+            self.escrow_pay(&owner, EscrowAsset::MainToken, paid_main_token.as_yoctonear());
+        }
+        if paid_ft > 0 {
+            self.escrow_pay(&owner, EscrowAsset::Ft(erc20_address), paid_ft);
+        }
+
+        if main_token_paid && ft_paid {
+            self.finish_stage(grant_id, stage, &grant_stage, receiver_id);
+            return true;
+        }
+
+        self.try_set_grant_status(grant_id, GrantStatus::NftClaimed);
+        let balance_entry = self.grant_mut(grant_id);
+        balance_entry.main_token_amount = balance_entry.main_token_amount.saturating_add(unpaid_main_token);
+        balance_entry.ft_pledged = U128::from(balance_entry.ft_pledged.0.saturating_add(unpaid_ft));
+        balance_entry.ft_escrowed = U128::from(balance_entry.ft_escrowed.0.saturating_add(unpaid_ft));
+        // the stage was claimable, it stays unlocked for the next claim
+        balance_entry.stages[stage as usize] = GrantStage {
+            main_token_amount: unpaid_main_token,
+            ft_amount: U128::from(unpaid_ft),
+            status: StageStatus::Unlocked,
+            ..grant_stage
+        };
+
+        Self::log_stage(
+            EventLogVariant::ClaimStageFailed,
+            StageLog {
+                owner_id: owner.to_string(),
+                spender_id: spender.to_string(),
+                stage,
+                main_token_amount: unpaid_main_token.as_yoctonear().to_string(),
+                ft_token_amount: unpaid_ft.to_string(),
+                receiver_id: receiver_id.map(|x| x.to_string()),
+                grant_id: Some(grant_id),
+                campaign_id: Some(campaign_id),
+                memo: Some("L2E Team".to_string()),
+            },
+        );
+
+        false
+    }
+
+    /// Confirm a stage with the `ByOwner` unlock condition is completed, called by the owner of the grant.
+    pub fn unlock_stage(&mut self, grant_id: GrantId, stage: u32) -> bool {
+        let owner = env::predecessor_account_id();
+        require!(self.grant_record(grant_id).owner == owner, "Only the owner can unlock a stage");
+        self.unlock_owner_stage(grant_id, stage);
+
+        true
+    }
+
+    /// Stages of every staged grant of spender, in campaign_id or in all campaigns.
+    pub fn get_stages_for_spender(&self, spender: AccountId, campaign_id: Option<CampaignId>) -> Vec<GrantStagesView> {
+        let now = env::block_timestamp();
+        self.balances
+            .get(&spender)
+            .map(|balances| {
+                balances
+                    .iter()
+                    .filter(|x| !x.stages.is_empty() && (campaign_id.is_none() || campaign_id == Some(x.campaign_id)))
+                    .map(|x| stages_view(&spender, x, now))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Stages of every staged grant of owner, in campaign_id or in all campaigns.
+    pub fn get_stages_for_owner(&self, owner: AccountId, campaign_id: Option<CampaignId>) -> Vec<GrantStagesView> {
+        let now = env::block_timestamp();
+        let grants: Vec<NftEntry> = self
+            .nfts
+            .get(&owner)
+            .map(|nfts| {
+                nfts.iter()
+                    .filter(|x| campaign_id.is_none() || campaign_id == Some(x.campaign_id))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        grants
+            .iter()
+            .filter_map(|nft_entry| {
+                self.balance_entry(nft_entry.grant_id)
+                    .filter(|x| !x.stages.is_empty())
+                    .map(|x| stages_view(&nft_entry.spender, &x, now))
+            })
+            .collect()
+    }
+}
+
+impl L2eTop {
+    /// Pay a claimable stage to receiver_id or the spender, resolved by claim_stage_callback.
+    /// Returns false and leaves the grant as it is when its escrowed ft no longer covers the stage
+    /// or the grant can't go to PaymentInFlight, verify_stage_callback reaches it after an async gap.
+    pub(crate) fn start_stage_payout(&mut self, grant_id: GrantId, stage: u32, receiver_id: Option<AccountId>) -> bool {
+        let spender = self.grant_record(grant_id).spender;
+        let balance_entry = self.grant_mut(grant_id);
+        let grant_stage = balance_entry.stages[stage as usize].clone();
+        let ft_escrowed = match balance_entry.ft_escrowed.0.checked_sub(grant_stage.ft_amount.0) {
+            Some(ft_escrowed) => ft_escrowed,
+            None => {
+                log!("start_stage_payout: ft of stage {} of grant {} is not funded.", stage, grant_id);
+                return false;
+            }
+        };
+        if !self.try_set_grant_status(grant_id, GrantStatus::PaymentInFlight) {
+            return false;
+        }
+
+        // the stage amounts leave the grant now, claim_stage_callback puts back what fails to pay
+        let balance_entry = self.grant_mut(grant_id);
+        balance_entry.main_token_amount = balance_entry.main_token_amount.saturating_sub(grant_stage.main_token_amount);
+        balance_entry.ft_pledged = U128::from(balance_entry.ft_pledged.0.saturating_sub(grant_stage.ft_amount.0));
+        balance_entry.ft_escrowed = U128::from(ft_escrowed);
+        balance_entry.stages[stage as usize].status = StageStatus::Paying;
+        let erc20_address = balance_entry.erc20_address.clone();
+
+        let payout = Self::payout(
+            receiver_id.as_ref().unwrap_or(&spender),
+            grant_stage.main_token_amount,
+            &erc20_address,
+            grant_stage.ft_amount,
+            receiver_id.is_some(),
+        );
+        match payout {
+            Some(payout) => {
+                let _claim_stage_promise = payout.then(
+                    Self::ext(env::current_account_id())
+                        .claim_stage_callback(grant_id, stage, grant_stage, receiver_id),
+                );
+            }
+            None => self.finish_stage(grant_id, stage, &grant_stage, receiver_id),
+        }
+
+        true
+    }
+
+    /// Unlock a locked ByOwner stage of a grant, for unlock_stage and a passing quiz reveal.
+    pub(crate) fn unlock_owner_stage(&mut self, grant_id: GrantId, stage: u32) {
+        let GrantRecord { campaign_id, owner, spender, .. } = self.grant_record(grant_id);
+        let balance_entry = self.grant_mut(grant_id);
+        let grant_stage = balance_entry.stages.get_mut(stage as usize).expect("Stage not found");
+        require!(grant_stage.unlock == StageUnlock::ByOwner, "Stage is not unlocked by the owner");
+        require!(grant_stage.status == StageStatus::Locked, "Stage is already unlocked");
+        grant_stage.status = StageStatus::Unlocked;
+        let (main_token_amount, ft_amount) = (grant_stage.main_token_amount, grant_stage.ft_amount);
+
+        Self::log_stage(
+            EventLogVariant::UnlockStage,
+            StageLog {
+                owner_id: owner.to_string(),
+                spender_id: spender.to_string(),
+                stage,
+                main_token_amount: main_token_amount.as_yoctonear().to_string(),
+                ft_token_amount: ft_amount.0.to_string(),
+                receiver_id: None,
+                grant_id: Some(grant_id),
+                campaign_id: Some(campaign_id),
+                memo: Some("L2E Team".to_string()),
+            },
+        );
+    }
+
+    /// Stages of a grant from approve_for_spender, their amounts add up to the amounts of the grant.
+    pub(crate) fn new_stages(
+        stages: Option<Vec<StageSchedule>>,
+        main_token_amount: NearToken,
+        ft_amount: U128,
+    ) -> Vec<GrantStage> {
+        let stages = match stages {
+            Some(stages) => stages,
+            None => return vec![],
+        };
+        require!(!stages.is_empty(), "stages should not be empty");
+        require!(
+            stages.iter().all(|x| x.main_token_amount > NearToken::from_near(0) || x.ft_amount.0 > 0),
+            "Every stage should have main token or ft"
+        );
+        let stages_main_token = stages
+            .iter()
+            .fold(NearToken::from_near(0), |total, x| total.saturating_add(x.main_token_amount));
+        let stages_ft = stages.iter().fold(0u128, |total, x| total.saturating_add(x.ft_amount.0));
+        require!(
+            stages_main_token == main_token_amount && stages_ft == ft_amount.0,
+            "Stage amounts should add up to main_token_amount and ft_amount"
+        );
+        stages.into_iter().map(GrantStage::new).collect()
+    }
+
+    /// Mark a paid stage claimed, the grant and its nft entry are removed and the grant is paid once every stage is claimed.
+    fn finish_stage(&mut self, grant_id: GrantId, stage: u32, grant_stage: &GrantStage, receiver_id: Option<AccountId>) {
+        let GrantRecord { campaign_id, owner, spender, .. } = self.grant_record(grant_id);
+        let balance_entry = self.grant_mut(grant_id);
+        balance_entry.stages[stage as usize].status = StageStatus::Claimed;
+        if balance_entry.stages.iter().all(|x| x.status == StageStatus::Claimed) {
+            self.take_balance_entry(grant_id);
+            self.take_nft_entry(grant_id);
+            self.try_set_grant_status(grant_id, GrantStatus::Paid);
+        } else {
+            self.try_set_grant_status(grant_id, GrantStatus::NftClaimed);
+        }
+
+        Self::log_stage(
+            EventLogVariant::ClaimStage,
+            StageLog {
+                owner_id: owner.to_string(),
+                spender_id: spender.to_string(),
+                stage,
+                main_token_amount: grant_stage.main_token_amount.as_yoctonear().to_string(),
+                ft_token_amount: grant_stage.ft_amount.0.to_string(),
+                receiver_id: receiver_id.map(|x| x.to_string()),
+                grant_id: Some(grant_id),
+                campaign_id: Some(campaign_id),
+                memo: Some("L2E Team".to_string()),
+            },
+        );
+    }
+
+    fn log_stage(event: fn(Vec<StageLog>) -> EventLogVariant, stage_log: StageLog) {
+        let stage_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: event(vec![stage_log]),
+        };
+
+        env::log_str(&stage_log.to_string());
+    }
+}
+
+/// Whether the unlock condition of a stage is met at block timestamp `now`, the stage order is checked by the caller.
+fn is_unlocked(grant_stage: &GrantStage, now: u64) -> bool {
+    match grant_stage.status {
+        StageStatus::Unlocked => true,
+        StageStatus::Locked => match &grant_stage.unlock {
+            StageUnlock::AfterPreviousStage => true,
+            StageUnlock::At(unlocks_at) => unlocks_at.0 <= now,
+            StageUnlock::ByOwner => false,
+        },
+        StageStatus::Paying | StageStatus::Claimed => false,
+    }
+}
+
+fn stages_view(spender: &AccountId, balance_entry: &BalanceEntry, now: u64) -> GrantStagesView {
+    let mut previous_claimed = true;
+    let stages = balance_entry
+        .stages
+        .iter()
+        .enumerate()
+        .map(|(index, grant_stage)| {
+            let status = match grant_stage.status {
+                StageStatus::Locked | StageStatus::Unlocked if previous_claimed && is_unlocked(grant_stage, now) => {
+                    StageStatus::Unlocked
+                }
+                StageStatus::Locked | StageStatus::Unlocked => StageStatus::Locked,
+                status => status,
+            };
+            previous_claimed = grant_stage.status == StageStatus::Claimed;
+            StageView {
+                stage: index as u32,
+                main_token_amount: U128::from(grant_stage.main_token_amount.as_yoctonear()),
+                ft_amount: grant_stage.ft_amount,
+                unlock: grant_stage.unlock.clone(),
+                status,
+            }
+        })
+        .collect();
+
+    GrantStagesView {
+        grant_id: balance_entry.grant_id,
+        campaign_id: balance_entry.campaign_id,
+        owner: balance_entry.owner.clone(),
+        spender: spender.clone(),
+        stages,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::DEFAULT_CAMPAIGN_ID;
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::{testing_env, PromiseResult};
+
+    /// Run claim_stage_callback for a stage claimed by claim_stage, with the given payout results.
+    fn resolve_stage(contract: &mut L2eTop, grant_id: GrantId, stage: u32, results: Vec<PromiseResult>) -> bool {
+        let grant_stage = contract.balance_entry(grant_id).unwrap().stages[stage as usize].clone();
+        assert_eq!(grant_stage.status, StageStatus::Paying);
+        callback_env(results);
+        contract.claim_stage_callback(grant_id, stage, grant_stage, None)
+    }
+
+    fn stage_statuses(contract: &L2eTop, spender: &AccountId) -> Vec<StageStatus> {
+        contract.get_stages_for_spender(spender.clone(), None)[0].stages.iter().map(|x| x.status).collect()
+    }
+
+    #[test]
+    fn test_start_stage_payout_skips_unfunded_stage() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let grant_id = approve_staged_grant(
+            &mut contract,
+            &owner,
+            &spender,
+            U128::from(100),
+            vec![stage(1, 100, StageUnlock::AfterPreviousStage)],
+        );
+
+        // as reached from verify_stage_callback, the ft of the stage was never escrowed
+        assert!(!contract.start_stage_payout(grant_id, 0, None));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::NftClaimed));
+        let balance_entry = contract.balance_entry(grant_id).unwrap();
+        assert_eq!(balance_entry.main_token_amount, NearToken::from_near(1));
+        assert_eq!(balance_entry.ft_escrowed, U128::from(0));
+        assert_eq!(stage_statuses(&contract, &spender), vec![StageStatus::Unlocked]);
+    }
+
+    #[test]
+    fn test_claim_stage_in_order() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let grant_id = approve_staged_grant(
+            &mut contract,
+            &owner,
+            &spender,
+            U128::from(0),
+            vec![
+                stage(1, 0, StageUnlock::AfterPreviousStage),
+                stage(2, 0, StageUnlock::At(U64::from(5_000))),
+                stage(3, 0, StageUnlock::ByOwner),
+            ],
+        );
+        assert_eq!(
+            stage_statuses(&contract, &spender),
+            vec![StageStatus::Unlocked, StageStatus::Locked, StageStatus::Locked]
+        );
+
+        testing_env!(context(&spender).block_timestamp(1_000).build());
+        assert!(contract.claim_stage(grant_id, 0, None, None, None));
+        assert_eq!(
+            stage_statuses(&contract, &spender),
+            vec![StageStatus::Paying, StageStatus::Locked, StageStatus::Locked]
+        );
+        assert!(resolve_stage(&mut contract, grant_id, 0, vec![PromiseResult::Successful(vec![])]));
+
+        // stage 1 unlocks at 5000
+        testing_env!(context(&spender).block_timestamp(1_000).build());
+        assert_eq!(
+            stage_statuses(&contract, &spender),
+            vec![StageStatus::Claimed, StageStatus::Locked, StageStatus::Locked]
+        );
+        testing_env!(context(&spender).block_timestamp(5_000).build());
+        assert!(contract.claim_stage(grant_id, 1, None, None, None));
+        assert!(resolve_stage(&mut contract, grant_id, 1, vec![PromiseResult::Successful(vec![])]));
+
+        // stage 2 is unlocked by the owner
+        testing_env!(context(&owner).block_timestamp(5_000).build());
+        let owner_view = contract.get_stages_for_owner(owner.clone(), Some(DEFAULT_CAMPAIGN_ID));
+        assert_eq!(owner_view.len(), 1);
+        assert_eq!(owner_view[0].grant_id, grant_id);
+        assert_eq!(owner_view[0].spender, spender);
+        assert_eq!(owner_view[0].stages[2].status, StageStatus::Locked);
+        assert!(contract.unlock_stage(grant_id, 2));
+        assert_eq!(
+            stage_statuses(&contract, &spender),
+            vec![StageStatus::Claimed, StageStatus::Claimed, StageStatus::Unlocked]
+        );
+
+        testing_env!(context(&spender).block_timestamp(5_000).build());
+        assert!(contract.claim_stage(grant_id, 2, None, None, None));
+        assert_eq!(contract.balance_entry(grant_id).unwrap().main_token_amount, NearToken::from_near(0));
+        assert!(resolve_stage(&mut contract, grant_id, 2, vec![PromiseResult::Successful(vec![])]));
+
+        // the last stage removes the grant
+        assert!(contract.balances.get(&spender).unwrap().iter().all(|x| x.owner != owner));
+        assert!(contract.nfts.get(&owner).unwrap().iter().all(|x| x.spender != spender));
+        assert_eq!(
+            contract.get_escrow_ledger(owner).main_token.paid,
+            U128::from(NearToken::from_near(6).as_yoctonear())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Previous stage is not claimed yet")]
+    fn test_claim_stage_rejects_skipped_stage() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let grant_id = approve_staged_grant(
+            &mut contract,
+            &owner,
+            &spender,
+            U128::from(0),
+            vec![stage(1, 0, StageUnlock::AfterPreviousStage), stage(1, 0, StageUnlock::AfterPreviousStage)],
+        );
+
+        contract.claim_stage(grant_id, 1, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Stage is locked")]
+    fn test_claim_stage_waits_for_owner_unlock() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let grant_id =
+            approve_staged_grant(&mut contract, &owner, &spender, U128::from(0), vec![stage(1, 0, StageUnlock::ByOwner)]);
+
+        contract.claim_stage(grant_id, 0, None, None, None);
+    }
+
+    #[test]
+    fn test_claim_stage_callback_restores_failed_leg() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init(erc20.clone(), "erc721.near".parse().unwrap());
+        let grant_id = approve_staged_grant(
+            &mut contract,
+            &owner,
+            &spender,
+            U128::from(100),
+            vec![stage(1, 100, StageUnlock::AfterPreviousStage), stage(1, 0, StageUnlock::AfterPreviousStage)],
+        );
+
+        testing_env!(context(&erc20).build());
+        let msg = format!(r#"{{"grant_id":{}}}"#, grant_id);
+        let _ = contract.ft_on_transfer(owner.clone(), U128::from(100), msg);
+
+        testing_env!(context(&spender).build());
+        assert!(contract.claim_stage(grant_id, 0, None, None, None));
+        // main token transfer succeeded, ft_transfer failed
+        assert!(!resolve_stage(
+            &mut contract,
+            grant_id,
+            0,
+            vec![PromiseResult::Successful(vec![]), PromiseResult::Failed]
+        ));
+
+        let grant = contract.balance_entry(grant_id).unwrap();
+        assert_eq!(grant.main_token_amount, NearToken::from_near(1));
+        assert_eq!(grant.ft_escrowed, U128::from(100));
+        assert_eq!(grant.stages[0].main_token_amount, NearToken::from_near(0));
+        assert_eq!(grant.stages[0].ft_amount, U128::from(100));
+        assert_eq!(grant.stages[0].status, StageStatus::Unlocked);
+        assert_eq!(
+            contract.get_escrow_ledger(owner).main_token.paid,
+            U128::from(NearToken::from_near(1).as_yoctonear())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Stage amounts should add up to main_token_amount and ft_amount")]
+    fn test_approve_for_spender_rejects_unbalanced_stages() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+
+        testing_env!(context(&owner).attached_deposit(NearToken::from_near(2)).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        contract.approve_for_spender(
+            DEFAULT_CAMPAIGN_ID,
+            "spender.near".parse().unwrap(),
+            NearToken::from_near(2),
+            U128::from(0),
+            None,
+            None,
+            Some(vec![stage(1, 0, StageUnlock::AfterPreviousStage)]),
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Grant is paid by stage, use claim_stage")]
+    fn test_transfer_balances_from_rejects_staged_grant() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let grant_id =
+            approve_staged_grant(&mut contract, &owner, &spender, U128::from(0), vec![stage(1, 0, StageUnlock::AfterPreviousStage)]);
+
+        contract.transfer_balances_from(grant_id, None);
+    }
+}
@@ -0,0 +1,95 @@
+use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_sdk::json_types::U128;
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{env, testing_env, AccountId, NearToken, PromiseResult};
+
+use crate::{GrantId, L2eTop, StageSchedule, StageUnlock, VestingTerms, DEFAULT_CAMPAIGN_ID};
+
+/// Context of a call by predecessor, chain the other settings of the test on it.
+pub(crate) fn context(predecessor: &AccountId) -> VMContextBuilder {
+    let mut builder = VMContextBuilder::new();
+    builder.predecessor_account_id(predecessor.clone());
+    builder
+}
+
+/// Context of a callback run with the given results of the promises it was chained to.
+pub(crate) fn callback_env(results: Vec<PromiseResult>) {
+    testing_env!(
+        VMContextBuilder::new().build(),
+        near_sdk::test_vm_config(),
+        near_sdk::RuntimeFeesConfig::test(),
+        Default::default(),
+        results,
+    );
+}
+
+/// approve_for_spender as `owner` and confirm the mint, returns the grant id and the nft tokenid of the grant.
+pub(crate) fn approve_and_mint(
+    contract: &mut L2eTop,
+    owner: &AccountId,
+    spender: &AccountId,
+    main_token_amount: NearToken,
+    ft_amount: U128,
+) -> (GrantId, TokenId) {
+    testing_env!(context(owner).attached_deposit(main_token_amount).build());
+    let grant_id = contract
+        .approve_for_spender(DEFAULT_CAMPAIGN_ID, spender.clone(), main_token_amount, ft_amount, None, None, None, None, None)
+        .unwrap();
+    let token_id = contract.nft_entry(grant_id).unwrap().token_id;
+
+    let token = Token {
+        token_id: token_id.clone(),
+        owner_id: env::current_account_id(),
+        metadata: None,
+        approved_account_ids: None,
+    };
+    assert!(contract.nft_mint_callback(grant_id, Ok(token)).is_some());
+
+    (grant_id, token_id)
+}
+
+pub(crate) fn stage(main_token_near: u128, ft_amount: u128, unlock: StageUnlock) -> StageSchedule {
+    StageSchedule {
+        main_token_amount: NearToken::from_near(main_token_near),
+        ft_amount: U128::from(ft_amount),
+        unlock,
+    }
+}
+
+/// approve_for_spender at block timestamp 1000, confirm the mint and claim the nft as spender.
+pub(crate) fn approve_and_claim_nft(
+    contract: &mut L2eTop,
+    owner: &AccountId,
+    spender: &AccountId,
+    main_token_amount: NearToken,
+    ft_amount: U128,
+    stages: Option<Vec<StageSchedule>>,
+    vesting: Option<VestingTerms>,
+) -> GrantId {
+    testing_env!(context(owner).attached_deposit(main_token_amount).block_timestamp(1_000).build());
+    let grant_id = contract
+        .approve_for_spender(DEFAULT_CAMPAIGN_ID, spender.clone(), main_token_amount, ft_amount, None, None, stages, vesting, None)
+        .unwrap();
+    let token_id = contract.nft_entry(grant_id).unwrap().token_id;
+    let token = Token { token_id, owner_id: env::current_account_id(), metadata: None, approved_account_ids: None };
+    contract.nft_mint_callback(grant_id, Ok(token));
+
+    testing_env!(context(spender).block_timestamp(1_000).build());
+    assert!(contract.transfer_nft_from(grant_id, None, None, None));
+    assert!(contract.nft_transfer_callback(grant_id, None, Ok(())));
+    grant_id
+}
+
+/// approve_for_spender with stages, the grant amounts are the sum of the stages.
+pub(crate) fn approve_staged_grant(
+    contract: &mut L2eTop,
+    owner: &AccountId,
+    spender: &AccountId,
+    ft_amount: U128,
+    stages: Vec<StageSchedule>,
+) -> GrantId {
+    let main_token_amount = stages
+        .iter()
+        .fold(NearToken::from_near(0), |total, x| total.saturating_add(x.main_token_amount));
+    approve_and_claim_nft(contract, owner, spender, main_token_amount, ft_amount, Some(stages), None)
+}
@@ -0,0 +1,200 @@
+use near_sdk::{env, log, near, AccountId, Promise, PromiseError};
+
+use crate::{
+    ext_verifier, CampaignId, EventLog, EventLogVariant, GrantId, GrantRecord, GrantStatus, L2eTop, L2eTopExt,
+    StageStatus, VerifierClaimLog, VerifierContractLog, CONSTRACT_NAME, CONSTRACT_VERSION,
+};
+
+#[near]
+impl L2eTop {
+    /// Point a campaign to a verifier contract implementing `ext_verifier`, called by its owner or an admin.
+    /// transfer_nft_from and claim_stage then only go on when its verify returns true. None removes the verifier.
+    pub fn set_verifier_contract(&mut self, campaign_id: CampaignId, verifier_contract: Option<AccountId>) -> bool {
+        let campaign = self.verifier_campaign_mut(campaign_id);
+        campaign.verifier_contract = verifier_contract.clone();
+
+        let set_verifier_contract_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: EventLogVariant::SetVerifierContract(vec![VerifierContractLog {
+                campaign_id,
+                owner_id: env::predecessor_account_id().to_string(),
+                verifier_id: verifier_contract.map(|x| x.to_string()),
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+        env::log_str(&set_verifier_contract_log.to_string());
+
+        true
+    }
+
+    /// Transfer the nft of a grant once the verifier accepted the proof of transfer_nft_from.
+    /// A rejected or failed verify leaves the nft claimable with another proof.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn verify_nft_claim_callback(
+        &mut self,
+        grant_id: GrantId,
+        receiver_id: Option<AccountId>,
+        #[callback_result] call_result: Result<bool, PromiseError>,
+    ) -> bool {
+        let GrantRecord { campaign_id, spender, status, .. } = self.grant_record(grant_id);
+        let nft_entry = self.nft_entry(grant_id).expect("No nft found for grant");
+        self.nft_claims_in_flight.remove(&nft_entry.token_id);
+        let expired = self.balance_entry(grant_id).is_some_and(|x| x.is_expired(env::block_timestamp()));
+
+        if !Self::is_verified(call_result) || status != GrantStatus::Minted || expired {
+            self.log_claim_not_verified(campaign_id, &spender, grant_id, None);
+            return false;
+        }
+        self.start_nft_claim(grant_id, &spender, receiver_id, &nft_entry.erc721_address, &nft_entry.token_id);
+
+        true
+    }
+
+    /// Pay a stage once the verifier accepted the proof of claim_stage.
+    /// A rejected or failed verify gives the stage its status back, it can be claimed with another proof.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn verify_stage_callback(
+        &mut self,
+        grant_id: GrantId,
+        stage: u32,
+        stage_status: StageStatus,
+        receiver_id: Option<AccountId>,
+        #[callback_result] call_result: Result<bool, PromiseError>,
+    ) -> bool {
+        let GrantRecord { campaign_id, spender, status, .. } = self.grant_record(grant_id);
+        // a grant that expired meanwhile was returned to the owner with the stage
+        if status != GrantStatus::NftClaimed {
+            self.log_claim_not_verified(campaign_id, &spender, grant_id, Some(stage));
+            return false;
+        }
+
+        if !Self::is_verified(call_result) {
+            self.grant_mut(grant_id).stages[stage as usize].status = stage_status;
+            self.log_claim_not_verified(campaign_id, &spender, grant_id, Some(stage));
+            return false;
+        }
+        if !self.start_stage_payout(grant_id, stage, receiver_id) {
+            self.grant_mut(grant_id).stages[stage as usize].status = stage_status;
+            return false;
+        }
+
+        true
+    }
+}
+
+impl L2eTop {
+    /// Verifier contract of a campaign, None if its claims need no verify.
+    pub(crate) fn verifier_contract(&self, campaign_id: CampaignId) -> Option<AccountId> {
+        self.campaigns.get(&campaign_id).and_then(|x| x.verifier_contract.clone())
+    }
+
+    /// Whether claims in a campaign go through verifier keys or a verifier contract.
+    /// Merkle drops and attendance sessions have no verifier step, they are not open in such a campaign.
+    pub(crate) fn is_claim_gated(&self, campaign_id: CampaignId) -> bool {
+        self.requires_attestation(campaign_id) || self.verifier_contract(campaign_id).is_some()
+    }
+
+    /// Ask the verifier contract whether the claim of spender in campaign_id goes on.
+    pub(crate) fn verify_claim(
+        verifier_contract: &AccountId,
+        campaign_id: CampaignId,
+        spender: &AccountId,
+        proof: Option<String>,
+    ) -> Promise {
+        ext_verifier::ext(verifier_contract.clone()).verify(campaign_id, spender.clone(), proof.unwrap_or_default())
+    }
+
+    fn is_verified(call_result: Result<bool, PromiseError>) -> bool {
+        match call_result {
+            Ok(verified) => verified,
+            Err(e) => {
+                log!("There was an error contacting verifier contract verify: {:#?}", e);
+                false
+            }
+        }
+    }
+
+    fn log_claim_not_verified(&self, campaign_id: CampaignId, spender: &AccountId, grant_id: GrantId, stage: Option<u32>) {
+        let claim_not_verified_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: EventLogVariant::ClaimNotVerified(vec![VerifierClaimLog {
+                campaign_id,
+                spender_id: spender.to_string(),
+                verifier_id: self.verifier_contract(campaign_id).map(|x| x.to_string()),
+                grant_id,
+                stage,
+                memo: Some("L2E Team".to_string()),
+            }]),
+        };
+
+        env::log_str(&claim_not_verified_log.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::{StageUnlock, DEFAULT_CAMPAIGN_ID};
+    use near_sdk::json_types::U128;
+    use near_sdk::{testing_env, NearToken};
+
+    #[test]
+    fn test_transfer_nft_from_waits_for_verifier_contract() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        assert!(contract.set_verifier_contract(DEFAULT_CAMPAIGN_ID, Some("verifier.near".parse().unwrap())));
+        let (grant_id, token_id) = approve_and_mint(&mut contract, &owner, &spender, NearToken::from_near(1), U128::from(0));
+
+        testing_env!(context(&spender).build());
+        assert!(contract.transfer_nft_from(grant_id, None, None, Some("proof".to_string())));
+        assert!(contract.nft_claims_in_flight.contains(&token_id));
+        assert!(contract.claim_all(None, None).nft_claims.is_empty());
+
+        // a rejected proof leaves the nft claimable
+        assert!(!contract.verify_nft_claim_callback(grant_id, None, Ok(false)));
+        assert!(!contract.nft_claims_in_flight.contains(&token_id));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::Minted));
+
+        assert!(contract.transfer_nft_from(grant_id, None, None, Some("proof".to_string())));
+        assert!(contract.verify_nft_claim_callback(grant_id, None, Ok(true)));
+        assert!(contract.nft_claims_in_flight.contains(&token_id));
+    }
+
+    #[test]
+    fn test_claim_stage_waits_for_verifier_contract() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let grant_id = approve_staged_grant(
+            &mut contract,
+            &owner,
+            &spender,
+            U128::from(0),
+            vec![stage(1, 0, StageUnlock::AfterPreviousStage), stage(1, 0, StageUnlock::AfterPreviousStage)],
+        );
+        testing_env!(context(&owner).build());
+        assert!(contract.set_verifier_contract(DEFAULT_CAMPAIGN_ID, Some("verifier.near".parse().unwrap())));
+        let stage_status = contract.balance_entry(grant_id).unwrap().stages[0].status;
+
+        testing_env!(context(&spender).build());
+        assert!(contract.claim_stage(grant_id, 0, None, None, Some("proof".to_string())));
+        assert_eq!(contract.balance_entry(grant_id).unwrap().stages[0].status, StageStatus::Paying);
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::NftClaimed));
+
+        assert!(!contract.verify_stage_callback(grant_id, 0, stage_status, None, Err(PromiseError::Failed)));
+        assert_eq!(contract.balance_entry(grant_id).unwrap().stages[0].status, stage_status);
+
+        assert!(contract.claim_stage(grant_id, 0, None, None, Some("proof".to_string())));
+        assert!(contract.verify_stage_callback(grant_id, 0, stage_status, None, Ok(true)));
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::PaymentInFlight));
+        assert_eq!(contract.balance_entry(grant_id).unwrap().main_token_amount, NearToken::from_near(1));
+    }
+}
@@ -0,0 +1,521 @@
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, near, require, AccountId, NearToken};
+
+use crate::{
+    BalacnesTransferLog, BalanceEntry, CampaignId, EscrowAsset, EventLog, EventLogVariant, GrantId, GrantRecord,
+    GrantStatus, L2eTop, L2eTopExt, CONSTRACT_NAME, CONSTRACT_VERSION,
+};
+
+/// Vesting terms of a grant, timestamps and durations in nanoseconds.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct VestingTerms {
+    // block timestamp the vesting starts at
+    pub start: U64,
+    // nothing vests before start + cliff
+    pub cliff: U64,
+    // everything is vested at start + duration
+    pub duration: U64,
+    // vested amounts grow every release_interval, None is continuous
+    pub release_interval: Option<U64>,
+}
+
+/// Vesting of a grant, stored in `BalanceEntry::vesting`.
+/// The grant amounts are what is not withdrawn yet, the totals are the amounts vesting.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Vesting {
+    pub terms: VestingTerms,
+    pub main_token_total: NearToken,
+    // ft pledged by approve_for_spender, only the escrowed part can be withdrawn
+    pub ft_total: U128,
+    pub main_token_withdrawn: NearToken,
+    pub ft_withdrawn: U128,
+    // withdraw_vested payout in flight, locked until withdraw_vested_callback
+    pub withdrawing: bool,
+}
+
+/// Vesting amounts of one asset.
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct VestingAmounts {
+    pub total: U128,
+    pub vested: U128,
+    pub withdrawn: U128,
+    // total - vested
+    pub locked: U128,
+    // vested, not withdrawn and escrowed, what withdraw_vested pays now
+    pub withdrawable: U128,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct VestingView {
+    pub grant_id: GrantId,
+    pub campaign_id: CampaignId,
+    pub owner: AccountId,
+    pub spender: AccountId,
+    pub terms: VestingTerms,
+    // yoctoNEAR
+    pub main_token: VestingAmounts,
+    // raw units of erc20_address
+    pub erc20_address: AccountId,
+    pub ft: VestingAmounts,
+}
+
+impl VestingTerms {
+    /// Part of `total` vested at block timestamp `now`.
+    pub fn vested(&self, total: u128, now: u64) -> u128 {
+        let start = self.start.0;
+        if now < start.saturating_add(self.cliff.0) {
+            return 0;
+        }
+        let duration = self.duration.0 as u128;
+        let mut elapsed = (now - start) as u128;
+        if elapsed >= duration {
+            return total;
+        }
+        if let Some(release_interval) = self.release_interval {
+            elapsed -= elapsed % release_interval.0 as u128;
+        }
+        // total * elapsed / duration without overflowing u128
+        total / duration * elapsed + total % duration * elapsed / duration
+    }
+}
+
+impl Vesting {
+    /// Main token and ft withdraw_vested can pay at block timestamp `now` for the grant.
+    fn withdrawable(&self, balance_entry: &BalanceEntry, now: u64) -> (NearToken, u128) {
+        let main_token_vested = self.terms.vested(self.main_token_total.as_yoctonear(), now);
+        let main_token = main_token_vested.saturating_sub(self.main_token_withdrawn.as_yoctonear());
+        let ft_vested = self.terms.vested(self.ft_total.0, now);
+        let ft = std::cmp::min(ft_vested.saturating_sub(self.ft_withdrawn.0), balance_entry.ft_escrowed.0);
+        (NearToken::from_yoctonear(main_token), ft)
+    }
+
+    /// Whether nothing is left to withdraw. The main token is only fully withdrawn after the vesting end, from then
+    /// on only the escrowed part of the pledged ft is left, a pledge the owner never funded does not keep the grant open.
+    fn is_withdrawn(&self, balance_entry: &BalanceEntry) -> bool {
+        self.main_token_withdrawn == self.main_token_total
+            && std::cmp::min(balance_entry.ft_pledged.0, balance_entry.ft_escrowed.0) == 0
+    }
+}
+
+#[near]
+impl L2eTop {
+    /// Pay the part of a vesting grant that vested and was not withdrawn yet, once its nft is claimed.
+    /// The grant is removed when everything is withdrawn, after the vesting end the ft pledged but not escrowed is
+    /// dropped. The vested part is paid to receiver_id if given.
+    pub fn withdraw_vested(&mut self, grant_id: GrantId, receiver_id: Option<AccountId>) -> bool {
+        let spender = env::predecessor_account_id();
+        require!(self.grant_record(grant_id).spender == spender, "Grant is not approved for spender");
+        require!(self.is_nft_claimed(grant_id), "No claimed nft found for spender");
+
+        let now = env::block_timestamp();
+        let balance_entry = self.grant_mut(grant_id);
+        require!(!balance_entry.is_expired(now), "Grant is expired");
+        let vesting = balance_entry.vesting.clone().expect("Grant has no vesting");
+        require!(!vesting.withdrawing, "Withdrawal is already in progress");
+        let (main_token_amount, ft_amount) = vesting.withdrawable(balance_entry, now);
+        require!(main_token_amount > NearToken::from_near(0) || ft_amount > 0, "Nothing vested to withdraw");
+
+        // the withdrawn amounts leave the grant now, withdraw_vested_callback puts back what fails to pay
+        balance_entry.main_token_amount = balance_entry.main_token_amount.saturating_sub(main_token_amount);
+        balance_entry.ft_pledged = U128::from(balance_entry.ft_pledged.0.saturating_sub(ft_amount));
+        let ft_escrowed = balance_entry.ft_escrowed.0.checked_sub(ft_amount);
+        let ft_withdrawn = vesting.ft_withdrawn.0.checked_add(ft_amount);
+        require!(
+            ft_escrowed.is_some() && ft_withdrawn.is_some(),
+            "Vested ft is not covered by the escrowed ft"
+        );
+        balance_entry.ft_escrowed = U128::from(ft_escrowed.unwrap_or_default());
+        balance_entry.vesting = Some(Vesting {
+            main_token_withdrawn: vesting.main_token_withdrawn.saturating_add(main_token_amount),
+            ft_withdrawn: U128::from(ft_withdrawn.unwrap_or_default()),
+            withdrawing: true,
+            ..vesting
+        });
+        let erc20_address = balance_entry.erc20_address.clone();
+        self.set_grant_status(grant_id, GrantStatus::PaymentInFlight);
+
+        let receiver_id = Self::claim_receiver(&spender, receiver_id);
+        let payout = Self::payout(
+            receiver_id.as_ref().unwrap_or(&spender),
+            main_token_amount,
+            &erc20_address,
+            U128::from(ft_amount),
+            receiver_id.is_some(),
+        )
+        .expect("Nothing vested to withdraw");
+        let _withdraw_vested_promise = payout.then(
+            Self::ext(env::current_account_id()).withdraw_vested_callback(
+                grant_id,
+                main_token_amount,
+                U128::from(ft_amount),
+                receiver_id,
+            ),
+        );
+
+        true
+    }
+
+    /// Resolve the payout started by `withdraw_vested`.
+    /// The promise results are in order: main token transfer, ft_transfer. A zero leg is not part of the results.
+    /// Failed legs are put back into the grant and can be withdrawn again.
+    #[private] // Public - but only callable by env::current_account_id()
+    pub fn withdraw_vested_callback(
+        &mut self,
+        grant_id: GrantId,
+        main_token_amount: NearToken,
+        ft_amount: U128,
+        receiver_id: Option<AccountId>,
+    ) -> bool {
+        let GrantRecord { campaign_id, owner, spender, .. } = self.grant_record(grant_id);
+        let (main_token_paid, ft_paid) = Self::payout_results(main_token_amount, ft_amount);
+        let unpaid_main_token = if main_token_paid { NearToken::from_near(0) } else { main_token_amount };
+        let unpaid_ft = if ft_paid { 0 } else { ft_amount.0 };
+        let paid_main_token = main_token_amount.saturating_sub(unpaid_main_token);
+        let paid_ft = ft_amount.0.saturating_sub(unpaid_ft);
+
+        let balance_entry = self.grant_mut(grant_id);
+        let erc20_address = balance_entry.erc20_address.clone();
+        balance_entry.main_token_amount = balance_entry.main_token_amount.saturating_add(unpaid_main_token);
+        balance_entry.ft_pledged = U128::from(balance_entry.ft_pledged.0.saturating_add(unpaid_ft));
+        balance_entry.ft_escrowed = U128::from(balance_entry.ft_escrowed.0.saturating_add(unpaid_ft));
+        let vesting = balance_entry.vesting.as_mut().expect("Grant has no vesting");
+        vesting.main_token_withdrawn = vesting.main_token_withdrawn.saturating_sub(unpaid_main_token);
+        vesting.ft_withdrawn = U128::from(vesting.ft_withdrawn.0.saturating_sub(unpaid_ft));
+        vesting.withdrawing = false;
+        let withdrawn = vesting.clone().is_withdrawn(balance_entry);
+
+        if paid_main_token > NearToken::from_near(0) {
+            self.escrow_pay(&owner, EscrowAsset::MainToken, paid_main_token.as_yoctonear());
+        }
+        if paid_ft > 0 {
+            self.escrow_pay(&owner, EscrowAsset::Ft(erc20_address), paid_ft);
+        }
+
+        let paid = main_token_paid && ft_paid;
+        if paid && withdrawn {
+            // the budget of an unfunded pledge goes back to the campaign
+            if let Some(balance_entry) = self.take_balance_entry(grant_id) {
+                self.release_campaign(campaign_id, NearToken::from_near(0), balance_entry.ft_pledged.0);
+            }
+            self.take_nft_entry(grant_id);
+            self.try_set_grant_status(grant_id, GrantStatus::Paid);
+        } else {
+            self.try_set_grant_status(grant_id, GrantStatus::NftClaimed);
+        }
+
+        // the paid amounts on success, the unpaid ones restored on failure
+        let (main_token_amount, ft_amount) = if paid { (paid_main_token, paid_ft) } else { (unpaid_main_token, unpaid_ft) };
+        let transfer_log = BalacnesTransferLog {
+            authorized_id: Some(owner.to_string()),
+            old_owner_id: env::current_account_id().to_string(),
+            new_owner_id: receiver_id.as_ref().unwrap_or(&spender).to_string(),
+            main_token_amount: vec![main_token_amount.as_yoctonear().to_string()],
+            ft_token_amount: vec![ft_amount.to_string()],
+            ft_contract_ids: vec![],
+            spender_id: receiver_id.as_ref().map(|_| spender.to_string()),
+            grant_id: Some(grant_id),
+            campaign_id: Some(campaign_id),
+            memo: Some("L2E Team".to_string()),
+        };
+        let withdraw_vested_log = EventLog {
+            standard: CONSTRACT_NAME.to_string(),
+            version: CONSTRACT_VERSION.to_string(),
+            event: if paid {
+                EventLogVariant::WithdrawVested(vec![transfer_log])
+            } else {
+                EventLogVariant::WithdrawVestedFailed(vec![transfer_log])
+            },
+        };
+
+        env::log_str(&withdraw_vested_log.to_string());
+
+        paid
+    }
+
+    /// Vested, withdrawn and locked amounts of a vesting grant.
+    pub fn get_vesting(&self, grant_id: GrantId) -> Option<VestingView> {
+        let now = env::block_timestamp();
+        let GrantRecord { campaign_id, owner, spender, .. } = self.grants.get(&grant_id)?.clone();
+        let balance_entry = self.balance_entry(grant_id)?;
+        let vesting = balance_entry.vesting.clone()?;
+        let (main_token_withdrawable, ft_withdrawable) = vesting.withdrawable(&balance_entry, now);
+
+        let main_token_vested = vesting.terms.vested(vesting.main_token_total.as_yoctonear(), now);
+        let ft_vested = vesting.terms.vested(vesting.ft_total.0, now);
+        Some(VestingView {
+            grant_id,
+            campaign_id,
+            owner,
+            spender,
+            main_token: VestingAmounts {
+                total: U128::from(vesting.main_token_total.as_yoctonear()),
+                vested: U128::from(main_token_vested),
+                withdrawn: U128::from(vesting.main_token_withdrawn.as_yoctonear()),
+                locked: U128::from(vesting.main_token_total.as_yoctonear().saturating_sub(main_token_vested)),
+                withdrawable: U128::from(main_token_withdrawable.as_yoctonear()),
+            },
+            erc20_address: balance_entry.erc20_address,
+            ft: VestingAmounts {
+                total: vesting.ft_total,
+                vested: U128::from(ft_vested),
+                withdrawn: vesting.ft_withdrawn,
+                locked: U128::from(vesting.ft_total.0.saturating_sub(ft_vested)),
+                withdrawable: U128::from(ft_withdrawable),
+            },
+            terms: vesting.terms,
+        })
+    }
+}
+
+impl L2eTop {
+    /// Vesting of a grant from approve_for_spender.
+    pub(crate) fn new_vesting(
+        terms: Option<VestingTerms>,
+        main_token_amount: NearToken,
+        ft_amount: U128,
+    ) -> Option<Vesting> {
+        let terms = terms?;
+        require!(terms.duration.0 > 0, "Vesting duration should be greater than 0");
+        require!(terms.cliff.0 <= terms.duration.0, "Vesting cliff should not be longer than its duration");
+        require!(
+            terms.release_interval != Some(U64(0)),
+            "Vesting release_interval should be greater than 0"
+        );
+        Some(Vesting {
+            terms,
+            main_token_total: main_token_amount,
+            ft_total: ft_amount,
+            main_token_withdrawn: NearToken::from_near(0),
+            ft_withdrawn: U128::from(0),
+            withdrawing: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::DEFAULT_CAMPAIGN_ID;
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::{testing_env, PromiseResult};
+
+    /// Vesting from 1000 with a cliff of 1000, fully vested at 5000, released every 1000.
+    fn quarterly_vesting() -> VestingTerms {
+        VestingTerms {
+            start: U64::from(1_000),
+            cliff: U64::from(1_000),
+            duration: U64::from(4_000),
+            release_interval: Some(U64::from(1_000)),
+        }
+    }
+
+    /// Run withdraw_vested_callback with the given payout results.
+    fn resolve_withdrawal(
+        contract: &mut L2eTop,
+        grant_id: GrantId,
+        main_token_amount: NearToken,
+        ft_amount: U128,
+        results: Vec<PromiseResult>,
+    ) -> bool {
+        callback_env(results);
+        contract.withdraw_vested_callback(grant_id, main_token_amount, ft_amount, None)
+    }
+
+    #[test]
+    fn test_vesting_terms_vested() {
+        let terms = quarterly_vesting();
+        assert_eq!(terms.vested(100, 1_999), 0);
+        assert_eq!(terms.vested(100, 2_000), 25);
+        assert_eq!(terms.vested(100, 2_999), 25);
+        assert_eq!(terms.vested(100, 3_000), 50);
+        assert_eq!(terms.vested(100, 5_000), 100);
+        assert_eq!(terms.vested(100, 9_000), 100);
+
+        let continuous = VestingTerms { release_interval: None, ..terms };
+        assert_eq!(continuous.vested(100, 2_500), 37);
+        assert_eq!(
+            continuous.vested(u128::MAX, 3_000),
+            u128::MAX / 4_000 * 2_000 + u128::MAX % 4_000 * 2_000 / 4_000
+        );
+    }
+
+    #[test]
+    fn test_withdraw_vested_pays_vested_part() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init(erc20.clone(), "erc721.near".parse().unwrap());
+        let grant_id = approve_and_claim_nft(
+            &mut contract,
+            &owner,
+            &spender,
+            NearToken::from_near(4),
+            U128::from(100),
+            None,
+            Some(quarterly_vesting()),
+        );
+        testing_env!(context(&erc20).build());
+        let msg = format!(r#"{{"grant_id":{}}}"#, grant_id);
+        let _ = contract.ft_on_transfer(owner.clone(), U128::from(100), msg);
+
+        // half vested at 3000
+        testing_env!(context(&spender).block_timestamp(3_000).build());
+        let vesting = contract.get_vesting(grant_id).unwrap();
+        assert_eq!(vesting.main_token.vested, U128::from(NearToken::from_near(2).as_yoctonear()));
+        assert_eq!(vesting.main_token.locked, U128::from(NearToken::from_near(2).as_yoctonear()));
+        assert_eq!(vesting.ft.withdrawable, U128::from(50));
+        assert!(contract.withdraw_vested(grant_id, None));
+        assert!(resolve_withdrawal(
+            &mut contract,
+            grant_id,
+            NearToken::from_near(2),
+            U128::from(50),
+            vec![PromiseResult::Successful(vec![]), PromiseResult::Successful(vec![])]
+        ));
+
+        testing_env!(context(&spender).block_timestamp(3_000).build());
+        let vesting = contract.get_vesting(grant_id).unwrap();
+        assert_eq!(vesting.main_token.withdrawn, U128::from(NearToken::from_near(2).as_yoctonear()));
+        assert_eq!(vesting.main_token.withdrawable, U128::from(0));
+        assert_eq!(vesting.ft.withdrawn, U128::from(50));
+
+        // the rest at 5000 removes the grant
+        testing_env!(context(&spender).block_timestamp(5_000).build());
+        assert!(contract.withdraw_vested(grant_id, None));
+        assert!(resolve_withdrawal(
+            &mut contract,
+            grant_id,
+            NearToken::from_near(2),
+            U128::from(50),
+            vec![PromiseResult::Successful(vec![]), PromiseResult::Successful(vec![])]
+        ));
+        assert!(contract.get_vesting(grant_id).is_none());
+        assert!(contract.nfts.get(&owner).unwrap().iter().all(|x| x.spender != spender));
+        let ledger = contract.get_escrow_ledger(owner);
+        assert_eq!(ledger.main_token.paid, U128::from(NearToken::from_near(4).as_yoctonear()));
+        assert_eq!(ledger.ft[0].1.paid, U128::from(100));
+    }
+
+    #[test]
+    fn test_withdraw_vested_drops_unfunded_ft_after_vesting_end() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let grant_id = approve_and_claim_nft(
+            &mut contract,
+            &owner,
+            &spender,
+            NearToken::from_near(4),
+            U128::from(100),
+            None,
+            Some(quarterly_vesting()),
+        );
+
+        // the owner never funds the ft, withdrawing the whole main token pays the grant
+        testing_env!(context(&spender).block_timestamp(5_000).build());
+        assert!(contract.withdraw_vested(grant_id, None));
+        assert!(resolve_withdrawal(
+            &mut contract,
+            grant_id,
+            NearToken::from_near(4),
+            U128::from(0),
+            vec![PromiseResult::Successful(vec![])]
+        ));
+        assert!(contract.get_vesting(grant_id).is_none());
+        assert_eq!(contract.get_grant_status(grant_id), Some(GrantStatus::Paid));
+        assert_eq!(contract.get_campaign(DEFAULT_CAMPAIGN_ID).unwrap().ft_granted, U128::from(0));
+    }
+
+    #[test]
+    fn test_withdraw_vested_callback_restores_failed_leg() {
+        let erc20: AccountId = "erc20.near".parse().unwrap();
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init(erc20.clone(), "erc721.near".parse().unwrap());
+        let grant_id = approve_and_claim_nft(
+            &mut contract,
+            &owner,
+            &spender,
+            NearToken::from_near(4),
+            U128::from(100),
+            None,
+            Some(quarterly_vesting()),
+        );
+        testing_env!(context(&erc20).build());
+        let msg = format!(r#"{{"grant_id":{}}}"#, grant_id);
+        let _ = contract.ft_on_transfer(owner.clone(), U128::from(100), msg);
+
+        testing_env!(context(&spender).block_timestamp(2_000).build());
+        assert!(contract.withdraw_vested(grant_id, None));
+        // main token transfer succeeded, ft_transfer failed
+        assert!(!resolve_withdrawal(
+            &mut contract,
+            grant_id,
+            NearToken::from_near(1),
+            U128::from(25),
+            vec![PromiseResult::Successful(vec![]), PromiseResult::Failed]
+        ));
+
+        let grant = contract.balance_entry(grant_id).unwrap();
+        assert_eq!(grant.main_token_amount, NearToken::from_near(3));
+        assert_eq!(grant.ft_escrowed, U128::from(100));
+        let vesting = grant.vesting.unwrap();
+        assert_eq!(vesting.main_token_withdrawn, NearToken::from_near(1));
+        assert_eq!(vesting.ft_withdrawn, U128::from(0));
+        assert!(!vesting.withdrawing);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nothing vested to withdraw")]
+    fn test_withdraw_vested_rejects_before_cliff() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let grant_id = approve_and_claim_nft(
+            &mut contract,
+            &owner,
+            &spender,
+            NearToken::from_near(4),
+            U128::from(0),
+            None,
+            Some(quarterly_vesting()),
+        );
+
+        testing_env!(context(&spender).block_timestamp(1_999).build());
+        contract.withdraw_vested(grant_id, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Grant vests over time, use withdraw_vested")]
+    fn test_transfer_balances_from_rejects_vesting_grant() {
+        let owner: AccountId = "bob.near".parse().unwrap();
+        let spender: AccountId = "spender.near".parse().unwrap();
+
+        testing_env!(context(&owner).build());
+        let mut contract = L2eTop::init("erc20.near".parse().unwrap(), "erc721.near".parse().unwrap());
+        let grant_id = approve_and_claim_nft(
+            &mut contract,
+            &owner,
+            &spender,
+            NearToken::from_near(4),
+            U128::from(0),
+            None,
+            Some(quarterly_vesting()),
+        );
+
+        contract.transfer_balances_from(grant_id, None);
+    }
+}
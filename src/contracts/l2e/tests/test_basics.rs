@@ -84,13 +84,11 @@ async fn test_basics_on(contract_wasm: &[u8]) -> Result<(), Box<dyn std::error::
     //     &contract,
     //     &user_account,
     //     NearToken::from_near(1),
-    //     NearToken::from_near(100),
-    //     ft_contract.id().clone(),
-    //     nft_contract.id().clone(),
+    //     U128::from(100_000_000),
     // )
     // .await?;
-    // test_transfer_nft_from(&contract, &user_account, &contract.as_account(), &nft_contract.as_account()).await?;
-    // test_transfer_balances_from(&contract, &user_account, &contract.as_account(), &ft_contract.as_account()).await?;
+    // test_transfer_nft_from(&contract, &user_account, &nft_contract.as_account()).await?;
+    // test_transfer_balances_from(&contract, &user_account, &ft_contract.as_account()).await?;
 
     Ok(())
 }
@@ -237,8 +235,8 @@ async fn test_get_allowances_for_spender(
     assert!(user_message_outcome.is_success());
 
     assert_eq!(
-        user_message_outcome.json::<Option<(u128, u128)>>()?,
-        Some((0, 0))
+        user_message_outcome.json::<Option<(U128, U128)>>()?,
+        Some((U128::from(0), U128::from(0)))
     );
 
     log!("Test Get Allowances For Spender: OK");
@@ -250,15 +248,14 @@ async fn test_approve_for_spender(
     contract: &Contract,
     spender: &Account,
     main_token_amount: NearToken,
-    ft_amount: NearToken,
-    erc20: AccountId,
-    erc721: AccountId,
+    ft_amount: U128,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let user_message_outcome = contract
         .as_account()
         .call(contract.id(), "approve_for_spender")
         .deposit(NearToken::from_near(2))
         .args_json(json!({
+            "campaign_id": 0,
             "spender": spender.id(), 
             "main_token_amount": main_token_amount, 
             "ft_amount": ft_amount, 
@@ -276,8 +273,6 @@ async fn test_approve_for_spender(
                 reference: None,
                 reference_hash: None,
             },
-            "erc20": erc20,
-            "erc721": erc721,
         }))
         .max_gas()
         .transact()
@@ -292,13 +287,12 @@ async fn test_approve_for_spender(
 async fn test_transfer_nft_from(
     contract: &Contract,
     spender: &Account,
-    owner: &Account,
     erc721: &Account,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let user_message_outcome = spender
         .call(contract.id(), "transfer_nft_from")
         .args_json(json!({
-            "owner": owner.id(), 
+            "grant_id": 1,
             "erc721": erc721.id()
         }))
         .max_gas()
@@ -315,13 +309,12 @@ async fn test_transfer_nft_from(
 async fn test_transfer_balances_from(
     contract: &Contract,
     spender: &Account,
-    owner: &Account,
     erc20: &Account,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let user_message_outcome = spender
         .call(contract.id(), "transfer_balances_from")
         .args_json(json!({
-            "owner": owner.id(), 
+            "grant_id": 1,
             "erc20": erc20.id(),
         }))
         .max_gas()
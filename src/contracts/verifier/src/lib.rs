@@ -0,0 +1,115 @@
+/*!
+Allowlist verifier for L2E.TOP campaigns, the reference implementation of `ext_verifier`.
+NOTES:
+  - A campaign of the l2e contract points to this contract with `set_verifier_contract`.
+  - `verify` accepts a spender that the owner allowed for the campaign, the proof is not used.
+  - Other verifiers implement the same `verify(campaign_id, spender, proof) -> bool` view,
+    e.g. with the proof carrying a GitHub activity or test result to check.
+*/
+use near_sdk::store::LookupSet;
+use near_sdk::{env, near, require, AccountId, BorshStorageKey, PanicOnDefault};
+
+pub type CampaignId = u64;
+
+#[derive(PanicOnDefault)]
+#[near(contract_state)]
+pub struct Contract {
+    owner_id: AccountId,
+    // (campaignid, spender) allowed to claim in the campaign
+    allowlist: LookupSet<(CampaignId, AccountId)>,
+}
+
+#[derive(BorshStorageKey)]
+#[near]
+enum StorageKey {
+    Allowlist,
+}
+
+#[near]
+impl Contract {
+    /// Initializes the contract with the given `owner_id`, the only account that changes the allowlist.
+    #[init]
+    pub fn new(owner_id: AccountId) -> Self {
+        Self {
+            owner_id,
+            allowlist: LookupSet::new(StorageKey::Allowlist),
+        }
+    }
+
+    /// Allow accounts to claim in a campaign, called by the owner.
+    pub fn add_to_allowlist(&mut self, campaign_id: CampaignId, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids {
+            self.allowlist.insert((campaign_id, account_id));
+        }
+    }
+
+    /// Remove accounts from the allowlist of a campaign, called by the owner.
+    pub fn remove_from_allowlist(&mut self, campaign_id: CampaignId, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids {
+            self.allowlist.remove(&(campaign_id, account_id));
+        }
+    }
+
+    /// Whether spender can claim in campaign_id, called by the l2e contract before a claim goes on.
+    pub fn verify(&self, campaign_id: CampaignId, spender: AccountId, proof: String) -> bool {
+        // an allowlist needs no proof
+        let _ = proof;
+        self.allowlist.contains(&(campaign_id, spender))
+    }
+
+    pub fn get_owner_id(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    fn assert_owner(&self) {
+        require!(
+            env::predecessor_account_id() == self.owner_id,
+            "Only the owner can change the allowlist"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(accounts(0))
+            .signer_account_id(predecessor_account_id.clone())
+            .predecessor_account_id(predecessor_account_id);
+        builder
+    }
+
+    #[test]
+    fn test_verify_allowlisted_spender() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1));
+        contract.add_to_allowlist(1, vec![accounts(2)]);
+
+        assert!(contract.verify(1, accounts(2), String::new()));
+        assert!(!contract.verify(2, accounts(2), String::new()));
+        assert!(!contract.verify(1, accounts(3), String::new()));
+
+        contract.remove_from_allowlist(1, vec![accounts(2)]);
+        assert!(!contract.verify(1, accounts(2), String::new()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can change the allowlist")]
+    fn test_add_to_allowlist_by_other_account() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(1));
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.add_to_allowlist(1, vec![accounts(2)]);
+    }
+}